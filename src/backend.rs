@@ -0,0 +1,579 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::process::{Command, Output};
+
+/// A remote store that sessions can be shared to and synced with
+#[derive(Debug, Clone)]
+pub enum Backend {
+    /// Google Cloud Storage bucket, accessed through gsutil
+    Gcs { bucket: String },
+    /// Directory on an SSH host, accessed through ssh/scp so the user's
+    /// agent, ~/.ssh/config and known_hosts are all respected
+    Ssh {
+        destination: String,
+        port: Option<u16>,
+        root: String,
+    },
+    /// An `mcc serve` relay, authenticated with a bearer token
+    Http { base: String, token: Option<String> },
+}
+
+/// Path of the object API on an `mcc serve` relay
+const HTTP_OBJECTS_PATH: &str = "/v1/objects";
+
+/// Path of share links on an `mcc serve` relay
+const HTTP_LINKS_PATH: &str = "/v1/links";
+
+impl Backend {
+    /// Parse a remote URL such as `gs://bucket` or `ssh://user@host:22/srv/mcc`.
+    /// A bare name is a GCS bucket, as `mcc config set-bucket` always stored.
+    pub fn from_url(url: &str) -> Result<Self> {
+        if !url.is_empty() && !url.contains("://") {
+            return Self::from_url(&format!("gs://{}", url));
+        }
+
+        if let Some(bucket) = url.strip_prefix("gs://") {
+            let bucket = bucket.trim_end_matches('/');
+            if bucket.is_empty() {
+                anyhow::bail!("Missing bucket name in {}", url);
+            }
+            return Ok(Backend::Gcs {
+                bucket: bucket.to_string(),
+            });
+        }
+
+        if let Some(rest) = url.strip_prefix("ssh://") {
+            let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+            if authority.is_empty() {
+                anyhow::bail!("Missing host in {}", url);
+            }
+
+            // user@host:port -> destination "user@host" and port
+            let (destination, port) = match authority.rsplit_once(':') {
+                Some((destination, port)) => {
+                    let port = port
+                        .parse::<u16>()
+                        .context(format!("Invalid SSH port in {}", url))?;
+                    (destination.to_string(), Some(port))
+                }
+                None => (authority.to_string(), None),
+            };
+
+            // ssh://host/~/mcc is relative to the remote home directory, which
+            // is where ssh and scp resolve relative paths anyway
+            let path = path.trim_end_matches('/');
+            let root = match path.strip_prefix('~') {
+                Some(relative) => relative.trim_start_matches('/').to_string(),
+                None => format!("/{}", path),
+            };
+
+            return Ok(Backend::Ssh {
+                destination,
+                port,
+                root,
+            });
+        }
+
+        if url.starts_with("http://") || url.starts_with("https://") {
+            let base = url.trim_end_matches('/');
+            if base.split_once("://").is_none_or(|(_, host)| host.is_empty()) {
+                anyhow::bail!("Missing host in {}", url);
+            }
+
+            // MCC_TOKEN wins so CI and one-off commands don't need the config
+            let token = std::env::var("MCC_TOKEN")
+                .ok()
+                .or_else(|| crate::cloud::CloudConfig::load().ok().and_then(|config| config.token));
+            return Ok(Backend::Http {
+                base: base.to_string(),
+                token,
+            });
+        }
+
+        anyhow::bail!(
+            "Unsupported remote: {} (expected gs://bucket, ssh://user@host/path or https://relay)",
+            url
+        )
+    }
+
+    /// Split a full object URL into its backend and object key
+    pub fn resolve(url: &str) -> Result<(Self, String)> {
+        if let Some((base, key)) = url.split_once(&format!("{}/", HTTP_OBJECTS_PATH))
+            && (url.starts_with("http://") || url.starts_with("https://"))
+        {
+            return Ok((Self::from_url(base)?, key.to_string()));
+        }
+
+        let (parent, key) = url
+            .trim_end_matches('/')
+            .rsplit_once('/')
+            .context(format!("Invalid remote path: {}", url))?;
+
+        if key.is_empty() || parent.ends_with('/') {
+            anyhow::bail!("Remote path has no object name: {}", url);
+        }
+
+        Ok((Self::from_url(parent)?, key.to_string()))
+    }
+
+    /// Full URL of an object, suitable for passing back to `mcc fetch`
+    pub fn url(&self, key: &str) -> String {
+        match self {
+            Backend::Gcs { bucket } => format!("gs://{}/{}", bucket, key),
+            Backend::Ssh {
+                destination,
+                port,
+                root,
+            } => {
+                let port = port.map(|p| format!(":{}", p)).unwrap_or_default();
+                let path = if root.starts_with('/') {
+                    format!("{}/{}", root.trim_end_matches('/'), key)
+                } else if root.is_empty() {
+                    format!("/~/{}", key)
+                } else {
+                    format!("/~/{}/{}", root, key)
+                };
+                format!("ssh://{}{}{}", destination, port, path)
+            }
+            Backend::Http { base, .. } => format!("{}{}/{}", base, HTTP_OBJECTS_PATH, key),
+        }
+    }
+
+    /// Upload a local file to `key`, returning the object URL
+    pub fn upload(&self, local_path: &Path, key: &str) -> Result<String> {
+        match self {
+            Backend::Gcs { .. } => {
+                let output = gsutil(&["cp".as_ref(), local_path.as_os_str(), self.url(key).as_ref()])?;
+                check(&output, "gsutil upload failed")?;
+            }
+            Backend::Ssh { .. } => {
+                let remote_path = self.remote_path(key);
+                if let Some((dir, _)) = remote_path.rsplit_once('/')
+                    && !dir.is_empty()
+                {
+                    let output = self.ssh(&format!("mkdir -p {}", shell_quote(dir)))?;
+                    check(&output, "ssh mkdir failed")?;
+                }
+
+                let output = self.scp(local_path.as_os_str(), &self.scp_target(&remote_path))?;
+                check(&output, "scp upload failed")?;
+            }
+            Backend::Http { .. } => {
+                let data = std::fs::read(local_path).context(format!("Failed to read {}", local_path.display()))?;
+                self.http("PUT", key)
+                    .send_bytes(&data)
+                    .map_err(|e| http_error(e, "Upload failed"))?;
+            }
+        }
+
+        Ok(self.url(key))
+    }
+
+    /// Download `key` to a local file
+    pub fn download(&self, key: &str, local_path: &Path) -> Result<()> {
+        match self {
+            Backend::Gcs { .. } => {
+                let output = gsutil(&["cp".as_ref(), self.url(key).as_ref(), local_path.as_os_str()])?;
+                check(&output, "gsutil download failed")
+            }
+            Backend::Ssh { .. } => {
+                let source = self.scp_target(&self.remote_path(key));
+                let output = self.scp(source.as_ref(), local_path.to_str().context("Invalid local path")?)?;
+                check(&output, "scp download failed")
+            }
+            Backend::Http { .. } => {
+                let response = self
+                    .http("GET", key)
+                    .call()
+                    .map_err(|e| http_error(e, "Download failed"))?;
+                let mut file = std::fs::File::create(local_path)?;
+                std::io::copy(&mut response.into_reader(), &mut file)?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Whether an object exists at `key`
+    pub fn exists(&self, key: &str) -> Result<bool> {
+        let output = match self {
+            // gsutil stat exits 1 for missing objects
+            Backend::Gcs { .. } => gsutil(&["-q".as_ref(), "stat".as_ref(), self.url(key).as_ref()])?,
+            Backend::Ssh { .. } => self.ssh(&format!("test -f {}", shell_quote(&self.remote_path(key))))?,
+            Backend::Http { .. } => {
+                return match self.http("HEAD", key).call() {
+                    Ok(_) => Ok(true),
+                    Err(ureq::Error::Status(404, _)) => Ok(false),
+                    Err(e) => Err(http_error(e, &format!("Failed to check {}", self.url(key)))),
+                };
+            }
+        };
+
+        match output.status.code() {
+            Some(0) => Ok(true),
+            Some(1) => Ok(false),
+            _ => {
+                let error = String::from_utf8_lossy(&output.stderr);
+                anyhow::bail!("Failed to check {}: {}", self.url(key), error.trim())
+            }
+        }
+    }
+
+    /// List the keys of all objects under `prefix`
+    pub fn list(&self, prefix: &str) -> Result<Vec<String>> {
+        match self {
+            Backend::Gcs { bucket } => {
+                let pattern = format!("gs://{}/{}**", bucket, prefix);
+                let output = gsutil(&["ls".as_ref(), pattern.as_ref()])?;
+
+                // gsutil exits non-zero when nothing matches the wildcard
+                if !output.status.success() {
+                    let error = String::from_utf8_lossy(&output.stderr);
+                    if error.contains("matched no objects") {
+                        return Ok(Vec::new());
+                    }
+                    anyhow::bail!("Failed to list GCS files: {}", error);
+                }
+
+                let bucket_prefix = format!("gs://{}/", bucket);
+                Ok(String::from_utf8_lossy(&output.stdout)
+                    .lines()
+                    .map(|line| line.trim())
+                    .filter(|line| !line.is_empty() && !line.ends_with(':') && !line.ends_with('/'))
+                    .filter_map(|line| line.strip_prefix(&bucket_prefix))
+                    .map(|key| key.to_string())
+                    .collect())
+            }
+            Backend::Ssh { .. } => {
+                let (dir, root_prefix) = self.ssh_listing(prefix);
+                let output = self.ssh(&format!(
+                    "if [ -d {0} ]; then find {0} -type f; fi",
+                    shell_quote(&dir)
+                ))?;
+                check(&output, "Failed to list SSH files")?;
+
+                Ok(String::from_utf8_lossy(&output.stdout)
+                    .lines()
+                    .filter(|line| !line.is_empty())
+                    .filter_map(|line| line.strip_prefix(&root_prefix))
+                    .map(|key| key.to_string())
+                    .collect())
+            }
+            Backend::Http { base, .. } => {
+                let url = format!("{}{}", base, HTTP_OBJECTS_PATH);
+                let response = self
+                    .authorize(ureq::get(&url).query("prefix", prefix))
+                    .call()
+                    .map_err(|e| http_error(e, "Failed to list relay objects"))?;
+                serde_json::from_reader(response.into_reader()).context("Invalid listing from relay")
+            }
+        }
+    }
+
+    /// Delete the object at `key`
+    pub fn delete(&self, key: &str) -> Result<()> {
+        match self {
+            Backend::Gcs { .. } => {
+                let output = gsutil(&["rm".as_ref(), self.url(key).as_ref()])?;
+                check(&output, "gsutil delete failed")
+            }
+            Backend::Ssh { .. } => {
+                let output = self.ssh(&format!("rm -f {}", shell_quote(&self.remote_path(key))))?;
+                check(&output, "ssh delete failed")
+            }
+            Backend::Http { .. } => {
+                self.http("DELETE", key)
+                    .call()
+                    .map_err(|e| http_error(e, "Delete failed"))?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Create a share link for `key` that stops working after `expires_at`
+    /// or once it has been downloaded `max_downloads` times. Only relays
+    /// can enforce this, so other remotes refuse.
+    pub fn create_link(
+        &self,
+        key: &str,
+        expires_at: Option<chrono::DateTime<chrono::Utc>>,
+        max_downloads: Option<u32>,
+    ) -> Result<String> {
+        let Backend::Http { base, .. } = self else {
+            anyhow::bail!("Share links need an mcc serve relay remote (https://...); buckets and SSH hosts can't expire them");
+        };
+
+        let url = format!("{}{}", base, HTTP_LINKS_PATH);
+        let body = serde_json::json!({
+            "key": key,
+            "expires_at": expires_at,
+            "max_downloads": max_downloads,
+        });
+        let response = self
+            .authorize(ureq::post(&url))
+            .set("Content-Type", "application/json")
+            .send_string(&body.to_string())
+            .map_err(|e| http_error(e, "Failed to create share link"))?;
+
+        let created: serde_json::Value =
+            serde_json::from_reader(response.into_reader()).context("Invalid response from relay")?;
+        let code = created
+            .get("code")
+            .and_then(|code| code.as_str())
+            .context("Relay did not return a link code")?;
+        Ok(format!("{}/{}", url, code))
+    }
+
+    /// Request for the object at `key` on an HTTP relay
+    fn http(&self, method: &str, key: &str) -> ureq::Request {
+        let url = match self {
+            Backend::Http { base, .. } => format!("{}{}/{}", base, HTTP_OBJECTS_PATH, percent_encode(key)),
+            _ => self.url(key),
+        };
+        self.authorize(ureq::request(method, &url))
+    }
+
+    fn authorize(&self, request: ureq::Request) -> ureq::Request {
+        match self {
+            Backend::Http { token: Some(token), .. } => request.set("Authorization", &format!("Bearer {}", token)),
+            _ => request,
+        }
+    }
+
+    /// Path of `key` on the SSH host
+    fn remote_path(&self, key: &str) -> String {
+        match self {
+            Backend::Ssh { root, .. } if !root.is_empty() => {
+                format!("{}/{}", root.trim_end_matches('/'), key)
+            }
+            _ => key.to_string(),
+        }
+    }
+
+    /// Directory to run `find` in to list `prefix`, and what to strip from
+    /// the paths it prints to get keys. The remote home directory itself has
+    /// to be listed as `.`, since `find ''` finds nothing.
+    fn ssh_listing(&self, prefix: &str) -> (String, String) {
+        let dir = self.remote_path(prefix.trim_end_matches('/'));
+        if dir.is_empty() {
+            return (".".to_string(), "./".to_string());
+        }
+        let root_prefix = match self {
+            Backend::Ssh { root, .. } if !root.is_empty() => format!("{}/", root.trim_end_matches('/')),
+            _ => String::new(),
+        };
+        (dir, root_prefix)
+    }
+
+    fn scp_target(&self, remote_path: &str) -> String {
+        match self {
+            Backend::Ssh { destination, .. } => format!("{}:{}", destination, remote_path),
+            Backend::Gcs { .. } | Backend::Http { .. } => remote_path.to_string(),
+        }
+    }
+
+    /// Run a shell command on the SSH host
+    fn ssh(&self, remote_command: &str) -> Result<Output> {
+        let Backend::Ssh {
+            destination, port, ..
+        } = self
+        else {
+            anyhow::bail!("Not an SSH backend");
+        };
+
+        let mut command = Command::new("ssh");
+        command.args(["-o", "BatchMode=yes"]);
+        if let Some(port) = port {
+            command.arg("-p").arg(port.to_string());
+        }
+        command
+            .arg(destination)
+            .arg("--")
+            .arg(remote_command)
+            .output()
+            .context("Failed to run ssh")
+    }
+
+    /// Copy a file to or from the SSH host
+    fn scp(&self, source: &std::ffi::OsStr, target: &str) -> Result<Output> {
+        let Backend::Ssh { port, .. } = self else {
+            anyhow::bail!("Not an SSH backend");
+        };
+
+        let mut command = Command::new("scp");
+        command.args(["-q", "-o", "BatchMode=yes"]);
+        if let Some(port) = port {
+            command.arg("-P").arg(port.to_string());
+        }
+        command
+            .arg(source)
+            .arg(target)
+            .output()
+            .context("Failed to run scp")
+    }
+}
+
+/// Run gsutil, which respects gcloud auth
+#[cfg(feature = "gcs")]
+fn gsutil(args: &[&std::ffi::OsStr]) -> Result<Output> {
+    // TODO: Make this configurable or search common paths
+    let gsutil_path = std::env::var("GSUTIL_PATH")
+        .unwrap_or_else(|_| "/Users/lyledean/Downloads/google-cloud-sdk/bin/gsutil".to_string());
+
+    Command::new(&gsutil_path)
+        .args(args)
+        .output()
+        .context(format!("Failed to run gsutil at: {}", gsutil_path))
+}
+
+#[cfg(not(feature = "gcs"))]
+fn gsutil(_args: &[&std::ffi::OsStr]) -> Result<Output> {
+    anyhow::bail!("GCS support not enabled. Rebuild with --features gcs")
+}
+
+/// Turn a failed command into an error carrying its stderr
+fn check(output: &Output, message: &str) -> Result<()> {
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("{}: {}", message, error.trim());
+    }
+    Ok(())
+}
+
+/// Whether a URL is a relay share link rather than an object URL
+pub fn is_share_link(url: &str) -> bool {
+    (url.starts_with("http://") || url.starts_with("https://")) && url.contains(&format!("{}/", HTTP_LINKS_PATH))
+}
+
+/// Download a share link. Links carry their own authorization, so no
+/// token is sent.
+pub fn download_link(url: &str, local_path: &Path) -> Result<()> {
+    let response = ureq::get(url).call().map_err(|e| match e {
+        ureq::Error::Status(404 | 410, _) => {
+            anyhow::anyhow!("This share link has expired or has already been used")
+        }
+        e => http_error(e, "Download failed"),
+    })?;
+
+    let mut file = std::fs::File::create(local_path)?;
+    std::io::copy(&mut response.into_reader(), &mut file)?;
+    Ok(())
+}
+
+/// Turn a failed HTTP request into an error carrying the relay's message
+fn http_error(error: ureq::Error, message: &str) -> anyhow::Error {
+    match error {
+        ureq::Error::Status(401, _) => {
+            anyhow::anyhow!("{}: Unauthorized (set a token with: mcc config set-token <token>)", message)
+        }
+        ureq::Error::Status(404, _) => anyhow::anyhow!("{}: No such file on the relay", message),
+        ureq::Error::Status(code, response) => {
+            let body = response.into_string().unwrap_or_default();
+            anyhow::anyhow!("{}: HTTP {} {}", message, code, body.trim())
+        }
+        ureq::Error::Transport(transport) => anyhow::anyhow!("{}: {}", message, transport),
+    }
+}
+
+/// Percent-encode an object key for use in a URL path, keeping `/`
+fn percent_encode(key: &str) -> String {
+    key.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Quote a string for a POSIX shell on the remote host
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ssh(url: &str) -> (String, Option<u16>, String) {
+        match Backend::from_url(url).unwrap() {
+            Backend::Ssh {
+                destination,
+                port,
+                root,
+            } => (destination, port, root),
+            other => panic!("{} parsed as {:?}", url, other),
+        }
+    }
+
+    fn bucket(url: &str) -> String {
+        match Backend::from_url(url).unwrap() {
+            Backend::Gcs { bucket } => bucket,
+            other => panic!("{} parsed as {:?}", url, other),
+        }
+    }
+
+    #[test]
+    fn parses_gcs_urls() {
+        assert_eq!(bucket("gs://team-sessions"), "team-sessions");
+        assert_eq!(bucket("gs://team-sessions/"), "team-sessions");
+    }
+
+    #[test]
+    fn bare_names_are_gcs_buckets() {
+        assert_eq!(bucket("team-sessions"), "team-sessions");
+    }
+
+    #[test]
+    fn parses_ssh_urls() {
+        assert_eq!(ssh("ssh://alice@box/srv/mcc"), ("alice@box".into(), None, "/srv/mcc".into()));
+        assert_eq!(ssh("ssh://alice@box:2222/srv/mcc/"), ("alice@box".into(), Some(2222), "/srv/mcc".into()));
+        assert_eq!(ssh("ssh://box/~/mcc"), ("box".into(), None, "mcc".into()));
+        assert_eq!(ssh("ssh://box/~"), ("box".into(), None, "".into()));
+        assert_eq!(ssh("ssh://box"), ("box".into(), None, "/".into()));
+    }
+
+    #[test]
+    fn parses_http_urls() {
+        for (url, expected) in [
+            ("https://relay.example.com", "https://relay.example.com"),
+            ("http://localhost:7878/", "http://localhost:7878"),
+        ] {
+            match Backend::from_url(url).unwrap() {
+                Backend::Http { base, .. } => assert_eq!(base, expected),
+                other => panic!("{} parsed as {:?}", url, other),
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_bad_urls() {
+        for url in ["", "gs://", "ssh://", "ssh:///srv/mcc", "ssh://box:port/srv", "https://", "ftp://host/path"] {
+            assert!(Backend::from_url(url).is_err(), "{} was accepted", url);
+        }
+    }
+
+    #[test]
+    fn lists_the_remote_home_directory() {
+        let listing = |url: &str, prefix: &str| Backend::from_url(url).unwrap().ssh_listing(prefix);
+        assert_eq!(listing("ssh://box/~", ""), (".".into(), "./".into()));
+        assert_eq!(listing("ssh://box/~", "shares/"), ("shares".into(), "".into()));
+        assert_eq!(listing("ssh://box/~/mcc", ""), ("mcc/".into(), "mcc/".into()));
+        assert_eq!(listing("ssh://box/~/mcc", "shares/"), ("mcc/shares".into(), "mcc/".into()));
+        assert_eq!(listing("ssh://box/srv/mcc", "shares/"), ("/srv/mcc/shares".into(), "/srv/mcc/".into()));
+    }
+
+    #[test]
+    fn resolves_object_urls() {
+        let (backend, key) = Backend::resolve("gs://team-sessions/abc.mcc").unwrap();
+        assert_eq!(backend.url(&key), "gs://team-sessions/abc.mcc");
+
+        let (backend, key) = Backend::resolve("ssh://alice@box:2222/~/mcc/abc.mcc").unwrap();
+        assert_eq!(key, "abc.mcc");
+        assert_eq!(backend.url(&key), "ssh://alice@box:2222/~/mcc/abc.mcc");
+
+        let (backend, key) = Backend::resolve("https://relay.example.com/v1/objects/shares/abc.mcc").unwrap();
+        assert_eq!(key, "shares/abc.mcc");
+        assert_eq!(backend.url(&key), "https://relay.example.com/v1/objects/shares/abc.mcc");
+
+        assert!(Backend::resolve("gs://team-sessions/").is_err());
+    }
+}
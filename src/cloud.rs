@@ -1,166 +1,331 @@
-use anyhow::Result;
-use std::path::Path;
-
-#[cfg(feature = "gcs")]
-use {anyhow::Context, std::process::Command};
-
-/// Configuration for cloud storage
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::backend::{Backend, is_share_link};
+use crate::codec::Codec;
+use crate::export::ExportedSession;
+use crate::filter::SessionFilter;
+use crate::import::{preview_session, project_session_dir, relocate_transcript};
+use crate::manifest::{ManifestEntry, REMOTE_MANIFEST_KEY, SyncManifest};
+use crate::prune::PrunePolicy;
+use crate::remote::{RemoteSession, synced_session};
+use crate::session::Session;
+use crate::transfer::{
+    DEFAULT_WORKERS, Direction, TransferJob, TransferOptions, download_decoded, run_transfers,
+    temp_file, upload_encoded, with_retries,
+};
+
+/// Configuration for cloud storage, stored in ~/.mcc/config.json
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct CloudConfig {
-    pub bucket: String,
+    /// Remote URL; older configs only know about GCS and call it gcs_bucket
+    #[serde(default, alias = "gcs_bucket")]
+    pub remote: String,
+    #[serde(skip)]
     pub enabled: bool,
+    /// Number of concurrent transfers used by sync and restore
+    #[serde(default = "default_workers")]
+    pub workers: usize,
+    /// Encrypt synced sessions with the key in ~/.mcc/sync.key
+    #[serde(default)]
+    pub encrypt: bool,
+    /// Retention policy applied to the remote after each sync
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prune: Option<PrunePolicy>,
+    /// Team namespace shares are uploaded under
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub team: Option<String>,
+    /// User namespace shares are uploaded under; defaults to $USER
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// Object key template for `mcc share`, see `share_key`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub layout: Option<String>,
+    /// Bearer token for an `mcc serve` relay remote
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+/// Where `mcc share` puts exports when no layout is configured
+const DEFAULT_LAYOUT: &str = "shares/{user}/{timestamp}-{id}.mcc";
+const DEFAULT_TEAM_LAYOUT: &str = "teams/{team}/shares/{user}/{timestamp}-{id}.mcc";
+
+fn default_workers() -> usize {
+    DEFAULT_WORKERS
 }
 
 impl CloudConfig {
     /// Load cloud config from ~/.mcc/config.json
     pub fn load() -> Result<Self> {
         let home = std::env::var("HOME")?;
-        let config_path = std::path::PathBuf::from(home).join(".mcc/config.json");
+        let config_path = PathBuf::from(home).join(".mcc/config.json");
 
         if !config_path.exists() {
             return Ok(Self {
-                bucket: String::new(),
-                enabled: false,
+                workers: DEFAULT_WORKERS,
+                ..Self::default()
             });
         }
 
-        let content = std::fs::read_to_string(&config_path)?;
-        let config: serde_json::Value = serde_json::from_str(&content)?;
-
-        Ok(Self {
-            bucket: config
-                .get("gcs_bucket")
-                .and_then(|v| v.as_str())
-                .unwrap_or("")
-                .to_string(),
-            enabled: !config
-                .get("gcs_bucket")
-                .and_then(|v| v.as_str())
-                .unwrap_or("")
-                .is_empty(),
-        })
+        let content = fs::read_to_string(&config_path)?;
+        let mut config: Self = serde_json::from_str(&content)?;
+        config.enabled = !config.remote.is_empty();
+        Ok(config)
     }
 
     /// Save cloud config to ~/.mcc/config.json
     pub fn save(&self) -> Result<()> {
         let home = std::env::var("HOME")?;
-        let config_dir = std::path::PathBuf::from(home).join(".mcc");
-        std::fs::create_dir_all(&config_dir)?;
+        let config_dir = PathBuf::from(home).join(".mcc");
+        fs::create_dir_all(&config_dir)?;
 
         let config_path = config_dir.join("config.json");
-        let config = serde_json::json!({
-            "gcs_bucket": self.bucket,
-        });
-
-        std::fs::write(&config_path, serde_json::to_string_pretty(&config)?)?;
+        fs::write(&config_path, serde_json::to_string_pretty(self)?)?;
         Ok(())
     }
-}
 
-#[cfg(feature = "gcs")]
-/// Upload a session file to GCS using gsutil
-pub async fn upload_session(file_path: &Path, bucket: &str) -> Result<String> {
-    let filename = file_path
-        .file_name()
-        .and_then(|f| f.to_str())
-        .context("Invalid filename")?;
+    /// Storage backend for the configured remote
+    pub fn backend(&self) -> Result<Backend> {
+        Backend::from_url(&self.remote)
+    }
 
-    // Strip gs:// prefix from bucket if present
-    let bucket_name = bucket.strip_prefix("gs://").unwrap_or(bucket);
-    let gcs_path = format!("gs://{}/{}", bucket_name, filename);
+    /// User namespace for shares
+    pub fn share_user(&self) -> String {
+        self.user
+            .clone()
+            .or_else(|| std::env::var("USER").ok())
+            .unwrap_or_else(|| "unknown".to_string())
+    }
 
-    // TODO: Make this configurable or search common paths
-    let gsutil_path = std::env::var("GSUTIL_PATH")
-        .unwrap_or_else(|_| "/Users/lyledean/Downloads/google-cloud-sdk/bin/gsutil".to_string());
+    /// Object key for sharing `exported`, from the layout template.
+    /// Placeholders: {team}, {user}, {timestamp}, {id}, {project}
+    pub fn share_key(&self, exported: &ExportedSession) -> Result<String> {
+        let layout = match (&self.layout, &self.team) {
+            (Some(layout), _) => layout.as_str(),
+            (None, Some(_)) => DEFAULT_TEAM_LAYOUT,
+            (None, None) => DEFAULT_LAYOUT,
+        };
+
+        if layout.contains("{team}") && self.team.is_none() {
+            anyhow::bail!("Layout {} uses {{team}} but no team is set. Run: mcc config set-team <name>", layout);
+        }
 
-    // Use gsutil which respects gcloud auth
-    let output = Command::new(&gsutil_path)
-        .arg("cp")
-        .arg(file_path)
-        .arg(&gcs_path)
-        .output()
-        .context(format!("Failed to run gsutil at: {}", gsutil_path))?;
+        let project = Path::new(&exported.session.project_path)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("unknown");
 
-    if !output.status.success() {
-        let error = String::from_utf8_lossy(&output.stderr);
-        anyhow::bail!("gsutil upload failed: {}", error);
+        Ok(layout
+            .replace("{team}", &key_segment(self.team.as_deref().unwrap_or_default()))
+            .replace("{user}", &key_segment(&self.share_user()))
+            .replace("{timestamp}", &chrono::Utc::now().format("%Y%m%d-%H%M%S").to_string())
+            .replace("{id}", &key_segment(&exported.session.id))
+            .replace("{project}", &key_segment(project)))
     }
+}
 
-    Ok(gcs_path)
+/// Make a value safe to use as one segment of an object key
+fn key_segment(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') { c } else { '-' })
+        .collect()
 }
 
-#[cfg(feature = "gcs")]
-/// Download a session file from GCS using gsutil
-pub async fn download_session(gcs_path: &str, output_path: &Path) -> Result<()> {
-    // TODO: Make this configurable or search common paths
-    let gsutil_path = std::env::var("GSUTIL_PATH")
-        .unwrap_or_else(|_| "/Users/lyledean/Downloads/google-cloud-sdk/bin/gsutil".to_string());
+/// Upload an export under the configured share layout and record it in the
+/// share index so `mcc remote ls` and `mcc fetch <id>` can find it.
+/// Returns the object key.
+pub async fn upload_session(file_path: &Path, backend: &Backend, config: &CloudConfig) -> Result<String> {
+    let exported = preview_session(file_path)?;
+    let size = fs::metadata(file_path)?.len();
+
+    // Names include a timestamp, but two uploads in the same second must
+    // still not overwrite each other
+    let base_key = config.share_key(&exported)?;
+    let mut key = base_key.clone();
+    let mut attempt = 1;
+    while with_retries(|| backend.exists(&key))? {
+        attempt += 1;
+        key = match base_key.rsplit_once('.') {
+            Some((stem, extension)) if !extension.contains('/') => format!("{}-{}.{}", stem, attempt, extension),
+            _ => format!("{}-{}", base_key, attempt),
+        };
+    }
 
-    // Use gsutil which respects gcloud auth
-    let output = Command::new(&gsutil_path)
-        .arg("cp")
-        .arg(gcs_path)
-        .arg(output_path)
-        .output()
-        .context(format!("Failed to run gsutil at: {}", gsutil_path))?;
+    with_retries(|| backend.upload(file_path, &key))?;
 
-    if !output.status.success() {
-        let error = String::from_utf8_lossy(&output.stderr);
-        anyhow::bail!("gsutil download failed: {}", error);
+    if let Err(e) = crate::remote::record_share(backend, &key, &exported, size, &config.share_user()) {
+        eprintln!("Warning: Failed to update share index: {}", e);
     }
 
+    Ok(key)
+}
+
+/// Download a session file from a full remote URL (gs://..., ssh://...,
+/// or a relay object or share link)
+pub async fn download_session(remote_path: &str, output_path: &Path) -> Result<()> {
+    // Share links work without a token, and each attempt may use up one of
+    // a limited number of downloads, so they are fetched once, directly
+    if is_share_link(remote_path) {
+        return crate::backend::download_link(remote_path, output_path);
+    }
+
+    let (backend, key) = Backend::resolve(remote_path)?;
+    backend.download(&key, output_path)
+}
+
+/// Configure the remote used by share, sync and restore
+pub fn configure_remote(remote: &str) -> Result<()> {
+    // Validate before saving so a typo doesn't break later commands
+    Backend::from_url(remote)?;
+
+    let mut config = CloudConfig::load().unwrap_or_default();
+
+    config.remote = remote.to_string();
+    config.enabled = !remote.is_empty();
+    config.save()?;
+
+    println!("✓ Remote configured: {}", remote);
+    println!("\nYou can now use:");
+    println!("  mcc share <session>   # Upload to the remote");
+    println!("  mcc fetch <url>       # Download from the remote");
+    println!("  mcc sync              # Backup all sessions to the remote");
+    println!("  mcc restore           # Restore all sessions from the remote");
+
     Ok(())
 }
 
-#[cfg(not(feature = "gcs"))]
-#[allow(dead_code)]
-pub async fn upload_session(_file_path: &Path, _bucket: &str) -> Result<String> {
-    anyhow::bail!("GCS support not enabled. Rebuild with --features gcs")
+/// Configure how many transfers sync and restore run at once
+pub fn configure_workers(workers: usize) -> Result<()> {
+    if workers == 0 {
+        anyhow::bail!("Worker count must be at least 1");
+    }
+
+    let mut config = CloudConfig::load().unwrap_or_default();
+    config.workers = workers;
+    config.save()?;
+
+    println!("✓ Transfers will use {} workers", workers);
+    Ok(())
 }
 
-#[cfg(not(feature = "gcs"))]
-#[allow(dead_code)]
-pub async fn download_session(_gcs_path: &str, _output_path: &Path) -> Result<()> {
-    anyhow::bail!("GCS support not enabled. Rebuild with --features gcs")
+/// Set or clear the token used to authenticate with an `mcc serve` relay
+pub fn configure_token(token: Option<&str>) -> Result<()> {
+    let mut config = CloudConfig::load().unwrap_or_default();
+    config.token = token.map(|token| token.to_string());
+    config.save()?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let config_path = PathBuf::from(std::env::var("HOME")?).join(".mcc/config.json");
+        fs::set_permissions(&config_path, fs::Permissions::from_mode(0o600))?;
+    }
+
+    match token {
+        Some(_) => println!("✓ Relay token saved"),
+        None => println!("✓ Relay token removed"),
+    }
+    Ok(())
 }
 
-/// Configure GCS bucket
-pub fn configure_bucket(bucket: &str) -> Result<()> {
-    let mut config = CloudConfig::load().unwrap_or(CloudConfig {
-        bucket: String::new(),
-        enabled: false,
-    });
+/// Set or clear the team namespace used by `mcc share`
+pub fn configure_team(team: Option<&str>) -> Result<()> {
+    let mut config = CloudConfig::load().unwrap_or_default();
+    config.team = team.map(|team| team.to_string());
+    config.save()?;
 
-    config.bucket = bucket.to_string();
-    config.enabled = !bucket.is_empty();
+    match team {
+        Some(team) => println!("✓ Shares will be uploaded under team {}", team),
+        None => println!("✓ Shares will no longer use a team namespace"),
+    }
+    Ok(())
+}
+
+/// Set the user namespace used by `mcc share`
+pub fn configure_user(user: &str) -> Result<()> {
+    let mut config = CloudConfig::load().unwrap_or_default();
+    config.user = Some(user.to_string());
     config.save()?;
 
-    println!("✓ GCS bucket configured: {}", bucket);
-    println!("\nYou can now use:");
-    println!("  mcc share <session>   # Upload to GCS");
-    println!("  mcc fetch <gs://...>  # Download from GCS");
-    println!("  mcc sync              # Backup all sessions to GCS");
-    println!("  mcc restore           # Restore all sessions from GCS");
+    println!("✓ Shares will be uploaded as {}", user);
+    Ok(())
+}
+
+/// Set or reset the object key template used by `mcc share`
+pub fn configure_layout(layout: Option<&str>) -> Result<()> {
+    let mut config = CloudConfig::load().unwrap_or_default();
+
+    if let Some(layout) = layout
+        && !layout.contains("{id}")
+        && !layout.contains("{timestamp}")
+    {
+        anyhow::bail!("Layout must include {{id}} or {{timestamp}} so shares don't overwrite each other");
+    }
+
+    config.layout = layout.map(|layout| layout.to_string());
+    config.save()?;
 
+    match layout {
+        Some(layout) => println!("✓ Shares will be uploaded to {}", layout),
+        None => println!("✓ Shares will use the default layout"),
+    }
     Ok(())
 }
 
-#[cfg(feature = "gcs")]
-/// Sync all sessions to GCS bucket
-pub async fn sync_sessions(bucket: &str) -> Result<Vec<String>> {
-    use std::fs;
+/// Turn client-side encryption of synced sessions on or off
+pub fn configure_encryption(encrypt: bool) -> Result<()> {
+    let mut config = CloudConfig::load().unwrap_or_default();
 
-    let home = std::env::var("HOME")?;
-    let projects_dir = std::path::PathBuf::from(&home).join(".claude/projects");
+    if encrypt && crate::codec::ensure_key()? {
+        println!("✓ Generated sync key at {}", crate::codec::key_path()?.display());
+        println!("  Copy this file to every machine that restores your sessions.");
+        println!("  Without it, encrypted sessions cannot be read.");
+    }
 
-    if !projects_dir.exists() {
-        anyhow::bail!("No sessions found in ~/.claude/projects");
+    config.encrypt = encrypt;
+    config.save()?;
+
+    if encrypt {
+        println!("✓ Synced sessions will be compressed and encrypted");
+    } else {
+        println!("✓ Synced sessions will be compressed but not encrypted");
     }
+    Ok(())
+}
+
+/// Set or clear the retention policy applied after each sync
+pub fn configure_prune(policy: Option<PrunePolicy>) -> Result<()> {
+    let mut config = CloudConfig::load().unwrap_or_default();
+    let enabled = policy.is_some();
+    config.prune = policy;
+    config.save()?;
+
+    if enabled {
+        println!("✓ Remote sessions will be pruned after each sync");
+    } else {
+        println!("✓ Automatic pruning disabled");
+    }
+    Ok(())
+}
+
+/// Outcome of a sync or restore, as lists of remote keys
+#[derive(Debug, Default)]
+pub struct TransferSummary {
+    pub transferred: Vec<String>,
+    pub skipped: Vec<String>,
+    pub failed: Vec<(String, String)>,
+}
 
-    let mut uploaded_files = Vec::new();
-    let mut total_sessions = 0;
+/// All local session files as (remote key, local path) pairs
+fn local_session_files(projects_dir: &Path) -> Result<Vec<(String, PathBuf)>> {
+    let mut files = Vec::new();
 
     // Iterate through all project directories
-    for project_entry in fs::read_dir(&projects_dir)? {
+    for project_entry in fs::read_dir(projects_dir)? {
         let project_entry = project_entry?;
         let project_path = project_entry.path();
 
@@ -171,7 +336,8 @@ pub async fn sync_sessions(bucket: &str) -> Result<Vec<String>> {
         let project_name = project_path
             .file_name()
             .and_then(|s| s.to_str())
-            .unwrap_or("unknown");
+            .unwrap_or("unknown")
+            .to_string();
 
         // Find all .jsonl files in this project directory
         for session_entry in fs::read_dir(&project_path)? {
@@ -179,121 +345,580 @@ pub async fn sync_sessions(bucket: &str) -> Result<Vec<String>> {
             let session_path = session_entry.path();
 
             if session_path.extension().and_then(|s| s.to_str()) == Some("jsonl") {
-                total_sessions += 1;
-
-                // Create GCS path: sessions/<project-name>/<session-id>.jsonl
+                // Remote key: sessions/<project-name>/<session-id>.jsonl
                 let session_filename = session_path
                     .file_name()
                     .and_then(|f| f.to_str())
                     .context("Invalid session filename")?;
 
-                let bucket_name = bucket.strip_prefix("gs://").unwrap_or(bucket);
-                let gcs_path = format!("gs://{}/sessions/{}/{}", bucket_name, project_name, session_filename);
-
-                // TODO: Make this configurable or search common paths
-                let gsutil_path = std::env::var("GSUTIL_PATH")
-                    .unwrap_or_else(|_| "/Users/lyledean/Downloads/google-cloud-sdk/bin/gsutil".to_string());
-
-                let output = Command::new(&gsutil_path)
-                    .arg("cp")
-                    .arg(&session_path)
-                    .arg(&gcs_path)
-                    .output()
-                    .context(format!("Failed to run gsutil at: {}", gsutil_path))?;
-
-                if !output.status.success() {
-                    let error = String::from_utf8_lossy(&output.stderr);
-                    eprintln!("Warning: Failed to upload {}: {}", session_filename, error);
-                } else {
-                    uploaded_files.push(gcs_path);
-                }
+                files.push((
+                    format!("sessions/{}/{}", project_name, session_filename),
+                    session_path,
+                ));
             }
         }
     }
 
-    if total_sessions == 0 {
-        anyhow::bail!("No session files found");
+    Ok(files)
+}
+
+/// Fetch the remote manifest, or None if the remote has never been synced
+/// with manifests enabled
+pub fn load_remote_manifest(backend: &Backend, codec: &Codec) -> Result<Option<SyncManifest>> {
+    if !with_retries(|| backend.exists(REMOTE_MANIFEST_KEY))? {
+        return Ok(None);
     }
 
-    Ok(uploaded_files)
+    let temp_file = temp_file("remote-manifest.json")?;
+    download_decoded(backend, codec, REMOTE_MANIFEST_KEY, &temp_file)?;
+    let manifest = SyncManifest::load(&temp_file)?;
+    fs::remove_file(&temp_file).ok();
+    Ok(Some(manifest))
 }
 
-#[cfg(feature = "gcs")]
-/// Restore all sessions from GCS bucket
-pub async fn restore_sessions(bucket: &str) -> Result<Vec<String>> {
-    use std::fs;
+/// Remote keys with their manifest entry, if the manifest has one
+pub type RemoteEntries = BTreeMap<String, Option<ManifestEntry>>;
+
+/// Every session on the remote with its manifest entry. Objects the
+/// manifest doesn't know about (uploaded before manifests existed, or by
+/// another tool) are included with no entry so callers fetch them anyway.
+pub fn load_remote_entries(
+    backend: &Backend,
+    codec: &Codec,
+) -> Result<(Option<SyncManifest>, RemoteEntries)> {
+    let manifest = load_remote_manifest(backend, codec)?;
+
+    let mut entries: RemoteEntries = manifest
+        .iter()
+        .flat_map(|manifest| manifest.entries.iter())
+        .map(|(key, entry)| (key.clone(), Some(entry.clone())))
+        .collect();
+
+    for key in with_retries(|| backend.list("sessions/"))? {
+        if key != REMOTE_MANIFEST_KEY {
+            entries.entry(key).or_insert(None);
+        }
+    }
+
+    Ok((manifest, entries))
+}
 
+pub fn save_remote_manifest(backend: &Backend, codec: &Codec, manifest: &SyncManifest) -> Result<()> {
+    let temp_file = temp_file("remote-manifest.json")?;
+    manifest.save(&temp_file)?;
+    upload_encoded(backend, codec, &temp_file, REMOTE_MANIFEST_KEY)?;
+    fs::remove_file(&temp_file).ok();
+    Ok(())
+}
+
+/// Local path for a remote key of the form sessions/<project-name>/<session-id>.jsonl
+fn local_path_for_key(projects_dir: &Path, key: &str) -> Option<PathBuf> {
+    let (project_name, session_filename) = key
+        .strip_prefix("sessions/")
+        .and_then(|rest| rest.split_once('/'))?;
+    Some(projects_dir.join(project_name).join(session_filename))
+}
+
+fn projects_dir() -> Result<PathBuf> {
     let home = std::env::var("HOME")?;
-    let projects_dir = std::path::PathBuf::from(&home).join(".claude/projects");
-    fs::create_dir_all(&projects_dir)?;
+    Ok(PathBuf::from(home).join(".claude/projects"))
+}
+
+/// Sync new and changed sessions to the remote
+pub async fn sync_sessions(backend: &Backend, options: &TransferOptions) -> Result<TransferSummary> {
+    let projects_dir = projects_dir()?;
+
+    if !projects_dir.exists() {
+        anyhow::bail!("No sessions found in ~/.claude/projects");
+    }
+
+    let files = local_session_files(&projects_dir)?;
+    if files.is_empty() {
+        anyhow::bail!("No session files found");
+    }
+
+    let local_manifest_path = SyncManifest::local_path()?;
+    let mut local_manifest = SyncManifest::load(&local_manifest_path)?;
+    let mut remote_manifest = load_remote_manifest(backend, &options.codec)?.unwrap_or_default();
+    let mut summary = TransferSummary::default();
+    let mut pending = std::collections::HashMap::new();
+    let mut jobs = Vec::new();
+
+    for (key, session_path) in files {
+        let entry = local_manifest.entry_for(&key, &session_path)?;
+
+        let unchanged = remote_manifest
+            .entries
+            .get(&key)
+            .is_some_and(|remote| remote.sha256 == entry.sha256)
+            || remote_manifest.was_pruned(&key, &entry);
+
+        if unchanged {
+            summary.skipped.push(key.clone());
+            local_manifest.entries.insert(key, entry);
+        } else {
+            jobs.push(TransferJob {
+                key: key.clone(),
+                local_path: session_path,
+                size: entry.size,
+            });
+            pending.insert(key, entry);
+        }
+    }
 
-    let bucket_name = bucket.strip_prefix("gs://").unwrap_or(bucket);
-    let gcs_sessions_path = format!("gs://{}/sessions/**", bucket_name);
+    for (job, result) in run_transfers(backend, Direction::Upload, jobs, options).await {
+        match result {
+            Ok(()) => {
+                if let Some(entry) = pending.remove(&job.key) {
+                    remote_manifest.pruned.remove(&job.key);
+                    remote_manifest.entries.insert(job.key.clone(), entry.clone());
+                    local_manifest.entries.insert(job.key.clone(), entry);
+                }
+                summary.transferred.push(job.key);
+            }
+            Err(e) => summary.failed.push((job.key, format!("{:#}", e))),
+        }
+    }
 
-    // TODO: Make this configurable or search common paths
-    let gsutil_path = std::env::var("GSUTIL_PATH")
-        .unwrap_or_else(|_| "/Users/lyledean/Downloads/google-cloud-sdk/bin/gsutil".to_string());
+    if !summary.transferred.is_empty() {
+        save_remote_manifest(backend, &options.codec, &remote_manifest)?;
+    }
+    local_manifest.save(&local_manifest_path)?;
 
-    // List all files in the sessions directory
-    let list_output = Command::new(&gsutil_path)
-        .arg("ls")
-        .arg("-r")
-        .arg(&gcs_sessions_path)
-        .output()
-        .context(format!("Failed to run gsutil at: {}", gsutil_path))?;
+    Ok(summary)
+}
 
-    if !list_output.status.success() {
-        let error = String::from_utf8_lossy(&list_output.stderr);
-        anyhow::bail!("Failed to list GCS files: {}", error);
+/// Restore new and changed sessions matching `filter` from the remote.
+/// With `into`, the selected sessions are restored under that project path
+/// instead of where they were recorded, rewriting paths like `mcc import`.
+pub async fn restore_sessions(
+    backend: &Backend,
+    options: &TransferOptions,
+    filter: &SessionFilter,
+    into: Option<&str>,
+) -> Result<TransferSummary> {
+    let projects_dir = projects_dir()?;
+    fs::create_dir_all(&projects_dir)?;
+
+    let local_manifest_path = SyncManifest::local_path()?;
+    let mut local_manifest = SyncManifest::load(&local_manifest_path)?;
+    let (_, remote_entries) = load_remote_entries(backend, &options.codec)?;
+
+    let selected: Vec<(RemoteSession, Option<ManifestEntry>)> = remote_entries
+        .into_iter()
+        .filter_map(|(key, entry)| synced_session(key, entry.as_ref()).map(|session| (session, entry)))
+        .filter(|(session, _)| {
+            filter.matches(&session.id, &session.project_path, session.git_branch.as_deref(), session.date)
+        })
+        .collect();
+
+    if let Some(into) = into {
+        return restore_into(backend, options, selected, into).await;
     }
 
-    let files = String::from_utf8_lossy(&list_output.stdout);
-    let mut restored_files = Vec::new();
+    let mut summary = TransferSummary::default();
+    let mut jobs = Vec::new();
 
-    for line in files.lines() {
-        let line = line.trim();
-        if line.is_empty() || line.ends_with(':') {
+    for (session, remote_entry) in selected {
+        let key = session.key;
+        let Some(local_session_path) = local_path_for_key(&projects_dir, &key) else {
             continue;
+        };
+
+        if let Some(remote_entry) = &remote_entry
+            && local_session_path.exists()
+        {
+            let local_entry = local_manifest.entry_for(&key, &local_session_path)?;
+            if local_entry.sha256 == remote_entry.sha256 {
+                local_manifest.entries.insert(key.clone(), local_entry);
+                summary.skipped.push(key);
+                continue;
+            }
+        }
+
+        if let Some(parent) = local_session_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        jobs.push(TransferJob {
+            key,
+            local_path: local_session_path,
+            size: remote_entry.map(|entry| entry.size).unwrap_or(0),
+        });
+    }
+
+    for (job, result) in run_transfers(backend, Direction::Download, jobs, options).await {
+        match result {
+            Ok(()) => {
+                let local_entry = ManifestEntry::from_file(&job.local_path)?;
+                local_manifest.entries.insert(job.key.clone(), local_entry);
+                summary.transferred.push(job.key);
+            }
+            Err(e) => summary.failed.push((job.key, format!("{:#}", e))),
+        }
+    }
+
+    local_manifest.save(&local_manifest_path)?;
+
+    Ok(summary)
+}
+
+/// Download sessions from a single project into temp files, then write them
+/// under `into` with their paths rewritten. The relocated copies are new
+/// sessions as far as sync is concerned, so the local manifest isn't touched.
+async fn restore_into(
+    backend: &Backend,
+    options: &TransferOptions,
+    selected: Vec<(RemoteSession, Option<ManifestEntry>)>,
+    into: &str,
+) -> Result<TransferSummary> {
+    let projects: BTreeSet<&str> = selected
+        .iter()
+        .map(|(session, _)| session.project_path.as_str())
+        .collect();
+    if projects.len() > 1 {
+        anyhow::bail!(
+            "--into needs the filters to select a single project, but they match:\n  {}",
+            projects.into_iter().collect::<Vec<_>>().join("\n  ")
+        );
+    }
+
+    let target_dir = project_session_dir(into)?;
+    fs::create_dir_all(&target_dir)?;
+
+    let mut summary = TransferSummary::default();
+    let mut sessions = std::collections::HashMap::new();
+    let mut jobs = Vec::new();
+
+    for (session, remote_entry) in selected {
+        jobs.push(TransferJob {
+            key: session.key.clone(),
+            local_path: temp_file("restore.jsonl")?,
+            size: remote_entry.map(|entry| entry.size).unwrap_or(0),
+        });
+        sessions.insert(session.key.clone(), session);
+    }
+
+    for (job, result) in run_transfers(backend, Direction::Download, jobs, options).await {
+        let session = &sessions[&job.key];
+        let result = result.and_then(|_| {
+            // Prefer the cwd recorded in the transcript over the lossy
+            // project name in the key
+            let original_path = Session::load(job.local_path.clone(), session.project_path.clone())?.project_path;
+            let target = target_dir.join(format!("{}.jsonl", session.id));
+            relocate_transcript(&job.local_path, &target, &original_path, into)
+        });
+        fs::remove_file(&job.local_path).ok();
+
+        match result {
+            Ok(()) => summary.transferred.push(job.key),
+            Err(e) => summary.failed.push((job.key, format!("{:#}", e))),
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Outcome of a two-way sync, as lists of remote keys
+#[derive(Debug, Default)]
+pub struct TwoWaySummary {
+    pub uploaded: Vec<String>,
+    pub downloaded: Vec<String>,
+    pub skipped: Vec<String>,
+    /// Diverged sessions as (key, key of the preserved remote copy)
+    pub conflicts: Vec<(String, String)>,
+    pub failed: Vec<(String, String)>,
+}
+
+/// What a two-way sync decided to do with one session
+#[derive(Debug, PartialEq)]
+enum Resolution {
+    Skip,
+    /// Deleted from the remote by `mcc prune` and unchanged locally since
+    Pruned,
+    Upload,
+    Download,
+    /// The remote copy was already fetched to this temp file and is newer
+    FastForwardLocal(PathBuf),
+    /// Neither copy extends the other; the remote copy is in this temp file
+    Conflict(PathBuf),
+}
+
+/// Sync sessions in both directions. The local manifest records the state
+/// at the last sync, so a side that still matches it is simply behind. When
+/// both sides changed, a session that is an append-only extension of the
+/// other (Claude Code only ever appends to a transcript) is fast-forwarded;
+/// anything else is a conflict and both copies are kept.
+pub async fn sync_bidirectional(backend: &Backend, options: &TransferOptions) -> Result<TwoWaySummary> {
+    let projects_dir = projects_dir()?;
+    fs::create_dir_all(&projects_dir)?;
+
+    let local_manifest_path = SyncManifest::local_path()?;
+    let mut local_manifest = SyncManifest::load(&local_manifest_path)?;
+    let (remote_manifest, mut remote_entries) = load_remote_entries(backend, &options.codec)?;
+    let mut remote_manifest = remote_manifest.unwrap_or_default();
+
+    let mut keys: BTreeMap<String, PathBuf> =
+        local_session_files(&projects_dir)?.into_iter().collect();
+    for key in remote_entries.keys() {
+        if let Some(local_path) = local_path_for_key(&projects_dir, key) {
+            keys.entry(key.clone()).or_insert(local_path);
         }
+    }
 
-        // Parse GCS path: gs://bucket/sessions/<project-name>/<session-id>.jsonl
-        if let Some(path_after_sessions) = line.strip_prefix(&format!("gs://{}/sessions/", bucket_name)) {
-            if let Some((project_name, session_filename)) = path_after_sessions.split_once('/') {
-                // Create local project directory
-                let local_project_dir = projects_dir.join(project_name);
-                fs::create_dir_all(&local_project_dir)?;
-
-                let local_session_path = local_project_dir.join(session_filename);
-
-                // Download the session file
-                let output = Command::new(&gsutil_path)
-                    .arg("cp")
-                    .arg(line)
-                    .arg(&local_session_path)
-                    .output()
-                    .context(format!("Failed to run gsutil at: {}", gsutil_path))?;
-
-                if !output.status.success() {
-                    let error = String::from_utf8_lossy(&output.stderr);
-                    eprintln!("Warning: Failed to download {}: {}", session_filename, error);
-                } else {
-                    restored_files.push(local_session_path.display().to_string());
+    let mut summary = TwoWaySummary::default();
+    let mut uploads = Vec::new();
+    let mut downloads = Vec::new();
+    let mut conflict_jobs = std::collections::HashSet::new();
+    let conflict_suffix = chrono::Utc::now().format("%Y%m%d-%H%M%S").to_string();
+
+    for (key, local_path) in keys {
+        let local_entry = if local_path.exists() {
+            Some(local_manifest.entry_for(&key, &local_path)?)
+        } else {
+            None
+        };
+        // None: not on the remote; Some(None): on the remote without a hash
+        let remote = remote_entries.remove(&key);
+        let base = local_manifest.entries.get(&key).map(|e| e.sha256.as_str());
+        let pruned = local_entry
+            .as_ref()
+            .is_some_and(|local| remote_manifest.was_pruned(&key, local));
+
+        let resolution = match resolve_by_hash(local_entry.as_ref(), remote.as_ref().map(Option::as_ref), base, pruned) {
+            Some(resolution) => resolution,
+            None => compare_with_remote(backend, &options.codec, &key, &local_path)?,
+        };
+        let remote_size = remote.flatten().map(|e| e.size).unwrap_or(0);
+
+        match resolution {
+            Resolution::Skip => {
+                if let Some(entry) = local_entry {
+                    remote_manifest.entries.insert(key.clone(), entry.clone());
+                    local_manifest.entries.insert(key.clone(), entry);
+                }
+                summary.skipped.push(key);
+            }
+            Resolution::Pruned => summary.skipped.push(key),
+            Resolution::Upload => uploads.push(TransferJob {
+                size: local_entry.map(|e| e.size).unwrap_or(0),
+                key,
+                local_path,
+            }),
+            Resolution::Download => {
+                if let Some(parent) = local_path.parent() {
+                    fs::create_dir_all(parent)?;
                 }
+                downloads.push(TransferJob {
+                    size: remote_size,
+                    key,
+                    local_path,
+                });
+            }
+            Resolution::FastForwardLocal(temp) => {
+                fs::rename(&temp, &local_path).or_else(|_| {
+                    fs::copy(&temp, &local_path)?;
+                    fs::remove_file(&temp)
+                })?;
+                let entry = ManifestEntry::from_file(&local_path)?;
+                remote_manifest.entries.insert(key.clone(), entry.clone());
+                local_manifest.entries.insert(key.clone(), entry);
+                summary.downloaded.push(key);
+            }
+            Resolution::Conflict(temp) => {
+                // Keep the local copy under the original name and the remote
+                // copy next to it; both are uploaded so every machine sees them
+                let conflict_path = conflict_copy_path(&local_path, &conflict_suffix);
+                fs::rename(&temp, &conflict_path).or_else(|_| {
+                    fs::copy(&temp, &conflict_path)?;
+                    fs::remove_file(&temp)
+                })?;
+                let conflict_key = conflict_copy_path(Path::new(&key), &conflict_suffix)
+                    .to_string_lossy()
+                    .into_owned();
+
+                uploads.push(TransferJob {
+                    size: fs::metadata(&conflict_path)?.len(),
+                    key: conflict_key.clone(),
+                    local_path: conflict_path,
+                });
+                uploads.push(TransferJob {
+                    size: local_entry.map(|e| e.size).unwrap_or(0),
+                    key: key.clone(),
+                    local_path,
+                });
+                conflict_jobs.insert(key.clone());
+                conflict_jobs.insert(conflict_key.clone());
+                summary.conflicts.push((key, conflict_key));
             }
         }
     }
 
-    Ok(restored_files)
+    for (job, result) in run_transfers(backend, Direction::Upload, uploads, options).await {
+        match result {
+            Ok(()) => {
+                let entry = ManifestEntry::from_file(&job.local_path)?;
+                remote_manifest.pruned.remove(&job.key);
+                remote_manifest.entries.insert(job.key.clone(), entry.clone());
+                local_manifest.entries.insert(job.key.clone(), entry);
+                if !conflict_jobs.contains(&job.key) {
+                    summary.uploaded.push(job.key);
+                }
+            }
+            Err(e) => summary.failed.push((job.key, format!("{:#}", e))),
+        }
+    }
+
+    for (job, result) in run_transfers(backend, Direction::Download, downloads, options).await {
+        match result {
+            Ok(()) => {
+                let entry = ManifestEntry::from_file(&job.local_path)?;
+                remote_manifest.entries.insert(job.key.clone(), entry.clone());
+                local_manifest.entries.insert(job.key.clone(), entry);
+                summary.downloaded.push(job.key);
+            }
+            Err(e) => summary.failed.push((job.key, format!("{:#}", e))),
+        }
+    }
+
+    save_remote_manifest(backend, &options.codec, &remote_manifest)?;
+    local_manifest.save(&local_manifest_path)?;
+
+    Ok(summary)
+}
+
+/// Decide what to do with a session from the hashes of the local copy, the
+/// remote copy (None: not on the remote; Some(None): on the remote without
+/// a hash) and the copy at the last sync. None when both sides changed, or
+/// there is no remote hash, and the contents have to be compared.
+fn resolve_by_hash(
+    local: Option<&ManifestEntry>,
+    remote: Option<Option<&ManifestEntry>>,
+    base: Option<&str>,
+    pruned: bool,
+) -> Option<Resolution> {
+    match (local, remote) {
+        (None, _) => Some(Resolution::Download),
+        (Some(_), None) if pruned => Some(Resolution::Pruned),
+        (Some(_), None) => Some(Resolution::Upload),
+        (Some(local), Some(Some(remote))) if local.sha256 == remote.sha256 => Some(Resolution::Skip),
+        (Some(_), Some(Some(remote))) if base == Some(remote.sha256.as_str()) => Some(Resolution::Upload),
+        (Some(local), Some(_)) if base == Some(local.sha256.as_str()) => Some(Resolution::Download),
+        (Some(_), Some(_)) => None,
+    }
+}
+
+/// How two copies of a transcript relate. Claude Code only appends, so a
+/// copy that starts with the other is simply ahead of it.
+#[derive(Debug, PartialEq)]
+enum Relation {
+    Same,
+    LocalAhead,
+    RemoteAhead,
+    Diverged,
+}
+
+fn relation(local: &[u8], remote: &[u8]) -> Relation {
+    if local == remote {
+        Relation::Same
+    } else if local.starts_with(remote) {
+        Relation::LocalAhead
+    } else if remote.starts_with(local) {
+        Relation::RemoteAhead
+    } else {
+        Relation::Diverged
+    }
+}
+
+/// Download the remote copy of a session and compare it byte-wise with the
+/// local one. The temp file is handed over when the resolution needs it.
+fn compare_with_remote(backend: &Backend, codec: &Codec, key: &str, local_path: &Path) -> Result<Resolution> {
+    let temp_file = temp_file("compare.jsonl")?;
+    download_decoded(backend, codec, key, &temp_file)?;
+
+    let resolution = match relation(&fs::read(local_path)?, &fs::read(&temp_file)?) {
+        Relation::Same => Resolution::Skip,
+        Relation::LocalAhead => Resolution::Upload,
+        Relation::RemoteAhead => return Ok(Resolution::FastForwardLocal(temp_file)),
+        Relation::Diverged => return Ok(Resolution::Conflict(temp_file)),
+    };
+
+    fs::remove_file(&temp_file).ok();
+    Ok(resolution)
 }
 
-#[cfg(not(feature = "gcs"))]
-#[allow(dead_code)]
-pub async fn sync_sessions(_bucket: &str) -> Result<Vec<String>> {
-    anyhow::bail!("GCS support not enabled. Rebuild with --features gcs")
+/// `<dir>/<id>.jsonl` -> `<dir>/<id>.conflict-<suffix>.jsonl`
+fn conflict_copy_path(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("session");
+    path.with_file_name(format!("{}.conflict-{}.jsonl", stem, suffix))
 }
 
-#[cfg(not(feature = "gcs"))]
-#[allow(dead_code)]
-pub async fn restore_sessions(_bucket: &str) -> Result<Vec<String>> {
-    anyhow::bail!("GCS support not enabled. Rebuild with --features gcs")
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(sha256: &str) -> ManifestEntry {
+        ManifestEntry {
+            size: 1,
+            mtime: 0,
+            sha256: sha256.to_string(),
+            info: None,
+        }
+    }
+
+    #[test]
+    fn one_sided_changes_resolve_by_hash() {
+        let (base, changed) = (entry("base"), entry("changed"));
+
+        // Local changed since the last sync, remote didn't
+        assert_eq!(resolve_by_hash(Some(&changed), Some(Some(&base)), Some("base"), false), Some(Resolution::Upload));
+        // Remote changed, local didn't
+        assert_eq!(resolve_by_hash(Some(&base), Some(Some(&changed)), Some("base"), false), Some(Resolution::Download));
+        assert_eq!(resolve_by_hash(Some(&changed), Some(Some(&changed)), Some("base"), false), Some(Resolution::Skip));
+    }
+
+    #[test]
+    fn sessions_on_one_side_only() {
+        let local = entry("local");
+        assert_eq!(resolve_by_hash(None, Some(Some(&local)), None, false), Some(Resolution::Download));
+        assert_eq!(resolve_by_hash(Some(&local), None, None, false), Some(Resolution::Upload));
+        assert_eq!(resolve_by_hash(Some(&local), None, Some("local"), true), Some(Resolution::Pruned));
+    }
+
+    #[test]
+    fn both_sides_changed_needs_a_comparison() {
+        let (local, remote) = (entry("local"), entry("remote"));
+        assert_eq!(resolve_by_hash(Some(&local), Some(Some(&remote)), Some("base"), false), None);
+        assert_eq!(resolve_by_hash(Some(&local), Some(Some(&remote)), None, false), None);
+        // Without a remote hash only an unchanged local copy can be decided
+        assert_eq!(resolve_by_hash(Some(&local), Some(None), Some("base"), false), None);
+        assert_eq!(resolve_by_hash(Some(&local), Some(None), Some("local"), false), Some(Resolution::Download));
+    }
+
+    #[test]
+    fn compares_transcripts() {
+        let base = b"{\"type\":\"user\"}\n".as_slice();
+        let ahead = b"{\"type\":\"user\"}\n{\"type\":\"assistant\"}\n".as_slice();
+        let other = b"{\"type\":\"user\"}\n{\"type\":\"summary\"}\n".as_slice();
+
+        assert_eq!(relation(base, base), Relation::Same);
+        assert_eq!(relation(ahead, base), Relation::LocalAhead);
+        assert_eq!(relation(base, ahead), Relation::RemoteAhead);
+        assert_eq!(relation(b"", base), Relation::RemoteAhead);
+        assert_eq!(relation(ahead, other), Relation::Diverged);
+        assert_eq!(relation(b"{\"type\":\"summary\"}\n", ahead), Relation::Diverged);
+    }
+
+    #[test]
+    fn names_conflict_copies() {
+        assert_eq!(
+            conflict_copy_path(Path::new("sessions/-home-alice-api/abc.jsonl"), "20260102-123456"),
+            Path::new("sessions/-home-alice-api/abc.conflict-20260102-123456.jsonl")
+        );
+        assert_eq!(
+            conflict_copy_path(Path::new("/home/alice/.claude/projects/p/abc.jsonl"), "x"),
+            Path::new("/home/alice/.claude/projects/p/abc.conflict-x.jsonl")
+        );
+        assert_eq!(conflict_copy_path(Path::new("abc.jsonl"), "x"), Path::new("abc.conflict-x.jsonl"));
+    }
 }
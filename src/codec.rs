@@ -0,0 +1,259 @@
+use anyhow::{Context, Result};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Prefix of encrypted objects, followed by a 12-byte nonce and the
+/// ChaCha20-Poly1305 ciphertext of the gzipped data
+const ENCRYPTED_MAGIC: &[u8; 8] = b"MCCENC01";
+const NONCE_LEN: usize = 12;
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// How objects stored by sync are encoded. Everything is gzipped; when
+/// encryption is on it is also sealed with the key in ~/.mcc/sync.key.
+/// Decoding detects the format from magic bytes, so plain uploads made by
+/// older versions still restore.
+#[derive(Clone)]
+pub struct Codec {
+    key: Option<Key>,
+    encrypt: bool,
+}
+
+impl Codec {
+    /// Load the sync key if there is one. It is needed to read encrypted
+    /// objects even when new uploads aren't being encrypted.
+    pub fn load(encrypt: bool) -> Result<Self> {
+        let key_path = key_path()?;
+        let key = if key_path.exists() {
+            Some(read_key(&key_path)?)
+        } else {
+            None
+        };
+
+        if encrypt && key.is_none() {
+            anyhow::bail!(
+                "Encryption is enabled but {} is missing. Copy it from another machine or run: mcc config set-encryption on",
+                key_path.display()
+            );
+        }
+
+        Ok(Self { key, encrypt })
+    }
+
+    pub fn encode(&self, plain: &[u8]) -> Result<Vec<u8>> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(plain).context("Failed to write compressed data")?;
+        let compressed = encoder.finish().context("Failed to finish compression")?;
+
+        if !self.encrypt {
+            return Ok(compressed);
+        }
+
+        let key = self.key.as_ref().context("Missing sync key")?;
+        let cipher = ChaCha20Poly1305::new(key);
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, compressed.as_slice())
+            .map_err(|_| anyhow::anyhow!("Failed to encrypt data"))?;
+
+        let mut sealed = Vec::with_capacity(ENCRYPTED_MAGIC.len() + NONCE_LEN + ciphertext.len());
+        sealed.extend_from_slice(ENCRYPTED_MAGIC);
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        Ok(sealed)
+    }
+
+    pub fn decode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        if let Some(sealed) = data.strip_prefix(ENCRYPTED_MAGIC.as_slice()) {
+            let key = self
+                .key
+                .as_ref()
+                .context("Object is encrypted but ~/.mcc/sync.key is missing")?;
+            if sealed.len() < NONCE_LEN {
+                anyhow::bail!("Encrypted object is truncated");
+            }
+
+            let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+            let compressed = ChaCha20Poly1305::new(key)
+                .decrypt(Nonce::from_slice(nonce), ciphertext)
+                .map_err(|_| anyhow::anyhow!("Failed to decrypt object (wrong key or corrupted data)"))?;
+            return gunzip(&compressed);
+        }
+
+        if data.starts_with(&GZIP_MAGIC) {
+            return gunzip(data);
+        }
+
+        // Uploaded by a version of mcc that stored raw .jsonl
+        Ok(data.to_vec())
+    }
+
+    /// Encode a local file into `encoded_path`
+    pub fn encode_file(&self, plain_path: &Path, encoded_path: &Path) -> Result<()> {
+        let plain = fs::read(plain_path).context(format!("Failed to read {}", plain_path.display()))?;
+        fs::write(encoded_path, self.encode(&plain)?)?;
+        Ok(())
+    }
+
+    /// Decode `encoded_path` into a local file
+    pub fn decode_file(&self, encoded_path: &Path, plain_path: &Path) -> Result<()> {
+        let encoded = fs::read(encoded_path)?;
+        fs::write(plain_path, self.decode(&encoded)?)
+            .context(format!("Failed to write {}", plain_path.display()))?;
+        Ok(())
+    }
+}
+
+/// Location of the sync encryption key, ~/.mcc/sync.key
+pub fn key_path() -> Result<PathBuf> {
+    let home = std::env::var("HOME")?;
+    Ok(PathBuf::from(home).join(".mcc/sync.key"))
+}
+
+/// Create a new sync key unless one already exists. Returns true if a key
+/// was generated.
+pub fn ensure_key() -> Result<bool> {
+    let key_path = key_path()?;
+    if key_path.exists() {
+        read_key(&key_path)?;
+        return Ok(false);
+    }
+
+    if let Some(parent) = key_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+    let hex: String = key.iter().map(|b| format!("{:02x}", b)).collect();
+
+    // Created private, so the key is never readable by others even briefly
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(&key_path).context("Failed to create sync key")?;
+    file.write_all(format!("{}\n", hex).as_bytes())?;
+
+    Ok(true)
+}
+
+fn read_key(key_path: &Path) -> Result<Key> {
+    let content = fs::read_to_string(key_path).context("Failed to read sync key")?;
+    let hex = content.trim();
+    if hex.len() != 64 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        anyhow::bail!("Invalid sync key in {} (expected 64 hex characters)", key_path.display());
+    }
+
+    let bytes: Vec<u8> = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+        .collect::<std::result::Result<_, _>>()?;
+    Ok(*Key::from_slice(&bytes))
+}
+
+fn gunzip(data: &[u8]) -> Result<Vec<u8>> {
+    let mut decoder = GzDecoder::new(data);
+    let mut plain = Vec::new();
+    decoder
+        .read_to_end(&mut plain)
+        .context("Failed to decompress object")?;
+    Ok(plain)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRANSCRIPT: &[u8] = b"{\"type\":\"user\",\"text\":\"h\xc3\xa9llo\"}\n{\"type\":\"assistant\"}\n";
+
+    fn codec(encrypt: bool) -> Codec {
+        Codec {
+            key: Some(ChaCha20Poly1305::generate_key(&mut OsRng)),
+            encrypt,
+        }
+    }
+
+    #[test]
+    fn round_trips_gzip() {
+        let codec = codec(false);
+        let encoded = codec.encode(TRANSCRIPT).unwrap();
+        assert!(encoded.starts_with(&GZIP_MAGIC));
+        assert_eq!(codec.decode(&encoded).unwrap(), TRANSCRIPT);
+    }
+
+    #[test]
+    fn round_trips_encrypted() {
+        let codec = codec(true);
+        let encoded = codec.encode(TRANSCRIPT).unwrap();
+        assert!(encoded.starts_with(ENCRYPTED_MAGIC));
+        assert!(!encoded.windows(4).any(|window| window == b"user"));
+        assert_eq!(codec.decode(&encoded).unwrap(), TRANSCRIPT);
+
+        // Each object gets its own nonce
+        assert_ne!(codec.encode(TRANSCRIPT).unwrap(), encoded);
+    }
+
+    #[test]
+    fn wrong_key_fails() {
+        let encoded = codec(true).encode(TRANSCRIPT).unwrap();
+        let error = codec(true).decode(&encoded).unwrap_err().to_string();
+        assert!(error.contains("wrong key"), "{}", error);
+
+        let no_key = Codec { key: None, encrypt: false };
+        assert!(no_key.decode(&encoded).unwrap_err().to_string().contains("sync.key is missing"));
+    }
+
+    #[test]
+    fn damaged_objects_fail() {
+        let codec = codec(true);
+        let mut encoded = codec.encode(TRANSCRIPT).unwrap();
+        let last = encoded.len() - 1;
+        encoded[last] ^= 1;
+        assert!(codec.decode(&encoded).is_err());
+        assert!(codec.decode(&encoded[..ENCRYPTED_MAGIC.len() + 4]).is_err());
+    }
+
+    #[test]
+    fn decrypting_objects_still_works_with_encryption_off() {
+        let encrypted = codec(true);
+        let encoded = encrypted.encode(TRANSCRIPT).unwrap();
+        let reading = Codec {
+            key: encrypted.key,
+            encrypt: false,
+        };
+        assert_eq!(reading.decode(&encoded).unwrap(), TRANSCRIPT);
+    }
+
+    #[test]
+    fn legacy_uncompressed_objects_decode_as_is() {
+        assert_eq!(codec(false).decode(TRANSCRIPT).unwrap(), TRANSCRIPT);
+        assert_eq!(codec(true).decode(TRANSCRIPT).unwrap(), TRANSCRIPT);
+    }
+
+    #[test]
+    fn encrypting_needs_a_key() {
+        let codec = Codec { key: None, encrypt: true };
+        assert!(codec.encode(TRANSCRIPT).is_err());
+    }
+
+    #[test]
+    fn reads_keys() {
+        let path = crate::transfer::temp_file("sync.key").unwrap();
+        fs::write(&path, format!("{}\n", "ab".repeat(32))).unwrap();
+        assert_eq!(read_key(&path).unwrap().as_slice(), [0xab; 32]);
+
+        fs::write(&path, "ab".repeat(31)).unwrap();
+        assert!(read_key(&path).is_err());
+        fs::write(&path, "zz".repeat(32)).unwrap();
+        assert!(read_key(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
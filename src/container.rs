@@ -0,0 +1,414 @@
+use anyhow::{Context, Result};
+use flate2::read::GzDecoder;
+use serde_json::Value;
+use std::fs;
+use std::io::{ErrorKind, Read};
+use std::path::{Path, PathBuf};
+
+use crate::manifest::sha256_hex;
+
+/// First bytes of a zstd export. The header is the magic, a container
+/// version and the SHA-256 of the dictionary it was compressed with (zeros
+/// for none), followed by one zstd frame holding a tar archive of:
+///   export.json        the export without its messages, as compact JSON
+///   sessions/0.jsonl   the primary session's messages, one per line
+///   sessions/1.jsonl   the first bundled session's, and so on
+const MAGIC: &[u8; 4] = b"MCCZ";
+const CONTAINER_VERSION: u8 = 1;
+const HEADER_LEN: usize = MAGIC.len() + 1 + 32;
+
+const GZIP_MAGIC: &[u8; 2] = &[0x1f, 0x8b];
+
+/// Well past gzip's ratio while still quick on sessions of tens of MB
+const ZSTD_LEVEL: i32 = 9;
+
+/// Largest dictionary `mcc dict train` builds unless told otherwise
+pub const DEFAULT_DICTIONARY_SIZE: usize = 112 * 1024;
+
+/// A zstd dictionary trained on transcripts, shared between exporter and
+/// importer out of band
+pub struct Dictionary {
+    pub id: String,
+    pub data: Vec<u8>,
+}
+
+impl Dictionary {
+    fn from_data(data: Vec<u8>) -> Self {
+        Self {
+            id: sha256_hex(&data),
+            data,
+        }
+    }
+
+    /// First 12 characters of the ID, enough to name it
+    pub fn short_id(&self) -> &str {
+        &self.id[..12]
+    }
+
+    /// Train a dictionary on the lines of Claude Code's transcripts
+    pub fn train(transcripts: &[PathBuf], max_size: usize) -> Result<Self> {
+        let mut samples = Vec::new();
+        for path in transcripts {
+            let Ok(content) = fs::read_to_string(path) else {
+                continue;
+            };
+            samples.extend(content.lines().filter(|line| !line.is_empty()).map(|line| line.as_bytes().to_vec()));
+        }
+        if samples.len() < 100 {
+            anyhow::bail!("Not enough transcript data to train on ({} messages)", samples.len());
+        }
+
+        let data = zstd::dict::from_samples(&samples, max_size).context("Failed to train dictionary")?;
+        Ok(Self::from_data(data))
+    }
+
+    /// Install a dictionary file in ~/.mcc/dicts so exports made with it
+    /// can be read
+    pub fn install_file(path: &Path) -> Result<Self> {
+        let data = fs::read(path).context(format!("Failed to read {}", path.display()))?;
+        let dictionary = Self::from_data(data);
+        dictionary.install()?;
+        Ok(dictionary)
+    }
+
+    pub fn install(&self) -> Result<PathBuf> {
+        let path = dictionaries_dir()?.join(format!("{}.dict", self.id));
+        fs::create_dir_all(dictionaries_dir()?)?;
+        fs::write(&path, &self.data)?;
+        Ok(path)
+    }
+
+    /// A dictionary file, or an installed dictionary by ID prefix
+    pub fn find(reference: &str) -> Result<Self> {
+        if Path::new(reference).is_file() {
+            return Self::install_file(Path::new(reference));
+        }
+
+        let mut matches = Vec::new();
+        if let Ok(entries) = fs::read_dir(dictionaries_dir()?) {
+            for entry in entries {
+                let path = entry?.path();
+                if path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with(reference) && name.ends_with(".dict"))
+                {
+                    matches.push(path);
+                }
+            }
+        }
+        match matches.as_slice() {
+            [path] => Ok(Self::from_data(fs::read(path)?)),
+            [] => anyhow::bail!("No dictionary file or installed dictionary matches {}", reference),
+            _ => anyhow::bail!("{} matches {} dictionaries; use a longer prefix", reference, matches.len()),
+        }
+    }
+
+    /// Every dictionary in ~/.mcc/dicts
+    pub fn installed() -> Result<Vec<Self>> {
+        let mut dictionaries = Vec::new();
+        let Ok(entries) = fs::read_dir(dictionaries_dir()?) else {
+            return Ok(dictionaries);
+        };
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "dict") {
+                dictionaries.push(Self::from_data(fs::read(&path)?));
+            }
+        }
+        Ok(dictionaries)
+    }
+}
+
+/// ~/.mcc/dicts, named by the SHA-256 of their content
+fn dictionaries_dir() -> Result<PathBuf> {
+    let home = std::env::var("HOME")?;
+    Ok(PathBuf::from(home).join(".mcc/dicts"))
+}
+
+/// Write an export in the zstd container
+pub fn write(exported: &Value, path: &Path, dictionary: Option<&Dictionary>) -> Result<()> {
+    let mut header = exported.clone();
+    let mut sessions = Vec::new();
+    if let Some(session) = header.get_mut("session") {
+        sessions.push(take_messages(session));
+    }
+    if let Some(bundled) = header.get_mut("bundled").and_then(|bundled| bundled.as_array_mut()) {
+        sessions.extend(bundled.iter_mut().map(take_messages));
+    }
+
+    let mut archive = tar::Builder::new(Vec::new());
+    append(&mut archive, "export.json", header.to_string().as_bytes())?;
+    for (index, messages) in sessions.iter().enumerate() {
+        let jsonl: String = messages.iter().map(|message| format!("{}\n", message)).collect();
+        append(&mut archive, &format!("sessions/{}.jsonl", index), jsonl.as_bytes())?;
+    }
+    let archive = archive.into_inner()?;
+
+    let compressed = match dictionary {
+        Some(dictionary) => zstd::bulk::Compressor::with_dictionary(ZSTD_LEVEL, &dictionary.data)?.compress(&archive)?,
+        None => zstd::bulk::compress(&archive, ZSTD_LEVEL)?,
+    };
+
+    let mut output = Vec::with_capacity(HEADER_LEN + compressed.len());
+    output.extend_from_slice(MAGIC);
+    output.push(CONTAINER_VERSION);
+    match dictionary {
+        Some(dictionary) => output.extend_from_slice(&hex_to_bytes(&dictionary.id)),
+        None => output.extend_from_slice(&[0; 32]),
+    }
+    output.extend_from_slice(&compressed);
+    fs::write(path, output).context(format!("Failed to create file: {:?}", path))
+}
+
+fn take_messages(session: &mut Value) -> Vec<Value> {
+    match session.as_object_mut().and_then(|session| session.remove("messages")) {
+        Some(Value::Array(messages)) => messages,
+        _ => Vec::new(),
+    }
+}
+
+fn append(archive: &mut tar::Builder<Vec<u8>>, name: &str, content: &[u8]) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(content.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(chrono::Utc::now().timestamp() as u64);
+    archive.append_data(&mut header, name, content)?;
+    Ok(())
+}
+
+fn hex_to_bytes(hex: &str) -> Vec<u8> {
+    (0..hex.len())
+        .step_by(2)
+        .filter_map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Read an export in either format, telling them apart by their first
+/// bytes, and say whether a failure looks like a cut-short download, a
+/// damaged file or something that isn't an export
+pub fn read(path: &Path) -> Result<Value> {
+    let bytes = fs::read(path).map_err(|e| anyhow::anyhow!("Failed to open .mcc file: {}", e))?;
+    if bytes.is_empty() {
+        anyhow::bail!("The file is empty; it was probably not fully downloaded");
+    }
+
+    if bytes.starts_with(GZIP_MAGIC) {
+        let mut json = String::new();
+        GzDecoder::new(bytes.as_slice())
+            .read_to_string(&mut json)
+            .map_err(|e| decompress_error(e, bytes.len()))?;
+        return parse(&json);
+    }
+    if bytes.starts_with(MAGIC) {
+        return read_container(&bytes);
+    }
+    anyhow::bail!("This isn't an mcc export (it isn't gzip or zstd compressed)")
+}
+
+fn read_container(bytes: &[u8]) -> Result<Value> {
+    if bytes.len() < HEADER_LEN {
+        anyhow::bail!("The file is truncated: it ends after {} bytes, inside its header", bytes.len());
+    }
+    if bytes[MAGIC.len()] != CONTAINER_VERSION {
+        anyhow::bail!(
+            "This export uses container version {}, which this version of mcc can't read. Upgrade mcc to open it.",
+            bytes[MAGIC.len()]
+        );
+    }
+
+    let dictionary_id: String = bytes[MAGIC.len() + 1..HEADER_LEN]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    let dictionary = if dictionary_id.bytes().all(|byte| byte == b'0') {
+        None
+    } else {
+        let dictionary = Dictionary::installed()?
+            .into_iter()
+            .find(|dictionary| dictionary.id == dictionary_id)
+            .with_context(|| {
+                format!(
+                    "This export was compressed with dictionary {}, which isn't installed. Get the .dict file from whoever exported it and run: mcc dict add <file>",
+                    &dictionary_id[..12]
+                )
+            })?;
+        Some(dictionary)
+    };
+
+    let compressed = &bytes[HEADER_LEN..];
+    let mut archive = Vec::new();
+    let result = match &dictionary {
+        Some(dictionary) => zstd::stream::read::Decoder::with_dictionary(compressed, &dictionary.data)
+            .and_then(|mut decoder| decoder.read_to_end(&mut archive)),
+        None => zstd::stream::read::Decoder::new(compressed).and_then(|mut decoder| decoder.read_to_end(&mut archive)),
+    };
+    result.map_err(|e| decompress_error(e, bytes.len()))?;
+
+    let mut header: Option<Value> = None;
+    let mut sessions: Vec<(usize, Vec<Value>)> = Vec::new();
+    for entry in tar::Archive::new(archive.as_slice()).entries()? {
+        let mut entry = entry.context("The export is corrupted: its archive is damaged")?;
+        let name = entry.path()?.to_string_lossy().to_string();
+        let mut content = String::new();
+        entry.read_to_string(&mut content)?;
+
+        if name == "export.json" {
+            header = Some(parse(&content)?);
+        } else if let Some(index) = name
+            .strip_prefix("sessions/")
+            .and_then(|name| name.strip_suffix(".jsonl"))
+            .and_then(|index| index.parse().ok())
+        {
+            let messages = content
+                .lines()
+                .map(parse)
+                .collect::<Result<Vec<Value>>>()?;
+            sessions.push((index, messages));
+        }
+    }
+
+    let mut exported = header.context("The export is incomplete: it has no export.json")?;
+    for (index, messages) in sessions {
+        let session = match index {
+            0 => exported.get_mut("session"),
+            _ => exported.get_mut("bundled").and_then(|bundled| bundled.get_mut(index - 1)),
+        };
+        match session.and_then(|session| session.as_object_mut()) {
+            Some(session) => {
+                session.insert("messages".to_string(), Value::Array(messages));
+            }
+            None => anyhow::bail!("The export is corrupted: it has messages for session {} but no such session", index),
+        }
+    }
+    Ok(exported)
+}
+
+fn decompress_error(e: std::io::Error, size: usize) -> anyhow::Error {
+    if e.kind() == ErrorKind::UnexpectedEof {
+        anyhow::anyhow!(
+            "The file is truncated: it ends after {} bytes, part-way through the compressed data. It was probably cut off while downloading; get a fresh copy.",
+            size
+        )
+    } else {
+        anyhow::anyhow!("The file is corrupted: its compressed data is damaged ({}). Get a fresh copy.", e)
+    }
+}
+
+fn parse(json: &str) -> Result<Value> {
+    serde_json::from_str(json).map_err(|e| {
+        if e.is_eof() {
+            anyhow::anyhow!("The export is incomplete: its JSON ends early ({})", e)
+        } else {
+            anyhow::anyhow!("The export is corrupted: it isn't valid JSON ({})", e)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn export() -> Value {
+        json!({
+            "version": "1.2.0",
+            "exported_at": "2025-01-01T00:00:00Z",
+            "exported_by": "alice@laptop",
+            "session": {
+                "id": "primary",
+                "project_path": "/home/alice/api",
+                "summary": "Add pagination",
+                "git_branch": null,
+                "messages": [{ "type": "user", "text": "héllo\nwörld" }, { "type": "assistant" }]
+            },
+            "bundled": [{
+                "id": "bundled",
+                "project_path": "/home/alice/web",
+                "summary": "Use the new API",
+                "git_branch": "main",
+                "messages": [{ "type": "user" }]
+            }]
+        })
+    }
+
+    /// Write `bytes` to a fresh temp file
+    fn file_with(bytes: &[u8]) -> PathBuf {
+        let path = crate::transfer::temp_file("container-test.mccz").unwrap();
+        fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    fn written(dictionary: Option<&Dictionary>) -> Vec<u8> {
+        let path = crate::transfer::temp_file("container-test.mccz").unwrap();
+        write(&export(), &path, dictionary).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        bytes
+    }
+
+    fn read_error(bytes: &[u8]) -> String {
+        let path = file_with(bytes);
+        let error = read(&path).unwrap_err().to_string();
+        fs::remove_file(&path).unwrap();
+        error
+    }
+
+    #[test]
+    fn round_trips_through_zstd() {
+        let bytes = written(None);
+        assert!(bytes.starts_with(MAGIC));
+        assert!(bytes[MAGIC.len() + 1..HEADER_LEN].iter().all(|byte| *byte == 0));
+
+        let path = file_with(&bytes);
+        assert_eq!(read(&path).unwrap(), export());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn reads_gzip_exports() {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        std::io::Write::write_all(&mut encoder, export().to_string().as_bytes()).unwrap();
+        let path = file_with(&encoder.finish().unwrap());
+        assert_eq!(read(&path).unwrap(), export());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn truncated_data_is_reported() {
+        let bytes = written(None);
+        let error = read_error(&bytes[..bytes.len() - 10]);
+        assert!(error.contains("truncated"), "{}", error);
+    }
+
+    #[test]
+    fn truncated_header_is_reported() {
+        let error = read_error(&written(None)[..HEADER_LEN - 1]);
+        assert!(error.contains("inside its header"), "{}", error);
+    }
+
+    #[test]
+    fn missing_dictionary_is_reported() {
+        let dictionary = Dictionary::from_data(b"a dictionary nobody has installed".repeat(64));
+        let bytes = written(Some(&dictionary));
+        assert_eq!(&bytes[MAGIC.len() + 1..HEADER_LEN], hex_to_bytes(&dictionary.id).as_slice());
+
+        let error = read_error(&bytes);
+        assert!(error.contains(dictionary.short_id()), "{}", error);
+        assert!(error.contains("isn't installed"), "{}", error);
+    }
+
+    #[test]
+    fn newer_container_version_is_refused() {
+        let mut bytes = written(None);
+        bytes[MAGIC.len()] = CONTAINER_VERSION + 1;
+        let error = read_error(&bytes);
+        assert!(error.contains("Upgrade mcc"), "{}", error);
+    }
+
+    #[test]
+    fn other_files_are_refused() {
+        assert!(read_error(b"").contains("empty"));
+        assert!(read_error(b"{\"version\":\"1.2.0\"}").contains("isn't an mcc export"));
+    }
+}
@@ -0,0 +1,218 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::export::{Attachment, AttachmentKind};
+use crate::import::project_session_dir;
+use crate::transfer::temp_dir;
+
+/// Instruction and settings files Claude Code reads from a project
+const PROJECT_FILES: &[&str] = &[
+    "CLAUDE.md",
+    "CLAUDE.local.md",
+    ".claude/CLAUDE.md",
+    ".claude/settings.json",
+    ".claude/settings.local.json",
+];
+
+/// Suffix given to installed copies that would overwrite the importer's own
+const SIDE_BY_SIDE_SUFFIX: &str = "mcc-import";
+
+/// How an exported context file compares with the importer's copy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContextStatus {
+    /// The importer doesn't have this file
+    New,
+    Same,
+    Differs { added: usize, removed: usize },
+}
+
+/// An exported context file and where the importer's copy would be
+pub struct ContextFile<'a> {
+    pub attachment: &'a Attachment,
+    pub local_path: PathBuf,
+    pub status: ContextStatus,
+}
+
+/// The project's CLAUDE.md files and settings, the user's own CLAUDE.md,
+/// and Claude Code's memory for the project kept in `session_dir`/memory
+pub fn capture(project_dir: &Path, session_dir: &Path) -> Result<Vec<Attachment>> {
+    let mut attachments = Vec::new();
+
+    for relative in PROJECT_FILES {
+        if let Ok(content) = fs::read_to_string(project_dir.join(relative)) {
+            attachments.push(Attachment {
+                kind: AttachmentKind::ProjectFile,
+                path: relative.to_string(),
+                content: if relative.ends_with(".json") {
+                    redact_settings(&content)
+                } else {
+                    content
+                },
+            });
+        }
+    }
+
+    let home = std::env::var("HOME")?;
+    if let Ok(content) = fs::read_to_string(PathBuf::from(&home).join(".claude/CLAUDE.md")) {
+        attachments.push(Attachment {
+            kind: AttachmentKind::UserFile,
+            path: "CLAUDE.md".to_string(),
+            content,
+        });
+    }
+
+    let memory_dir = session_dir.join("memory");
+    if memory_dir.is_dir() {
+        for entry in fs::read_dir(&memory_dir)? {
+            let path = entry?.path();
+            if let (true, Some(name), Ok(content)) = (
+                path.is_file(),
+                path.file_name().and_then(|name| name.to_str()),
+                fs::read_to_string(&path),
+            ) {
+                attachments.push(Attachment {
+                    kind: AttachmentKind::Memory,
+                    path: name.to_string(),
+                    content,
+                });
+            }
+        }
+    }
+
+    Ok(attachments)
+}
+
+/// Settings can carry credentials in `env` and `apiKeyHelper`; keep the
+/// keys so the shape is visible but drop the values
+fn redact_settings(content: &str) -> String {
+    let Ok(mut settings) = serde_json::from_str::<serde_json::Value>(content) else {
+        return content.to_string();
+    };
+
+    if let Some(env) = settings.get_mut("env").and_then(|env| env.as_object_mut()) {
+        for value in env.values_mut() {
+            *value = serde_json::json!("<redacted>");
+        }
+    }
+    if let Some(helper) = settings.get_mut("apiKeyHelper") {
+        *helper = serde_json::json!("<redacted>");
+    }
+    serde_json::to_string_pretty(&settings).unwrap_or_else(|_| content.to_string())
+}
+
+/// Where the importer's copy of a context attachment lives when the session
+/// is imported into `project_path`
+fn local_path(attachment: &Attachment, project_path: &str) -> Result<Option<PathBuf>> {
+    let relative = Path::new(&attachment.path);
+    if !relative
+        .components()
+        .all(|component| matches!(component, std::path::Component::Normal(_)))
+    {
+        anyhow::bail!("Refusing context file with unsafe path: {}", attachment.path);
+    }
+    // Only the files capture exports; anything else could be a script
+    // planted in the importer's project
+    let expected = match attachment.kind {
+        AttachmentKind::ProjectFile => PROJECT_FILES.contains(&attachment.path.as_str()),
+        AttachmentKind::UserFile => attachment.path == "CLAUDE.md",
+        _ => true,
+    };
+    if !expected {
+        anyhow::bail!("Refusing unexpected context file: {}", attachment.path);
+    }
+
+    Ok(match attachment.kind {
+        AttachmentKind::ProjectFile => Some(Path::new(project_path).join(relative)),
+        AttachmentKind::Memory => Some(project_session_dir(project_path)?.join("memory").join(relative)),
+        AttachmentKind::UserFile => {
+            let home = std::env::var("HOME")?;
+            Some(PathBuf::from(home).join(".claude").join(relative))
+        }
+        AttachmentKind::SessionFile | AttachmentKind::Todo => None,
+    })
+}
+
+/// Compare the context files in `attachments` with the importer's own
+pub fn compare<'a>(attachments: &'a [Attachment], project_path: &str) -> Result<Vec<ContextFile<'a>>> {
+    let mut files = Vec::new();
+    for attachment in attachments {
+        let Some(local_path) = local_path(attachment, project_path)? else {
+            continue;
+        };
+
+        let status = match fs::read_to_string(&local_path) {
+            Err(_) => ContextStatus::New,
+            Ok(local) if local == attachment.content => ContextStatus::Same,
+            Ok(local) => {
+                let ours: Vec<&str> = local.lines().collect();
+                let theirs: Vec<&str> = attachment.content.lines().collect();
+                ContextStatus::Differs {
+                    added: theirs.iter().filter(|line| !ours.contains(line)).count(),
+                    removed: ours.iter().filter(|line| !theirs.contains(line)).count(),
+                }
+            }
+        };
+        files.push(ContextFile {
+            attachment,
+            local_path,
+            status,
+        });
+    }
+    Ok(files)
+}
+
+/// Unified diff from the importer's copy to the exported one
+pub fn diff(file: &ContextFile) -> Result<String> {
+    let exported = temp_dir()?.join(format!("context-{}", file.attachment.path.replace('/', "-")));
+    fs::write(&exported, &file.attachment.content)?;
+
+    let output = Command::new("git")
+        .args(["diff", "--no-index", "--no-color", "--"])
+        .arg(&file.local_path)
+        .arg(&exported)
+        .output()
+        .context("Failed to run git diff");
+    fs::remove_file(&exported).ok();
+
+    Ok(String::from_utf8_lossy(&output?.stdout).to_string())
+}
+
+/// Write exported context files next to the importer's, e.g. as
+/// CLAUDE.mcc-import.md, so nothing of theirs is replaced. Project
+/// instructions and memory the importer doesn't have are written in place.
+/// Settings never are, since their secrets were redacted, and neither is the
+/// exporter's personal CLAUDE.md. Returns the paths written.
+pub fn install(files: &[ContextFile]) -> Result<Vec<PathBuf>> {
+    let mut written = Vec::new();
+    for file in files {
+        let in_place = match file.attachment.kind {
+            AttachmentKind::Memory => true,
+            AttachmentKind::ProjectFile => !file.attachment.path.ends_with(".json"),
+            _ => false,
+        };
+        let dest = match file.status {
+            ContextStatus::Same => continue,
+            ContextStatus::New if in_place => file.local_path.clone(),
+            _ => side_by_side_path(&file.local_path),
+        };
+
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&dest, &file.attachment.content).context(format!("Failed to write {}", dest.display()))?;
+        written.push(dest);
+    }
+    Ok(written)
+}
+
+/// `CLAUDE.md` -> `CLAUDE.mcc-import.md`
+fn side_by_side_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("file");
+    let name = match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => format!("{}.{}.{}", stem, SIDE_BY_SIDE_SUFFIX, ext),
+        None => format!("{}.{}", stem, SIDE_BY_SIDE_SUFFIX),
+    };
+    path.with_file_name(name)
+}
@@ -2,18 +2,55 @@ use anyhow::{Context, Result};
 use flate2::write::GzEncoder;
 use flate2::Compression;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs::{self, File};
 use std::io::Write;
 use std::path::{Path, PathBuf};
 
+use crate::files::{FileRef, SnapshotOptions};
+use crate::git::GitState;
+use crate::integrity::{self, Integrity};
+use crate::schema;
 use crate::session::{Session, SessionMessage};
+use crate::shrink::ShrinkOptions;
+
+/// Where `mcc export` writes and `mcc import` looks
+pub const EXPORT_FILE_NAME: &str = "mcc-export.json.gz";
+
+/// The same for exports in the zstd container
+pub const ZSTD_EXPORT_FILE_NAME: &str = "mcc-export.mccz";
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ExportedSession {
     pub version: String,
     pub exported_at: String,
     pub exported_by: String,
+    /// The only session, or the most recent one of a bundle. Versions of
+    /// mcc that don't know about bundles import just this one.
     pub session: SessionData,
+    /// The rest of a bundle's sessions
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bundled: Vec<SessionData>,
+    /// What a bundle contains, so it can be listed without reading every
+    /// transcript
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub manifest: Vec<BundleEntry>,
+    /// Snapshots of the files sessions referenced, keyed by SHA-256
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub blobs: BTreeMap<String, String>,
+    /// Checksums of everything above, set when the export is written
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub integrity: Option<Integrity>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleEntry {
+    pub id: String,
+    pub project_path: String,
+    pub summary: String,
+    pub messages: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git_branch: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -23,54 +60,309 @@ pub struct SessionData {
     pub messages: Vec<SessionMessage>,
     pub summary: String,
     pub git_branch: Option<String>,
+    /// Commit, remote and optionally uncommitted changes of the project
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git: Option<GitState>,
+    /// Files Claude Code keeps alongside the transcript
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<Attachment>,
+    /// Files Claude read or wrote, with their snapshots in the export's blobs
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<FileRef>,
+}
+
+/// A file that belongs to a session but isn't part of its transcript
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Attachment {
+    pub kind: AttachmentKind,
+    /// Relative to the directory `kind` lives in
+    pub path: String,
+    pub content: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttachmentKind {
+    /// Subagent (sidechain) transcripts and other files in the project's
+    /// session directory, ~/.claude/projects/<project>/
+    SessionFile,
+    /// The session's todo lists in ~/.claude/todos/
+    Todo,
+    /// CLAUDE.md and settings from the project directory
+    ProjectFile,
+    /// Claude Code's memory for the project, ~/.claude/projects/<project>/memory/
+    Memory,
+    /// The exporter's own ~/.claude/CLAUDE.md
+    UserFile,
+}
+
+impl AttachmentKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            AttachmentKind::SessionFile => "session file",
+            AttachmentKind::Todo => "todo list",
+            AttachmentKind::ProjectFile => "project instruction/settings file",
+            AttachmentKind::Memory => "memory file",
+            AttachmentKind::UserFile => "user instruction file",
+        }
+    }
+
+    /// Instructions and settings Claude was working under, as opposed to
+    /// files that are part of the session itself. These are only installed
+    /// on request, since the importer has their own.
+    pub fn is_context(&self) -> bool {
+        matches!(
+            self,
+            AttachmentKind::ProjectFile | AttachmentKind::Memory | AttachmentKind::UserFile
+        )
+    }
+}
+
+/// What `mcc export` includes besides the transcript
+#[derive(Debug, Clone, Default)]
+pub struct ExportOptions {
+    /// Uncommitted diff and untracked files
+    pub git_changes: bool,
+    /// CLAUDE.md files, project memory and settings
+    pub context: bool,
+    /// Snapshots of the files tool calls referenced
+    pub files: Option<SnapshotOptions>,
+    /// Write an older format version instead of the current one
+    pub format_version: Option<String>,
+    /// Write the zstd container instead of gzip-compressed JSON
+    pub zstd: bool,
+    /// Installed dictionary ID or .dict file to compress with
+    pub dictionary: Option<String>,
+    /// Truncate and deduplicate tool output, drop images
+    pub shrink: Option<ShrinkOptions>,
+}
+
+impl ExportOptions {
+    pub fn from_args(args: &[String]) -> Result<Self> {
+        let flag = |name: &str| args.iter().any(|arg| arg == name);
+        let value = |name: &str| {
+            args.iter()
+                .position(|arg| arg == name)
+                .and_then(|i| args.get(i + 1))
+                .cloned()
+        };
+        let dictionary = value("--dict");
+        Ok(Self {
+            git_changes: flag("--git-changes"),
+            context: flag("--context"),
+            files: SnapshotOptions::from_args(args)?,
+            format_version: value("--format-version"),
+            zstd: flag("--zstd") || dictionary.is_some(),
+            dictionary,
+            shrink: ShrinkOptions::from_args(args)?,
+        })
+    }
+
+    pub fn file_name(&self) -> &'static str {
+        if self.zstd { ZSTD_EXPORT_FILE_NAME } else { EXPORT_FILE_NAME }
+    }
 }
 
 impl ExportedSession {
     pub fn from_session(session: &Session) -> Result<Self> {
-        let hostname = hostname::get()
-            .ok()
-            .and_then(|h| h.into_string().ok())
-            .unwrap_or_else(|| "unknown".to_string());
-
-        let username = std::env::var("USER").unwrap_or_else(|_| "unknown".to_string());
+        Self::from_sessions(&[session])
+    }
 
-        Ok(ExportedSession {
-            version: "1.0.0".to_string(),
-            exported_at: chrono::Utc::now().to_rfc3339(),
-            exported_by: format!("{}@{}", username, hostname),
-            session: SessionData {
+    /// Bundle several sessions into one export. The first becomes the
+    /// primary session.
+    pub fn from_sessions(sessions: &[&Session]) -> Result<Self> {
+        let mut data = Vec::new();
+        for session in sessions {
+            data.push(SessionData {
                 id: session.id.clone(),
                 project_path: session.project_path.clone(),
                 messages: session.messages.clone(),
                 summary: session.summary.clone(),
                 git_branch: session.git_branch.clone(),
-            },
-        })
+                git: None,
+                attachments: session_attachments(session)?,
+                files: Vec::new(),
+            });
+        }
+        let mut data = data.into_iter();
+        let session = data.next().context("No sessions to export")?;
+        let bundled: Vec<SessionData> = data.collect();
+
+        let mut exported = ExportedSession {
+            version: schema::CURRENT_VERSION.to_string(),
+            exported_at: chrono::Utc::now().to_rfc3339(),
+            exported_by: local_identity(),
+            session,
+            bundled,
+            manifest: Vec::new(),
+            blobs: BTreeMap::new(),
+            integrity: None,
+        };
+        if exported.is_bundle() {
+            exported.manifest = exported
+                .sessions()
+                .map(|session| BundleEntry {
+                    id: session.id.clone(),
+                    project_path: session.project_path.clone(),
+                    summary: session.summary.clone(),
+                    messages: session.messages.len(),
+                    git_branch: session.git_branch.clone(),
+                })
+                .collect();
+        }
+        Ok(exported)
+    }
+
+    pub fn is_bundle(&self) -> bool {
+        !self.bundled.is_empty()
+    }
+
+    /// Every session in the export, primary first
+    pub fn sessions(&self) -> impl Iterator<Item = &SessionData> {
+        std::iter::once(&self.session).chain(&self.bundled)
+    }
+
+    /// Record the checksums of the export as it is now
+    pub fn seal(&mut self) -> Result<()> {
+        self.integrity = None;
+        self.integrity = Some(integrity::compute(&serde_json::to_value(&*self)?));
+        Ok(())
     }
 
     /// Export session to a compressed .mcc file
-    pub fn export_to_file(&self, output_path: &Path) -> Result<()> {
-        let json = serde_json::to_string_pretty(&self)?;
+    pub fn export_to_file(&mut self, output_path: &Path) -> Result<()> {
+        self.seal()?;
+        write_export(&serde_json::to_string_pretty(&self)?, output_path)
+    }
+}
 
-        let file = File::create(output_path)
-            .context(format!("Failed to create file: {:?}", output_path))?;
+/// Write an export's JSON, possibly converted to another format version, to
+/// a compressed .mcc file
+pub fn write_export(json: &str, output_path: &Path) -> Result<()> {
+    let file = File::create(output_path)
+        .context(format!("Failed to create file: {:?}", output_path))?;
 
-        let mut encoder = GzEncoder::new(file, Compression::default());
-        encoder
-            .write_all(json.as_bytes())
-            .context("Failed to write compressed data")?;
-        encoder
-            .finish()
-            .context("Failed to finish compression")?;
+    let mut encoder = GzEncoder::new(file, Compression::default());
+    encoder
+        .write_all(json.as_bytes())
+        .context("Failed to write compressed data")?;
+    encoder
+        .finish()
+        .context("Failed to finish compression")?;
 
-        Ok(())
+    Ok(())
+}
+
+/// The export in `dir` that `mcc import` picks up: whichever kind is there,
+/// or the newer if both are
+pub fn find_export_file(dir: &Path) -> PathBuf {
+    let modified = |name: &str| fs::metadata(dir.join(name)).and_then(|m| m.modified()).ok();
+    match (modified(EXPORT_FILE_NAME), modified(ZSTD_EXPORT_FILE_NAME)) {
+        (Some(gzip), Some(zstd)) if zstd > gzip => dir.join(ZSTD_EXPORT_FILE_NAME),
+        (None, Some(_)) => dir.join(ZSTD_EXPORT_FILE_NAME),
+        _ => dir.join(EXPORT_FILE_NAME),
+    }
+}
+
+/// Subagent transcripts, tool output and todo lists belonging to a session:
+/// - `<project dir>/<id>/...`, where Claude Code keeps subagent transcripts
+/// - `<project dir>/agent-*.jsonl` sidechains recorded with this session ID,
+///   as older versions of Claude Code stored them
+/// - `~/.claude/todos/<id>-*.json`
+fn session_attachments(session: &Session) -> Result<Vec<Attachment>> {
+    let mut attachments = Vec::new();
+    let Some(project_dir) = session.file_path.parent() else {
+        return Ok(attachments);
+    };
+
+    let session_dir = project_dir.join(&session.id);
+    if session_dir.is_dir() {
+        for path in files_under(&session_dir)? {
+            add_attachment(&mut attachments, AttachmentKind::SessionFile, project_dir, &path);
+        }
     }
+
+    for entry in fs::read_dir(project_dir)? {
+        let path = entry?.path();
+        let is_sidechain = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with("agent-") && name.ends_with(".jsonl"));
+        if is_sidechain && sidechain_session_id(&path).as_deref() == Some(session.id.as_str()) {
+            add_attachment(&mut attachments, AttachmentKind::SessionFile, project_dir, &path);
+        }
+    }
+
+    let home = std::env::var("HOME")?;
+    let todos_dir = PathBuf::from(home).join(".claude/todos");
+    if todos_dir.is_dir() {
+        let prefix = format!("{}-", session.id);
+        for entry in fs::read_dir(&todos_dir)? {
+            let path = entry?.path();
+            if path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(&prefix))
+            {
+                add_attachment(&mut attachments, AttachmentKind::Todo, &todos_dir, &path);
+            }
+        }
+    }
+
+    Ok(attachments)
+}
+
+/// Add `path` as an attachment relative to `root`. Files that aren't text
+/// are skipped; everything Claude Code writes here is JSON.
+fn add_attachment(attachments: &mut Vec<Attachment>, kind: AttachmentKind, root: &Path, path: &Path) {
+    let (Ok(relative), Ok(content)) = (path.strip_prefix(root), fs::read_to_string(path)) else {
+        return;
+    };
+    attachments.push(Attachment {
+        kind,
+        path: relative.to_string_lossy().to_string(),
+        content,
+    });
+}
+
+/// Every file under `dir`, recursively
+fn files_under(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files.extend(files_under(&path)?);
+        } else if path.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// The session a sidechain transcript was recorded in, from its first record
+fn sidechain_session_id(path: &Path) -> Option<String> {
+    let content = fs::read_to_string(path).ok()?;
+    let first: serde_json::Value = serde_json::from_str(content.lines().next()?).ok()?;
+    first.get("sessionId")?.as_str().map(|id| id.to_string())
+}
+
+/// `user@hostname` of whoever is running mcc
+pub fn local_identity() -> String {
+    let hostname = hostname::get()
+        .ok()
+        .and_then(|h| h.into_string().ok())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    let username = std::env::var("USER").unwrap_or_else(|_| "unknown".to_string());
+
+    format!("{}@{}", username, hostname)
 }
 
 /// Export a session to an .mcc file
 #[allow(dead_code)]
 pub fn export_session(session: &Session, output_dir: Option<&Path>) -> Result<PathBuf> {
-    let exported = ExportedSession::from_session(session)?;
+    let mut exported = ExportedSession::from_session(session)?;
 
     // Determine output directory
     let output_dir = output_dir
@@ -0,0 +1,212 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::filter::glob_match;
+use crate::manifest::sha256_hex;
+use crate::prune::parse_size;
+use crate::session::SessionMessage;
+
+/// Largest file snapshotted unless `--max-file-size` says otherwise
+const DEFAULT_MAX_FILE_SIZE: u64 = 1024 * 1024;
+
+/// Snapshots stop once this much content has been collected
+const DEFAULT_MAX_TOTAL_SIZE: u64 = 50 * 1024 * 1024;
+
+/// Where `mcc extract-files` puts files from outside the exporter's project
+const EXTERNAL_DIR: &str = "_external";
+
+/// A file Claude read or wrote during the session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileRef {
+    /// As the tool call named it, usually absolute
+    pub path: String,
+    /// SHA-256 of the content, the key into the export's blobs. Missing when
+    /// the file wasn't snapshotted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    pub size: u64,
+    /// Why the file wasn't snapshotted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skipped: Option<String>,
+}
+
+/// Which referenced files `mcc export --files` snapshots
+#[derive(Debug, Clone)]
+pub struct SnapshotOptions {
+    pub max_file_size: u64,
+    pub max_total_size: u64,
+    /// Globs matched against the path relative to the project, or the
+    /// absolute path for files outside it. Empty means everything.
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl SnapshotOptions {
+    /// None unless `--files` was given
+    pub fn from_args(args: &[String]) -> Result<Option<Self>> {
+        if !args.iter().any(|arg| arg == "--files") {
+            return Ok(None);
+        }
+
+        let value = |flag: &str| {
+            args.iter()
+                .position(|arg| arg == flag)
+                .and_then(|i| args.get(i + 1))
+        };
+        let globs = |flag: &str| -> Vec<String> {
+            value(flag)
+                .map(|list| list.split(',').map(|glob| glob.trim().to_string()).filter(|glob| !glob.is_empty()).collect())
+                .unwrap_or_default()
+        };
+
+        Ok(Some(Self {
+            max_file_size: value("--max-file-size").map(|size| parse_size(size)).transpose()?.unwrap_or(DEFAULT_MAX_FILE_SIZE),
+            max_total_size: value("--max-total-size").map(|size| parse_size(size)).transpose()?.unwrap_or(DEFAULT_MAX_TOTAL_SIZE),
+            include: globs("--include"),
+            exclude: globs("--exclude"),
+        }))
+    }
+
+    fn selects(&self, display_path: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|glob| glob_match(glob, display_path)))
+            && !self.exclude.iter().any(|glob| glob_match(glob, display_path))
+    }
+}
+
+/// Paths of files read, written or edited by tool calls, in the order they
+/// were first used
+pub fn referenced_paths(messages: &[SessionMessage]) -> Vec<String> {
+    let mut paths: Vec<String> = Vec::new();
+    for message in messages.iter().filter(|message| message.msg_type == "assistant") {
+        let Some(content) = message.data.pointer("/message/content").and_then(|content| content.as_array()) else {
+            continue;
+        };
+        for block in content {
+            if block.get("type").and_then(|kind| kind.as_str()) != Some("tool_use") {
+                continue;
+            }
+            let Some(input) = block.get("input") else {
+                continue;
+            };
+            for key in ["file_path", "notebook_path"] {
+                if let Some(path) = input.get(key).and_then(|path| path.as_str())
+                    && !paths.iter().any(|seen| seen == path)
+                {
+                    paths.push(path.to_string());
+                }
+            }
+        }
+    }
+    paths
+}
+
+/// Snapshot the files a session's tool calls referenced as they are now.
+/// Content goes into `blobs`, keyed by its SHA-256 so files shared between
+/// sessions of a bundle are stored once.
+pub fn snapshot(
+    messages: &[SessionMessage],
+    project_path: &str,
+    options: &SnapshotOptions,
+    blobs: &mut BTreeMap<String, String>,
+) -> Vec<FileRef> {
+    let mut total: u64 = blobs.values().map(|content| content.len() as u64).sum();
+    let mut files = Vec::new();
+
+    for path in referenced_paths(messages) {
+        if !options.selects(&display_path(&path, project_path)) {
+            continue;
+        }
+
+        let mut file = FileRef {
+            path: path.clone(),
+            sha256: None,
+            size: 0,
+            skipped: None,
+        };
+        let absolute = Path::new(project_path).join(&path);
+        match fs::metadata(&absolute) {
+            Err(_) => file.skipped = Some("no longer exists".to_string()),
+            Ok(metadata) if !metadata.is_file() => file.skipped = Some("not a file".to_string()),
+            Ok(metadata) => {
+                file.size = metadata.len();
+                if file.size > options.max_file_size {
+                    file.skipped = Some("too large".to_string());
+                } else {
+                    match fs::read_to_string(&absolute) {
+                        Err(_) => file.skipped = Some("not a text file".to_string()),
+                        Ok(content) => {
+                            let sha256 = sha256_hex(content.as_bytes());
+                            if !blobs.contains_key(&sha256) {
+                                if total + file.size > options.max_total_size {
+                                    file.skipped = Some("total size limit reached".to_string());
+                                } else {
+                                    total += file.size;
+                                    blobs.insert(sha256.clone(), content);
+                                }
+                            }
+                            if file.skipped.is_none() {
+                                file.sha256 = Some(sha256);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        files.push(file);
+    }
+    files
+}
+
+/// `path` relative to the project if it's inside it
+fn display_path(path: &str, project_path: &str) -> String {
+    Path::new(path)
+        .strip_prefix(project_path)
+        .map(|relative| relative.to_string_lossy().to_string())
+        .unwrap_or_else(|_| path.to_string())
+}
+
+/// Where `mcc extract-files` writes a snapshot: files in the project keep
+/// their relative path under `into`, others go under `into`/_external/
+/// with their absolute path
+pub fn extract_path(file: &FileRef, project_path: &str, into: &Path) -> Result<PathBuf> {
+    let path = Path::new(&file.path);
+    let relative = match path.strip_prefix(project_path) {
+        Ok(relative) => relative.to_path_buf(),
+        Err(_) if path.is_absolute() => Path::new(EXTERNAL_DIR).join(path.strip_prefix("/").unwrap_or(path)),
+        Err(_) => path.to_path_buf(),
+    };
+    if relative.as_os_str().is_empty() || !relative.components().all(|component| matches!(component, Component::Normal(_))) {
+        anyhow::bail!("Refusing snapshot with unsafe path: {}", file.path);
+    }
+    Ok(into.join(relative))
+}
+
+/// What happened to a snapshot `mcc extract-files` was asked to write
+pub enum Extracted {
+    Written,
+    /// An identical file was already there
+    Unchanged,
+    /// A different file was already there and `--force` wasn't given
+    Kept,
+}
+
+/// Write a snapshot to `dest`, leaving an existing different file alone
+/// unless `force`
+pub fn extract(content: &str, dest: &Path, force: bool) -> Result<Extracted> {
+    if let Ok(existing) = fs::read_to_string(dest) {
+        if existing == content {
+            return Ok(Extracted::Unchanged);
+        }
+        if !force {
+            return Ok(Extracted::Kept);
+        }
+    }
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(dest, content).context(format!("Failed to write {}", dest.display()))?;
+    Ok(Extracted::Written)
+}
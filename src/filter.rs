@@ -0,0 +1,232 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+
+/// Criteria for narrowing down a list of sessions, built from command-line
+/// flags such as `--project api --branch main --since 7d`
+#[derive(Debug, Default)]
+pub struct SessionFilter {
+    /// Substring of the project path, or a glob if it contains `*` or `?`
+    pub project: Option<String>,
+    /// Session ID or ID prefix
+    pub session: Option<String>,
+    pub branch: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl SessionFilter {
+    pub fn from_args(args: &[String]) -> Result<Self> {
+        let value = |flag: &str| {
+            args.iter()
+                .position(|arg| arg == flag)
+                .and_then(|i| args.get(i + 1))
+                .cloned()
+        };
+
+        Ok(Self {
+            project: value("--project"),
+            session: value("--session"),
+            branch: value("--branch"),
+            since: value("--since").map(|s| parse_time(&s)).transpose()?,
+            until: value("--until").map(|s| parse_until(&s)).transpose()?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.project.is_none()
+            && self.session.is_none()
+            && self.branch.is_none()
+            && self.since.is_none()
+            && self.until.is_none()
+    }
+
+    /// Sessions with an unknown modification time never match `--since`
+    /// or `--until`
+    pub fn matches(
+        &self,
+        id: &str,
+        project_path: &str,
+        git_branch: Option<&str>,
+        modified: Option<DateTime<Utc>>,
+    ) -> bool {
+        if let Some(project) = &self.project {
+            let matched = if project.contains(['*', '?']) {
+                glob_match(project, project_path)
+            } else {
+                project_path.contains(project.as_str())
+            };
+            if !matched {
+                return false;
+            }
+        }
+
+        if let Some(session) = &self.session
+            && !id.starts_with(session.as_str())
+        {
+            return false;
+        }
+
+        if let Some(branch) = &self.branch
+            && git_branch != Some(branch.as_str())
+        {
+            return false;
+        }
+
+        if let Some(since) = self.since
+            && modified.is_none_or(|modified| modified < since)
+        {
+            return false;
+        }
+
+        if let Some(until) = self.until
+            && modified.is_none_or(|modified| modified >= until)
+        {
+            return false;
+        }
+
+        true
+    }
+}
+
+/// Like `parse_time`, but a plain date means the end of that day so
+/// `--until 2026-01-31` includes the 31st
+fn parse_until(value: &str) -> Result<DateTime<Utc>> {
+    let time = parse_time(value)?;
+    if NaiveDate::parse_from_str(value, "%Y-%m-%d").is_ok() {
+        return time
+            .checked_add_signed(chrono::Duration::days(1))
+            .context(format!("Invalid date: {}", value));
+    }
+    Ok(time)
+}
+
+/// Match `text` against a glob where `*` matches any run of characters
+/// (including `/`) and `?` matches exactly one
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star, star_t)) = backtrack {
+            // Let the last `*` swallow one more character and try again
+            p = star + 1;
+            t = star_t + 1;
+            backtrack = Some((star, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Parse a point in time given as a date (`2026-01-02`), an RFC 3339
+/// timestamp, or an age relative to now (`30m`, `12h`, `7d`, `2w`)
+pub fn parse_time(value: &str) -> Result<DateTime<Utc>> {
+    if let Ok(age) = parse_age(value) {
+        return Ok(Utc::now() - age);
+    }
+
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.with_timezone(&Utc));
+    }
+
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .context(format!("Invalid date: {} (expected YYYY-MM-DD or an age like 7d)", value))?;
+    Ok(date
+        .and_hms_opt(0, 0, 0)
+        .context("Invalid date")?
+        .and_utc())
+}
+
+/// Parse an age such as `30m`, `12h`, `7d` or `2w`
+pub fn parse_age(value: &str) -> Result<chrono::Duration> {
+    let invalid = || format!("Invalid age: {} (expected e.g. 30m, 12h, 7d)", value);
+    let mut chars = value.chars();
+    let unit = chars.next_back().context(invalid())?;
+    let number: i64 = chars.as_str().parse().context(invalid())?;
+    if number < 0 {
+        anyhow::bail!("Invalid age: {} can't be negative", value);
+    }
+
+    let age = match unit {
+        'm' => chrono::Duration::try_minutes(number),
+        'h' => chrono::Duration::try_hours(number),
+        'd' => chrono::Duration::try_days(number),
+        'w' => chrono::Duration::try_weeks(number),
+        _ => anyhow::bail!("Invalid age unit in {} (use m, h, d or w)", value),
+    };
+    // Ages are added to or taken from now, so both have to stay in range
+    let now = Utc::now();
+    age.filter(|age| now.checked_sub_signed(*age).is_some() && now.checked_add_signed(*age).is_some())
+        .context(format!("Invalid age: {} is too large", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ages() {
+        assert_eq!(parse_age("30m").unwrap(), chrono::Duration::minutes(30));
+        assert_eq!(parse_age("12h").unwrap(), chrono::Duration::hours(12));
+        assert_eq!(parse_age("7d").unwrap(), chrono::Duration::days(7));
+        assert_eq!(parse_age("2w").unwrap(), chrono::Duration::weeks(2));
+        assert_eq!(parse_age("0d").unwrap(), chrono::Duration::zero());
+    }
+
+    #[test]
+    fn rejects_invalid_ages() {
+        for age in ["", "d", "7", "7x", "7é", "é", "7dd", "-30d", "1.5d", "99999999999999d", "9223372036854775807m"] {
+            assert!(parse_age(age).is_err(), "{:?} was accepted", age);
+        }
+    }
+
+    #[test]
+    fn parses_times() {
+        let time = parse_time("2026-01-31T12:00:00Z").unwrap();
+        assert_eq!(time.to_rfc3339(), "2026-01-31T12:00:00+00:00");
+        assert_eq!(parse_time("2026-01-31").unwrap().to_rfc3339(), "2026-01-31T00:00:00+00:00");
+        assert_eq!(parse_until("2026-01-31").unwrap().to_rfc3339(), "2026-02-01T00:00:00+00:00");
+
+        let age = Utc::now() - parse_time("7d").unwrap();
+        assert!((age - chrono::Duration::days(7)).num_seconds().abs() < 5);
+        assert!(parse_time("last tuesday").is_err());
+    }
+
+    #[test]
+    fn matches_globs() {
+        assert!(glob_match("*/api", "/home/alice/api"));
+        assert!(glob_match("/home/*/ap?", "/home/alice/api"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*é*", "café"));
+        assert!(!glob_match("*/api", "/home/alice/api-v2"));
+        assert!(!glob_match("?", ""));
+    }
+
+    #[test]
+    fn filters_sessions() {
+        let filter = SessionFilter {
+            project: Some("api".to_string()),
+            branch: Some("main".to_string()),
+            since: Some(parse_time("2026-01-01").unwrap()),
+            ..Default::default()
+        };
+        let modified = parse_time("2026-02-01").ok();
+
+        assert!(filter.matches("abc", "/home/alice/api", Some("main"), modified));
+        assert!(!filter.matches("abc", "/home/alice/web", Some("main"), modified));
+        assert!(!filter.matches("abc", "/home/alice/api", Some("dev"), modified));
+        assert!(!filter.matches("abc", "/home/alice/api", Some("main"), None));
+        assert!(!filter.matches("abc", "/home/alice/api", Some("main"), parse_time("2025-12-31").ok()));
+        assert!(SessionFilter::default().matches("abc", "/", None, None));
+    }
+}
@@ -0,0 +1,483 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::export::{EXPORT_FILE_NAME, ZSTD_EXPORT_FILE_NAME};
+
+/// Notes ref commits are linked to sessions under, kept apart from the
+/// default notes so `git log` output is unchanged
+pub const NOTES_REF: &str = "refs/notes/mcc";
+
+/// Marks the lines mcc adds to a hook, so they can be found and removed
+const HOOK_MARKER: &str = "# Added by mcc";
+
+/// Untracked files larger than this are listed but not included
+const MAX_UNTRACKED_SIZE: u64 = 256 * 1024;
+
+/// The state of the exporter's working tree, so whoever resumes the session
+/// can tell whether Claude's view of the files still matches theirs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GitState {
+    /// Commit SHA of HEAD
+    pub head: String,
+    /// None when HEAD is detached
+    pub branch: Option<String>,
+    pub remote_url: Option<String>,
+    /// Paths with uncommitted changes, including untracked files
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub changed_files: Vec<String>,
+    /// `git diff HEAD`, when exported with --git-changes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diff: Option<String>,
+    /// Untracked files, when exported with --git-changes
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub untracked: Vec<UntrackedFile>,
+    /// Untracked files left out because they are binary or too large
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skipped_untracked: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UntrackedFile {
+    pub path: String,
+    pub content: String,
+}
+
+impl GitState {
+    /// Capture the state of the repository at `dir`, or None if it isn't
+    /// one (or has no commits yet). With `include_changes`, the uncommitted
+    /// diff and untracked files are captured too.
+    pub fn capture(dir: &Path, include_changes: bool) -> Option<Self> {
+        let head = git(dir, &["rev-parse", "HEAD"])?;
+        let mut state = Self {
+            head,
+            branch: git(dir, &["symbolic-ref", "--short", "-q", "HEAD"]),
+            remote_url: git(dir, &["config", "--get", "remote.origin.url"]),
+            changed_files: changed_files(dir),
+            diff: None,
+            untracked: Vec::new(),
+            skipped_untracked: Vec::new(),
+        };
+
+        if include_changes {
+            state.diff = git_raw(dir, &["diff", "HEAD", "--binary"]).filter(|diff| !diff.is_empty());
+
+            let untracked = git_raw(dir, &["ls-files", "--others", "--exclude-standard", "-z"]).unwrap_or_default();
+            for path in untracked
+                .split('\0')
+                .filter(|path| !path.is_empty() && !is_export_file(path))
+            {
+                let full_path = dir.join(path);
+                let small = std::fs::metadata(&full_path).is_ok_and(|m| m.len() <= MAX_UNTRACKED_SIZE);
+                match std::fs::read_to_string(&full_path) {
+                    Ok(content) if small => state.untracked.push(UntrackedFile {
+                        path: path.to_string(),
+                        content,
+                    }),
+                    _ => state.skipped_untracked.push(path.to_string()),
+                }
+            }
+        }
+
+        Some(state)
+    }
+
+    /// State pointing at a commit, without branch or working tree details
+    pub fn at_commit(dir: &Path, commit: &str) -> Self {
+        Self {
+            head: commit.to_string(),
+            branch: None,
+            remote_url: git(dir, &["config", "--get", "remote.origin.url"]),
+            changed_files: Vec::new(),
+            diff: None,
+            untracked: Vec::new(),
+            skipped_untracked: Vec::new(),
+        }
+    }
+
+    pub fn short_head(&self) -> &str {
+        short_sha(&self.head)
+    }
+
+    /// Ways the working tree at `dir` differs from this state, for whoever
+    /// is importing the session. Empty if it matches.
+    pub fn differences(&self, dir: &Path) -> Vec<String> {
+        let Some(local_head) = git(dir, &["rev-parse", "HEAD"]) else {
+            return vec![format!("{} is not a git repository", dir.display())];
+        };
+        let mut differences = Vec::new();
+
+        let local_remote = git(dir, &["config", "--get", "remote.origin.url"]);
+        if let (Some(exported), Some(local)) = (&self.remote_url, &local_remote)
+            && normalize_url(exported) != normalize_url(local)
+        {
+            differences.push(format!("Different repository: exported from {}, this is {}", exported, local));
+        }
+
+        if local_head != self.head {
+            let known = git_raw(dir, &["cat-file", "-e", &format!("{}^{{commit}}", self.head)]).is_some();
+            let counts = known
+                .then(|| git(dir, &["rev-list", "--left-right", "--count", &format!("HEAD...{}", self.head)]))
+                .flatten();
+
+            match counts.as_deref().and_then(|counts| counts.split_once('\t')) {
+                Some((ahead, behind)) => differences.push(format!(
+                    "You are at {}, {} commits ahead and {} behind the exported commit {}",
+                    short_sha(&local_head),
+                    ahead,
+                    behind,
+                    self.short_head()
+                )),
+                None => differences.push(format!(
+                    "Exported at commit {}, which isn't in this clone (try: git fetch)",
+                    self.short_head()
+                )),
+            }
+        }
+
+        let local_branch = git(dir, &["symbolic-ref", "--short", "-q", "HEAD"]);
+        if self.branch.is_some() && local_branch != self.branch {
+            differences.push(format!(
+                "Exported on branch {}, you are on {}",
+                self.branch.as_deref().unwrap_or_default(),
+                local_branch.as_deref().unwrap_or("a detached HEAD")
+            ));
+        }
+
+        if !self.changed_files.is_empty() {
+            let included = if self.diff.is_some() || !self.untracked.is_empty() {
+                "included in the export"
+            } else {
+                "not included; export with --git-changes to ship them"
+            };
+            differences.push(format!(
+                "The exporter had uncommitted changes in {} files ({})",
+                self.changed_files.len(),
+                included
+            ));
+        }
+
+        let local_changes = changed_files(dir);
+        if !local_changes.is_empty() {
+            differences.push(format!("You have uncommitted changes in {} files", local_changes.len()));
+        }
+
+        differences
+    }
+}
+
+/// A worktree recreating the exporter's working tree
+pub struct Worktree {
+    pub path: PathBuf,
+    /// Hunks of the uncommitted diff that didn't apply, as git reported them
+    pub rejected: Vec<String>,
+    pub untracked_written: usize,
+    /// Untracked files whose paths would have landed outside the worktree
+    pub unsafe_untracked: Vec<String>,
+}
+
+/// Default location for a worktree of the repository at `repo`: next to it,
+/// named after the repository and the exported commit
+pub fn default_worktree_path(repo: &Path, state: &GitState) -> Result<PathBuf> {
+    let top_level = git(repo, &["rev-parse", "--show-toplevel"]).context(format!("{} is not a git repository", repo.display()))?;
+    let top_level = PathBuf::from(top_level);
+    let name = top_level.file_name().and_then(|name| name.to_str()).unwrap_or("worktree");
+    Ok(top_level
+        .parent()
+        .unwrap_or(&top_level)
+        .join(format!("{}-mcc-{}", name, state.short_head())))
+}
+
+/// Create a detached worktree of `repo` at `path` checked out at the exported
+/// commit, then apply the exported uncommitted diff and untracked files
+pub fn create_worktree(repo: &Path, state: &GitState, path: &Path) -> Result<Worktree> {
+    if path.exists() {
+        anyhow::bail!("{} already exists; choose another path with --worktree <path>", path.display());
+    }
+    if git_raw(repo, &["cat-file", "-e", &format!("{}^{{commit}}", state.head)]).is_none() {
+        anyhow::bail!("Commit {} isn't in this clone. Run git fetch and try again", state.short_head());
+    }
+
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(["worktree", "add", "--detach"])
+        .arg(path)
+        .arg(&state.head)
+        .output()
+        .context("Failed to run git")?;
+    if !output.status.success() {
+        anyhow::bail!("git worktree add failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+
+    let mut worktree = Worktree {
+        path: path.to_path_buf(),
+        rejected: Vec::new(),
+        untracked_written: 0,
+        unsafe_untracked: Vec::new(),
+    };
+
+    if let Some(diff) = &state.diff {
+        worktree.rejected = apply_patch(path, diff)?;
+    }
+
+    for file in &state.untracked {
+        // Exports come from other people; never write outside the worktree
+        let relative = Path::new(&file.path);
+        if relative.as_os_str().is_empty()
+            || !relative
+                .components()
+                .all(|component| matches!(component, std::path::Component::Normal(_)))
+        {
+            worktree.unsafe_untracked.push(file.path.clone());
+            continue;
+        }
+        let dest = path.join(relative);
+        if dest.exists() {
+            continue;
+        }
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&dest, &file.content).context(format!("Failed to write {}", dest.display()))?;
+        worktree.untracked_written += 1;
+    }
+
+    Ok(worktree)
+}
+
+/// Apply `diff` in `dir`, keeping the hunks that apply and leaving .rej
+/// files for the rest. Returns git's description of each rejected hunk.
+fn apply_patch(dir: &Path, diff: &str) -> Result<Vec<String>> {
+    let mut child = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["apply", "--reject", "--whitespace=nowarn", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run git apply")?;
+
+    child
+        .stdin
+        .take()
+        .context("Failed to write to git apply")?
+        .write_all(diff.as_bytes())?;
+    let output = child.wait_with_output()?;
+    if output.status.success() {
+        return Ok(Vec::new());
+    }
+
+    // git names the file before listing its rejected hunks
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let mut rejected = Vec::new();
+    let mut current_file = "";
+    for line in stderr.lines() {
+        if let Some(rest) = line.strip_prefix("Applying patch ") {
+            current_file = rest.split(" with ").next().unwrap_or(rest);
+        } else if let Some(hunk) = line.strip_prefix("Rejected hunk ") {
+            rejected.push(format!("{}: hunk {}", current_file, hunk.trim_end_matches('.')));
+        }
+    }
+
+    // Nothing was rejected hunk by hunk, e.g. the patch itself is corrupt
+    if rejected.is_empty() {
+        rejected.extend(
+            stderr
+                .lines()
+                .filter_map(|line| line.strip_prefix("error: "))
+                .map(|line| line.to_string()),
+        );
+    }
+    Ok(rejected)
+}
+
+/// Top-level directory of the repository containing `dir`
+pub fn top_level(dir: &Path) -> Option<PathBuf> {
+    git(dir, &["rev-parse", "--show-toplevel"]).map(PathBuf::from)
+}
+
+/// Full SHA of a revision such as `HEAD~2` or a short SHA
+pub fn resolve_commit(dir: &Path, rev: &str) -> Option<String> {
+    git(dir, &["rev-parse", "--verify", "-q", &format!("{}^{{commit}}", rev)])
+}
+
+/// When a commit was made
+pub fn commit_time(dir: &Path, commit: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+    let time = git(dir, &["show", "-s", "--format=%cI", commit])?;
+    chrono::DateTime::parse_from_rfc3339(&time)
+        .ok()
+        .map(|time| time.with_timezone(&chrono::Utc))
+}
+
+/// Record that `commit` came out of `session_id`
+pub fn add_session_note(dir: &Path, commit: &str, session_id: &str) -> Result<()> {
+    let note = format!("Claude-Session: {}", session_id);
+    if git_raw(dir, &["notes", "--ref", NOTES_REF, "add", "-f", "-m", &note, commit]).is_none() {
+        anyhow::bail!("Failed to add git note to {}", short_sha(commit));
+    }
+    Ok(())
+}
+
+/// The session a commit was linked to, if any
+pub fn session_note(dir: &Path, commit: &str) -> Option<String> {
+    let note = git(dir, &["notes", "--ref", NOTES_REF, "show", commit])?;
+    note.lines()
+        .find_map(|line| line.strip_prefix("Claude-Session:"))
+        .map(|id| id.trim().to_string())
+}
+
+/// Path of the post-commit hook, respecting core.hooksPath
+fn post_commit_hook(dir: &Path) -> Result<PathBuf> {
+    let hooks = git(dir, &["rev-parse", "--git-path", "hooks"]).context(format!("{} is not a git repository", dir.display()))?;
+    Ok(dir.join(hooks).join("post-commit"))
+}
+
+/// Add a post-commit hook that links each commit to the active session.
+/// An existing hook is kept and the call appended to it.
+pub fn install_hook(dir: &Path, exe: &Path) -> Result<PathBuf> {
+    let hook = post_commit_hook(dir)?;
+    let existing = std::fs::read_to_string(&hook).unwrap_or_default();
+    if existing.contains(HOOK_MARKER) {
+        anyhow::bail!("The mcc hook is already installed in {}", hook.display());
+    }
+
+    let mut content = if existing.is_empty() {
+        "#!/bin/sh\n".to_string()
+    } else {
+        format!("{}\n", existing.trim_end())
+    };
+    content.push_str(&format!(
+        "{}: link this commit to the Claude Code session that produced it\n\"{}\" hooks post-commit || true\n",
+        HOOK_MARKER,
+        exe.display()
+    ));
+
+    if let Some(parent) = hook.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&hook, content)?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&hook, std::fs::Permissions::from_mode(0o755))?;
+    }
+
+    Ok(hook)
+}
+
+/// Remove the lines `install_hook` added, and the hook itself if nothing
+/// else is left in it
+pub fn uninstall_hook(dir: &Path) -> Result<PathBuf> {
+    let hook = post_commit_hook(dir)?;
+    let existing = std::fs::read_to_string(&hook).unwrap_or_default();
+    if !existing.contains(HOOK_MARKER) {
+        anyhow::bail!("The mcc hook is not installed in {}", hook.display());
+    }
+
+    let mut skip_next = false;
+    let kept: Vec<&str> = existing
+        .lines()
+        .filter(|line| {
+            if line.starts_with(HOOK_MARKER) {
+                skip_next = true;
+                return false;
+            }
+            !std::mem::take(&mut skip_next)
+        })
+        .collect();
+
+    if kept.iter().all(|line| line.trim().is_empty() || line.starts_with("#!")) {
+        std::fs::remove_file(&hook)?;
+    } else {
+        std::fs::write(&hook, format!("{}\n", kept.join("\n")))?;
+    }
+    Ok(hook)
+}
+
+/// The export itself usually sits untracked in the project; it isn't a change
+fn is_export_file(path: &str) -> bool {
+    path == EXPORT_FILE_NAME || path == ZSTD_EXPORT_FILE_NAME
+}
+
+/// Paths with uncommitted changes according to `git status`
+fn changed_files(dir: &Path) -> Vec<String> {
+    let status = git_raw(dir, &["status", "--porcelain", "-z"]).unwrap_or_default();
+    let mut entries = status.split('\0').filter(|entry| entry.len() > 3);
+    let mut files = Vec::new();
+
+    while let Some(entry) = entries.next() {
+        if !is_export_file(&entry[3..]) {
+            files.push(entry[3..].to_string());
+        }
+        // Renames and copies are followed by the original path
+        if entry.starts_with('R') || entry.starts_with('C') {
+            entries.next();
+        }
+    }
+    files
+}
+
+/// Run git in `dir`, returning trimmed output, or None if it failed or
+/// printed nothing
+fn git(dir: &Path, args: &[&str]) -> Option<String> {
+    git_raw(dir, args)
+        .map(|output| output.trim().to_string())
+        .filter(|output| !output.is_empty())
+}
+
+/// Run git in `dir`, returning its output exactly as printed
+fn git_raw(dir: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git").arg("-C").arg(dir).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout).ok()
+}
+
+/// First 8 characters of a commit SHA
+pub fn short_sha(sha: &str) -> &str {
+    match sha.char_indices().nth(8) {
+        Some((end, _)) => &sha[..end],
+        None => sha,
+    }
+}
+
+/// Get the git remote URL for the current directory
+pub fn remote_url() -> Result<String> {
+    remote_url_for_path(".")
+}
+
+/// Get the git remote URL for a specific path
+pub fn remote_url_for_path(path: &str) -> Result<String> {
+    match git(Path::new(path), &["config", "--get", "remote.origin.url"]) {
+        Some(url) => Ok(url),
+        None => anyhow::bail!("No git remote found"),
+    }
+}
+
+/// Normalize git URL for comparison (handles https vs ssh formats)
+pub fn normalize_url(url: &str) -> String {
+    url.trim()
+        .trim_end_matches(".git")
+        .replace("git@github.com:", "github.com/")
+        .replace("https://github.com/", "github.com/")
+        .replace("http://github.com/", "github.com/")
+        .to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shortens_shas() {
+        assert_eq!(short_sha("3f2a9c1e8b7d6a5f"), "3f2a9c1e");
+        assert_eq!(short_sha("3f2a"), "3f2a");
+        assert_eq!(short_sha("ééééééééé"), "éééééééé");
+    }
+}
@@ -1,23 +1,29 @@
 use anyhow::{Context, Result};
-use flate2::read::GzDecoder;
-use std::fs::{self, File};
-use std::io::Read;
+use std::fs;
 use std::path::{Path, PathBuf};
 
-use crate::export::ExportedSession;
-
-/// Import a session from an .mcc file
-pub fn import_session(mcc_file: &Path, target_project_path: Option<String>) -> Result<PathBuf> {
-    // Read and decompress the .mcc file
-    let file = File::open(mcc_file).context("Failed to open .mcc file")?;
-    let mut decoder = GzDecoder::new(file);
-    let mut json = String::new();
-    decoder
-        .read_to_string(&mut json)
-        .context("Failed to decompress .mcc file")?;
+use crate::container;
+use crate::export::{Attachment, AttachmentKind, ExportedSession, SessionData};
+use crate::integrity;
+use crate::schema;
+
+/// A session written by `import_session`
+pub struct ImportedSession {
+    pub id: String,
+    pub project_path: String,
+    pub session_file: PathBuf,
+    pub messages: usize,
+    pub summary: String,
+    pub attachments: usize,
+    /// Format version of the export it came from
+    pub format_version: String,
+}
 
-    let exported: ExportedSession =
-        serde_json::from_str(&json).context("Failed to parse .mcc file")?;
+/// Import the sessions in an .mcc file. The primary session goes to
+/// `target_project_path`; the rest of a bundle go to the matching place
+/// relative to it. Returns the primary session first.
+pub fn import_session(mcc_file: &Path, target_project_path: Option<String>) -> Result<Vec<ImportedSession>> {
+    let exported = preview_session(mcc_file)?;
 
     // Determine target project path
     let project_path = if let Some(path) = target_project_path {
@@ -30,32 +36,39 @@ pub fn import_session(mcc_file: &Path, target_project_path: Option<String>) -> R
             .to_string()
     };
 
-    // Create Claude projects directory structure
-    let home = std::env::var("HOME")?;
-    let encoded_path = project_path.replace("/", "-");
-    let session_dir = PathBuf::from(&home)
-        .join(".claude/projects")
-        .join(&encoded_path);
+    let primary_path = &exported.session.project_path;
+    let mut imported = Vec::new();
+    for session in exported.sessions() {
+        let target_path = map_project_path(&session.project_path, primary_path, &project_path);
+        let mut session = write_session(session, &target_path)?;
+        session.format_version = exported.version.clone();
+        imported.push(session);
+    }
+
+    // Register the primary session last so it's the one /resume offers first
+    for session in imported.iter().skip(1).chain(imported.first()) {
+        update_claude_config(&session.project_path, &session.id)?;
+    }
+
+    Ok(imported)
+}
 
+/// Write one session's messages as a JSONL transcript under `project_path`
+fn write_session(session: &SessionData, project_path: &str) -> Result<ImportedSession> {
+    // Create Claude projects directory structure
+    let session_dir = project_session_dir(project_path)?;
     fs::create_dir_all(&session_dir)?;
 
     // Generate new session file
-    let session_file = session_dir.join(format!("{}.jsonl", exported.session.id));
+    let session_file = session_dir.join(format!("{}.jsonl", session.id));
 
     // Write session messages as JSONL, rewriting paths
-    let original_path = &exported.session.project_path;
+    let original_path = &session.project_path;
     let mut output = String::new();
 
-    for message in &exported.session.messages {
+    for message in &session.messages {
         let mut msg = message.clone();
-
-        // Rewrite the cwd field in the data if it exists
-        if let Some(cwd) = msg.data.get("cwd").and_then(|v| v.as_str())
-            && cwd == original_path
-            && let Some(obj) = msg.data.as_object_mut()
-        {
-            obj.insert("cwd".to_string(), serde_json::json!(project_path));
-        }
+        rewrite_cwd(&mut msg.data, original_path, project_path);
 
         output.push_str(&serde_json::to_string(&msg)?);
         output.push('\n');
@@ -63,10 +76,125 @@ pub fn import_session(mcc_file: &Path, target_project_path: Option<String>) -> R
 
     fs::write(&session_file, output)?;
 
-    // Update ~/.claude.json to register the session
-    update_claude_config(&project_path, &exported.session.id)?;
+    // Context files are only installed on request, see context::install
+    let attachments: Vec<&Attachment> = session
+        .attachments
+        .iter()
+        .filter(|attachment| !attachment.kind.is_context())
+        .collect();
+    for attachment in &attachments {
+        write_attachment(attachment, &session_dir, original_path, project_path)?;
+    }
 
-    Ok(session_file)
+    Ok(ImportedSession {
+        id: session.id.clone(),
+        project_path: project_path.to_string(),
+        session_file,
+        messages: session.messages.len(),
+        summary: session.summary.clone(),
+        attachments: attachments.len(),
+        format_version: schema::CURRENT_VERSION.to_string(),
+    })
+}
+
+/// Put an attachment back where Claude Code looks for it. Transcripts get
+/// the same path rewriting as the session itself.
+fn write_attachment(attachment: &Attachment, session_dir: &Path, original_path: &str, project_path: &str) -> Result<()> {
+    // Archives come from other people; never write outside the target directory
+    let relative = Path::new(&attachment.path);
+    if !relative
+        .components()
+        .all(|component| matches!(component, std::path::Component::Normal(_)))
+    {
+        anyhow::bail!("Refusing to import attachment with unsafe path: {}", attachment.path);
+    }
+
+    let dest = match attachment.kind {
+        AttachmentKind::SessionFile => session_dir.join(relative),
+        AttachmentKind::Todo => {
+            let home = std::env::var("HOME")?;
+            PathBuf::from(home).join(".claude/todos").join(relative)
+        }
+        _ => return Ok(()),
+    };
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let content = if attachment.path.ends_with(".jsonl") {
+        relocate_lines(&attachment.content, original_path, project_path)?
+    } else {
+        attachment.content.clone()
+    };
+
+    fs::write(&dest, content).context(format!("Failed to write {}", dest.display()))?;
+    Ok(())
+}
+
+/// Where a bundled session from `original` belongs, given that the primary
+/// session's project `primary` is being imported into `target`. The primary
+/// project and anything inside it move with it; other projects are assumed
+/// to sit next to it, as services in neighbouring checkouts usually do.
+fn map_project_path(original: &str, primary: &str, target: &str) -> String {
+    if original == primary {
+        return target.to_string();
+    }
+    if let Some(rest) = original.strip_prefix(primary)
+        && rest.starts_with('/')
+    {
+        return format!("{}{}", target, rest);
+    }
+
+    let name = Path::new(original).file_name().and_then(|name| name.to_str());
+    match (Path::new(target).parent(), name) {
+        (Some(parent), Some(name)) => parent.join(name).to_string_lossy().to_string(),
+        _ => original.to_string(),
+    }
+}
+
+/// Directory Claude Code keeps a project's sessions in,
+/// ~/.claude/projects/<path with / replaced by ->
+pub fn project_session_dir(project_path: &str) -> Result<PathBuf> {
+    let home = std::env::var("HOME")?;
+    Ok(PathBuf::from(home)
+        .join(".claude/projects")
+        .join(project_path.replace("/", "-")))
+}
+
+/// Rewrite the cwd field of a message if it points at `original_path`
+fn rewrite_cwd(data: &mut serde_json::Value, original_path: &str, target_path: &str) {
+    if let Some(cwd) = data.get("cwd").and_then(|v| v.as_str())
+        && cwd == original_path
+        && let Some(obj) = data.as_object_mut()
+    {
+        obj.insert("cwd".to_string(), serde_json::json!(target_path));
+    }
+}
+
+/// Copy a raw .jsonl transcript, rewriting paths the same way as
+/// `import_session`. Lines without a matching cwd are copied unchanged.
+pub fn relocate_transcript(source: &Path, dest: &Path, original_path: &str, target_path: &str) -> Result<()> {
+    let content = fs::read_to_string(source).context("Failed to read session file")?;
+    let output = relocate_lines(&content, original_path, target_path)?;
+    fs::write(dest, output).context(format!("Failed to write {}", dest.display()))?;
+    Ok(())
+}
+
+/// Rewrite the cwd of each JSONL record that points at `original_path`
+fn relocate_lines(content: &str, original_path: &str, target_path: &str) -> Result<String> {
+    let mut output = String::new();
+
+    for line in content.lines() {
+        match serde_json::from_str::<serde_json::Value>(line) {
+            Ok(mut data) if data.get("cwd").and_then(|v| v.as_str()) == Some(original_path) => {
+                rewrite_cwd(&mut data, original_path, target_path);
+                output.push_str(&serde_json::to_string(&data)?);
+            }
+            _ => output.push_str(line),
+        }
+        output.push('\n');
+    }
+    Ok(output)
 }
 
 /// Update ~/.claude.json to set lastSessionId for the project
@@ -88,14 +216,14 @@ fn update_claude_config(project_path: &str, session_id: &str) -> Result<()> {
     Ok(())
 }
 
-/// Preview an .mcc file without importing
+/// Read an .mcc file without importing it, migrating older formats
 pub fn preview_session(mcc_file: &Path) -> Result<ExportedSession> {
-    let file = File::open(mcc_file).context("Failed to open .mcc file")?;
-    let mut decoder = GzDecoder::new(file);
-    let mut json = String::new();
-    decoder
-        .read_to_string(&mut json)
-        .context("Failed to decompress .mcc file")?;
-
-    serde_json::from_str(&json).context("Failed to parse .mcc file")
+    let value = container::read(mcc_file)?;
+    if let integrity::Verdict::Modified(problems) = integrity::verify(&value) {
+        anyhow::bail!(
+            "The export doesn't match the checksums it was exported with, so it was changed or damaged since: {}",
+            problems.join("; ")
+        );
+    }
+    schema::load(value)
 }
@@ -0,0 +1,222 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+use crate::manifest::sha256_hex;
+
+/// Top-level field the manifest is stored in; it isn't a section itself
+const INTEGRITY_FIELD: &str = "integrity";
+
+/// Checksums of an export's contents, so a file that was cut short or
+/// changed after it was exported can be told apart from a valid one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Integrity {
+    /// Messages across all sessions
+    pub messages: usize,
+    /// SHA-256 of each top-level field, with bundled sessions hashed one by
+    /// one as `bundled[0]`, `bundled[1]`, ...
+    pub sections: BTreeMap<String, String>,
+    /// SHA-256 over the section names and hashes
+    pub total: String,
+}
+
+/// The outcome of checking an export that could be read
+pub enum Verdict {
+    Intact(Integrity),
+    /// Exported by a version of mcc that didn't write a manifest
+    Unsealed,
+    /// Decompresses and parses, but doesn't match its manifest
+    Modified(Vec<String>),
+}
+
+/// Compute the manifest of an export. Sections are hashed as compact JSON
+/// with sorted keys, which is how serde_json writes a `Value`.
+pub fn compute(exported: &Value) -> Integrity {
+    let sections = sections(exported);
+    Integrity {
+        messages: count_messages(exported),
+        total: total(&sections),
+        sections,
+    }
+}
+
+fn sections(exported: &Value) -> BTreeMap<String, String> {
+    let mut sections = BTreeMap::new();
+    let Some(fields) = exported.as_object() else {
+        return sections;
+    };
+
+    for (name, value) in fields {
+        match (name.as_str(), value.as_array()) {
+            (INTEGRITY_FIELD, _) => {}
+            ("bundled", Some(bundled)) => {
+                for (index, session) in bundled.iter().enumerate() {
+                    sections.insert(format!("bundled[{}]", index), hash(session));
+                }
+            }
+            _ => {
+                sections.insert(name.clone(), hash(value));
+            }
+        }
+    }
+    sections
+}
+
+fn hash(value: &Value) -> String {
+    sha256_hex(value.to_string().as_bytes())
+}
+
+fn total(sections: &BTreeMap<String, String>) -> String {
+    let listing: String = sections
+        .iter()
+        .map(|(name, sha256)| format!("{} {}\n", name, sha256))
+        .collect();
+    sha256_hex(listing.as_bytes())
+}
+
+fn count_messages(exported: &Value) -> usize {
+    let count = |session: &Value| {
+        session
+            .get("messages")
+            .and_then(|messages| messages.as_array())
+            .map_or(0, |messages| messages.len())
+    };
+    let bundled = exported
+        .get("bundled")
+        .and_then(|bundled| bundled.as_array())
+        .map_or(0, |bundled| bundled.iter().map(count).sum());
+    exported.get("session").map_or(0, count) + bundled
+}
+
+/// Check an export against the manifest it carries
+pub fn verify(exported: &Value) -> Verdict {
+    let Some(manifest) = exported.get(INTEGRITY_FIELD) else {
+        return Verdict::Unsealed;
+    };
+    let Ok(expected) = serde_json::from_value::<Integrity>(manifest.clone()) else {
+        return Verdict::Modified(vec!["The integrity manifest is unreadable".to_string()]);
+    };
+
+    let mut problems = Vec::new();
+    if total(&expected.sections) != expected.total {
+        problems.push("The integrity manifest itself was changed".to_string());
+    }
+
+    let actual = compute(exported);
+    if actual.messages != expected.messages {
+        problems.push(format!(
+            "It has {} messages, but {} were exported",
+            actual.messages, expected.messages
+        ));
+    }
+    for (name, sha256) in &expected.sections {
+        match actual.sections.get(name) {
+            None => problems.push(format!("{} is missing", name)),
+            Some(actual) if actual != sha256 => problems.push(format!("{} doesn't match its checksum", name)),
+            Some(_) => {}
+        }
+    }
+    for name in actual.sections.keys().filter(|name| !expected.sections.contains_key(*name)) {
+        problems.push(format!("{} was added after it was exported", name));
+    }
+
+    if problems.is_empty() {
+        Verdict::Intact(expected)
+    } else {
+        Verdict::Modified(problems)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn sealed() -> Value {
+        let mut exported = json!({
+            "version": "1.2.0",
+            "exported_at": "2025-01-01T00:00:00Z",
+            "exported_by": "alice@laptop",
+            "session": {
+                "id": "primary",
+                "project_path": "/home/alice/api",
+                "summary": "Add pagination",
+                "messages": [{ "type": "user" }, { "type": "assistant" }]
+            },
+            "bundled": [
+                { "id": "web", "project_path": "/home/alice/web", "summary": "", "messages": [{ "type": "user" }] }
+            ]
+        });
+        exported[INTEGRITY_FIELD] = serde_json::to_value(compute(&exported)).unwrap();
+        exported
+    }
+
+    fn problems(exported: &Value) -> Vec<String> {
+        match verify(exported) {
+            Verdict::Modified(problems) => problems,
+            Verdict::Intact(_) => panic!("verified as intact"),
+            Verdict::Unsealed => panic!("verified as unsealed"),
+        }
+    }
+
+    #[test]
+    fn sealed_exports_verify() {
+        let exported = sealed();
+        let Verdict::Intact(integrity) = verify(&exported) else {
+            panic!("didn't verify");
+        };
+        assert_eq!(integrity.messages, 3);
+        assert!(integrity.sections.contains_key("bundled[0]"));
+        assert!(!integrity.sections.contains_key(INTEGRITY_FIELD));
+    }
+
+    #[test]
+    fn key_order_doesnt_matter() {
+        let exported = sealed();
+        let reordered: Value = serde_json::from_str(&serde_json::to_string_pretty(&exported).unwrap()).unwrap();
+        assert!(matches!(verify(&reordered), Verdict::Intact(_)));
+    }
+
+    #[test]
+    fn unsealed_exports_are_reported() {
+        let mut exported = sealed();
+        exported.as_object_mut().unwrap().remove(INTEGRITY_FIELD);
+        assert!(matches!(verify(&exported), Verdict::Unsealed));
+    }
+
+    #[test]
+    fn changed_sections_are_detected() {
+        let mut exported = sealed();
+        exported["session"]["summary"] = json!("Something else");
+        assert_eq!(problems(&exported), vec!["session doesn't match its checksum"]);
+
+        let mut exported = sealed();
+        exported["bundled"][0]["messages"] = json!([]);
+        assert_eq!(
+            problems(&exported),
+            vec!["It has 2 messages, but 3 were exported", "bundled[0] doesn't match its checksum"]
+        );
+    }
+
+    #[test]
+    fn added_and_removed_sections_are_detected() {
+        let mut exported = sealed();
+        exported["blobs"] = json!({});
+        assert_eq!(problems(&exported), vec!["blobs was added after it was exported"]);
+
+        let mut exported = sealed();
+        exported.as_object_mut().unwrap().remove("exported_by");
+        assert_eq!(problems(&exported), vec!["exported_by is missing"]);
+    }
+
+    #[test]
+    fn tampered_manifests_are_detected() {
+        let mut exported = sealed();
+        exported[INTEGRITY_FIELD]["sections"]["session"] = json!("0".repeat(64));
+        assert!(problems(&exported).contains(&"The integrity manifest itself was changed".to_string()));
+
+        let mut exported = sealed();
+        exported[INTEGRITY_FIELD] = json!("not a manifest");
+        assert_eq!(problems(&exported), vec!["The integrity manifest is unreadable"]);
+    }
+}
@@ -0,0 +1,430 @@
+use anyhow::{Context, Result};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek::scalar::Scalar;
+use sha2::{Digest, Sha256, Sha512};
+use std::io::{Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// UDP port senders announce themselves on
+pub const DISCOVERY_PORT: u16 = 7421;
+
+/// How often a sender repeats its announcement
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long a receiver looks for a sender before giving up
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long either side waits on a stalled connection
+const IO_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Sent by the receiver before its handshake message
+const HANDSHAKE_MAGIC: &[u8; 8] = b"MCCLAN01";
+
+/// Sessions are small; anything claiming to be bigger is not a sender we trust
+const MAX_PAYLOAD: u64 = 1 << 30;
+
+/// Characters used in codes, leaving out ones that are easy to misread
+const CODE_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+
+/// A transfer code such as `427-kx4p-m2ra`. The number is public and only
+/// used to find the sender on the network; the whole code is the password
+/// both sides prove they know.
+pub struct TransferCode {
+    code: String,
+    nameplate: u16,
+}
+
+impl TransferCode {
+    fn generate() -> Self {
+        let nameplate = 100 + (OsRng.next_u32() % 900) as u16;
+        Self {
+            code: format!("{}-{}-{}", nameplate, code_word(), code_word()),
+            nameplate,
+        }
+    }
+
+    pub fn parse(code: &str) -> Result<Self> {
+        let code = code.trim().to_lowercase();
+        let nameplate = code
+            .split('-')
+            .next()
+            .and_then(|nameplate| nameplate.parse().ok())
+            .filter(|_| code.split('-').count() == 3)
+            .context(format!("Invalid code: {} (expected something like 427-kx4p-m2ra)", code))?;
+        Ok(Self { code, nameplate })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.code
+    }
+}
+
+/// Four random characters of a code
+pub fn code_word() -> String {
+    (0..4)
+        .map(|_| CODE_ALPHABET[OsRng.next_u32() as usize % CODE_ALPHABET.len()] as char)
+        .collect()
+}
+
+/// A sender waiting for its receiver. Created by `offer`, so the code can be
+/// shown before `send` blocks.
+pub struct Offer {
+    pub code: TransferCode,
+    listener: TcpListener,
+}
+
+impl Offer {
+    /// Address receivers can use with `--from` when broadcasts are blocked
+    pub fn direct_address(&self) -> Result<SocketAddr> {
+        Ok(SocketAddr::new(local_ip(), self.listener.local_addr()?.port()))
+    }
+}
+
+/// Listen for a receiver on a random port with a fresh code
+pub fn offer() -> Result<Offer> {
+    let listener = TcpListener::bind("0.0.0.0:0").context("Failed to listen for receivers")?;
+    Ok(Offer {
+        code: TransferCode::generate(),
+        listener,
+    })
+}
+
+/// Announce the offer on the local network and send `file` to the first
+/// receiver that proves it knows the code. Returns the receiver's address.
+///
+/// Anyone who connects with the wrong code ends the offer, so the code
+/// can't be guessed by trying again and again.
+pub fn send(offer: Offer, file: &Path) -> Result<SocketAddr> {
+    let payload = std::fs::read(file).context(format!("Failed to read {}", file.display()))?;
+    let port = offer.listener.local_addr()?.port();
+    let announcement = format!("MCC-SEND 1 {} {}", offer.code.nameplate, port);
+
+    // Announce until the process ends; the thread dies with it
+    std::thread::spawn(move || {
+        let Ok(socket) = UdpSocket::bind("0.0.0.0:0") else {
+            return;
+        };
+        let _ = socket.set_broadcast(true);
+        loop {
+            // Loopback as well, for a receiver on the same machine when the
+            // network filters broadcasts
+            for target in [("255.255.255.255", DISCOVERY_PORT), ("127.0.0.1", DISCOVERY_PORT)] {
+                let _ = socket.send_to(announcement.as_bytes(), target);
+            }
+            std::thread::sleep(ANNOUNCE_INTERVAL);
+        }
+    });
+
+    for connection in offer.listener.incoming() {
+        let Ok(mut connection) = connection else {
+            continue;
+        };
+        let peer = connection.peer_addr()?;
+        connection.set_read_timeout(Some(IO_TIMEOUT))?;
+        connection.set_write_timeout(Some(IO_TIMEOUT))?;
+
+        match serve_receiver(&mut connection, &offer.code, &payload) {
+            Ok(()) => return Ok(peer),
+            Err(SendError::WrongCode) => anyhow::bail!(
+                "{} tried to receive with the wrong code. Stopped so the code can't be guessed; run mcc send again",
+                peer.ip()
+            ),
+            Err(SendError::Io(e)) => eprintln!("Warning: Transfer to {} failed: {}", peer, e),
+        }
+    }
+
+    anyhow::bail!("Stopped listening for receivers")
+}
+
+enum SendError {
+    WrongCode,
+    Io(anyhow::Error),
+}
+
+impl<E: Into<anyhow::Error>> From<E> for SendError {
+    fn from(e: E) -> Self {
+        SendError::Io(e.into())
+    }
+}
+
+fn serve_receiver(connection: &mut TcpStream, code: &TransferCode, payload: &[u8]) -> Result<(), SendError> {
+    let mut magic = [0u8; 8];
+    connection.read_exact(&mut magic)?;
+    if &magic != HANDSHAKE_MAGIC {
+        return Err(SendError::Io(anyhow::anyhow!("Not an mcc receiver")));
+    }
+
+    let handshake = Handshake::start(code.as_str(), Role::Sender);
+    let mut their_message = [0u8; 32];
+    connection.read_exact(&mut their_message)?;
+    connection.write_all(&handshake.message)?;
+    let key = handshake.finish(&their_message).map_err(SendError::Io)?;
+
+    let mut their_confirmation = [0u8; 32];
+    connection.read_exact(&mut their_confirmation)?;
+    if their_confirmation != confirmation(&key, Role::Receiver) {
+        return Err(SendError::WrongCode);
+    }
+    connection.write_all(&confirmation(&key, Role::Sender))?;
+
+    let cipher = ChaCha20Poly1305::new(&key);
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, payload)
+        .map_err(|_| anyhow::anyhow!("Failed to encrypt session"))?;
+
+    connection.write_all(&nonce)?;
+    connection.write_all(&(ciphertext.len() as u64).to_be_bytes())?;
+    connection.write_all(&ciphertext)?;
+
+    // Wait for the receiver to acknowledge, so we only report success once
+    // it actually has the file
+    let mut ack = [0u8; 1];
+    connection.read_exact(&mut ack)?;
+    Ok(())
+}
+
+/// Find the sender for `code` on the local network (or connect to `from`
+/// directly) and save what it sends to `output`
+pub fn receive(code: &TransferCode, from: Option<SocketAddr>, output: &Path) -> Result<SocketAddr> {
+    let sender = match from {
+        Some(address) => address,
+        None => discover(code.nameplate)?,
+    };
+
+    let mut connection = TcpStream::connect_timeout(&sender, IO_TIMEOUT)
+        .context(format!("Failed to connect to sender at {}", sender))?;
+    connection.set_read_timeout(Some(IO_TIMEOUT))?;
+    connection.set_write_timeout(Some(IO_TIMEOUT))?;
+
+    let handshake = Handshake::start(code.as_str(), Role::Receiver);
+    connection.write_all(HANDSHAKE_MAGIC)?;
+    connection.write_all(&handshake.message)?;
+    let mut their_message = [0u8; 32];
+    connection
+        .read_exact(&mut their_message)
+        .context("Sender closed the connection")?;
+    let key = handshake.finish(&their_message)?;
+
+    connection.write_all(&confirmation(&key, Role::Receiver))?;
+    let mut their_confirmation = [0u8; 32];
+    if connection.read_exact(&mut their_confirmation).is_err()
+        || their_confirmation != confirmation(&key, Role::Sender)
+    {
+        anyhow::bail!("Wrong code. The sender has stopped; ask them to run mcc send again");
+    }
+
+    let mut nonce = [0u8; 12];
+    connection.read_exact(&mut nonce)?;
+    let mut len = [0u8; 8];
+    connection.read_exact(&mut len)?;
+    let len = u64::from_be_bytes(len);
+    if len > MAX_PAYLOAD {
+        anyhow::bail!("Sender offered {} bytes, which is too large for a session", len);
+    }
+
+    let mut ciphertext = vec![0u8; len as usize];
+    connection
+        .read_exact(&mut ciphertext)
+        .context("Transfer was interrupted")?;
+    let payload = ChaCha20Poly1305::new(&key)
+        .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+        .map_err(|_| anyhow::anyhow!("Received data failed its integrity check"))?;
+
+    std::fs::write(output, payload).context(format!("Failed to write {}", output.display()))?;
+    connection.write_all(&[1])?;
+    Ok(sender)
+}
+
+/// Wait for a sender announcing `nameplate`
+fn discover(nameplate: u16) -> Result<SocketAddr> {
+    let socket = UdpSocket::bind(("0.0.0.0", DISCOVERY_PORT)).context(format!(
+        "Failed to listen on UDP port {} (is another mcc receive running?)",
+        DISCOVERY_PORT
+    ))?;
+
+    let deadline = Instant::now() + DISCOVERY_TIMEOUT;
+    let mut buffer = [0u8; 128];
+    while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        socket.set_read_timeout(Some(remaining.max(Duration::from_millis(1))))?;
+        let Ok((len, from)) = socket.recv_from(&mut buffer) else {
+            break;
+        };
+
+        let announcement = String::from_utf8_lossy(&buffer[..len]);
+        let fields: Vec<&str> = announcement.split_whitespace().collect();
+        if let ["MCC-SEND", "1", announced, port] = fields.as_slice()
+            && announced.parse() == Ok(nameplate)
+            && let Ok(port) = port.parse()
+        {
+            return Ok(SocketAddr::new(from.ip(), port));
+        }
+    }
+
+    anyhow::bail!(
+        "No sender for code {} found on the network. Is mcc send still running? If broadcasts are blocked, use --from <address> as printed by the sender",
+        nameplate
+    )
+}
+
+/// The address other machines most likely reach us on
+fn local_ip() -> IpAddr {
+    // Connecting a UDP socket sends nothing; it just picks the interface
+    UdpSocket::bind("0.0.0.0:0")
+        .and_then(|socket| {
+            socket.connect("192.0.2.1:9")?;
+            socket.local_addr()
+        })
+        .map(|address| address.ip())
+        .unwrap_or(IpAddr::from([127, 0, 0, 1]))
+}
+
+#[derive(Clone, Copy)]
+pub enum Role {
+    Sender,
+    Receiver,
+}
+
+/// One side of a SPAKE2 exchange over Ristretto255. Each side blinds a
+/// random point with the code; only someone who knows the code can unblind
+/// the other's message, and an eavesdropper learns nothing they could use
+/// to guess the code offline. Also used by live broadcasts, with the relay
+/// as the sender and the follower as the receiver.
+pub struct Handshake {
+    role: Role,
+    secret: Scalar,
+    password: Scalar,
+    pub message: [u8; 32],
+}
+
+impl Handshake {
+    pub fn start(code: &str, role: Role) -> Self {
+        let password = Scalar::hash_from_bytes::<Sha512>(code.as_bytes());
+        let secret = Scalar::random(&mut OsRng);
+        let blinded = RISTRETTO_BASEPOINT_POINT * secret + blinding_point(role) * password;
+        Self {
+            role,
+            secret,
+            password,
+            message: blinded.compress().to_bytes(),
+        }
+    }
+
+    /// Derive the session key from the other side's message
+    pub fn finish(self, their_message: &[u8; 32]) -> Result<Key> {
+        let their_role = match self.role {
+            Role::Sender => Role::Receiver,
+            Role::Receiver => Role::Sender,
+        };
+        let their_point = CompressedRistretto(*their_message)
+            .decompress()
+            .context("Invalid handshake from peer")?;
+        let shared = (their_point - blinding_point(their_role) * self.password) * self.secret;
+
+        let (sender_message, receiver_message) = match self.role {
+            Role::Sender => (self.message, *their_message),
+            Role::Receiver => (*their_message, self.message),
+        };
+
+        let mut hasher = Sha256::new();
+        hasher.update(b"mcc lan transfer v1");
+        hasher.update(sender_message);
+        hasher.update(receiver_message);
+        hasher.update(shared.compress().as_bytes());
+        hasher.update(self.password.as_bytes());
+        Ok(hasher.finalize())
+    }
+}
+
+/// The fixed points M and N the two roles blind their messages with
+fn blinding_point(role: Role) -> RistrettoPoint {
+    let label: &[u8] = match role {
+        Role::Sender => b"mcc lan transfer M",
+        Role::Receiver => b"mcc lan transfer N",
+    };
+    RistrettoPoint::hash_from_bytes::<Sha512>(label)
+}
+
+/// Proof that a side derived the same key, without revealing it
+pub fn confirmation(key: &Key, role: Role) -> [u8; 32] {
+    let label: &[u8] = match role {
+        Role::Sender => b"sender",
+        Role::Receiver => b"receiver",
+    };
+    let mut hasher = Sha256::new();
+    hasher.update(key);
+    hasher.update(label);
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Run both sides of the handshake and return the keys they derive
+    fn agree(sender_code: &str, receiver_code: &str) -> (Key, Key) {
+        let sender = Handshake::start(sender_code, Role::Sender);
+        let receiver = Handshake::start(receiver_code, Role::Receiver);
+        let (sender_message, receiver_message) = (sender.message, receiver.message);
+        (
+            sender.finish(&receiver_message).unwrap(),
+            receiver.finish(&sender_message).unwrap(),
+        )
+    }
+
+    #[test]
+    fn same_code_agrees_on_a_key() {
+        let (sender_key, receiver_key) = agree("427-kx4p-m2ra", "427-kx4p-m2ra");
+        assert_eq!(sender_key, receiver_key);
+        assert_eq!(
+            confirmation(&sender_key, Role::Receiver),
+            confirmation(&receiver_key, Role::Receiver)
+        );
+        assert_ne!(confirmation(&sender_key, Role::Sender), confirmation(&sender_key, Role::Receiver));
+    }
+
+    #[test]
+    fn each_exchange_has_its_own_key() {
+        let (first, _) = agree("427-kx4p-m2ra", "427-kx4p-m2ra");
+        let (second, _) = agree("427-kx4p-m2ra", "427-kx4p-m2ra");
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn wrong_code_fails_confirmation() {
+        let (sender_key, receiver_key) = agree("427-kx4p-m2ra", "427-kx4p-m2rb");
+        assert_ne!(sender_key, receiver_key);
+        assert_ne!(
+            confirmation(&receiver_key, Role::Receiver),
+            confirmation(&sender_key, Role::Receiver)
+        );
+    }
+
+    #[test]
+    fn invalid_handshake_message_is_rejected() {
+        let handshake = Handshake::start("427-kx4p-m2ra", Role::Sender);
+        assert!(handshake.finish(&[0xff; 32]).is_err());
+    }
+
+    #[test]
+    fn parses_codes() {
+        let code = TransferCode::parse(" 427-KX4P-m2ra ").unwrap();
+        assert_eq!(code.as_str(), "427-kx4p-m2ra");
+        assert_eq!(code.nameplate, 427);
+        assert!(TransferCode::parse("kx4p-m2ra").is_err());
+        assert!(TransferCode::parse("427-kx4p").is_err());
+    }
+
+    #[test]
+    fn generated_codes_parse() {
+        let generated = TransferCode::generate();
+        let parsed = TransferCode::parse(generated.as_str()).unwrap();
+        assert_eq!(parsed.nameplate, generated.nameplate);
+    }
+}
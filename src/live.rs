@@ -0,0 +1,524 @@
+use anyhow::{Context, Result};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::lan::{Handshake, Role, code_word, confirmation};
+
+/// Port the relay listens on unless `--port` says otherwise
+pub const DEFAULT_RELAY_PORT: u16 = 7420;
+
+/// How often a broadcast checks the transcript for new records
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Wrong codes a broadcast tolerates before it refuses new followers, so
+/// its code can't be guessed by trying again and again
+const MAX_WRONG_CODES: u32 = 10;
+
+/// Records are at most a few MB; anything claiming to be bigger is not a
+/// relay we trust
+const MAX_FRAME: u32 = 256 * 1024 * 1024;
+
+/// Last record sent to a follower; transcript records are JSON, so it can't
+/// be mistaken for one
+const END: &str = "END";
+
+/// A session being broadcast: the code followers must know, every record so
+/// far, so late followers catch up, and the followers to fan new records
+/// out to
+#[derive(Default)]
+struct Stream {
+    code: String,
+    wrong_codes: u32,
+    backlog: Vec<String>,
+    followers: Vec<Sender<String>>,
+}
+
+type Streams = Arc<Mutex<HashMap<String, Stream>>>;
+
+/// A fresh code for a broadcast, such as `kx4p-m2ra`
+pub fn generate_code() -> String {
+    format!("{}-{}", code_word(), code_word())
+}
+
+/// Where the token for publishing to the relay on `port` is kept, so
+/// broadcasts on this machine can share a relay
+fn token_path(port: u16) -> Result<PathBuf> {
+    let home = std::env::var("HOME")?;
+    Ok(PathBuf::from(home).join(format!(".mcc/live/relay-{}.token", port)))
+}
+
+/// Token of the relay this machine runs on `port`, if there is one
+pub fn local_relay_token(port: u16) -> Option<String> {
+    let token = fs::read_to_string(token_path(port).ok()?).ok()?;
+    Some(token.trim().to_string())
+}
+
+/// Start a relay on `addr` in the background. Returns false if something
+/// is already listening there, which is fine: broadcasts share one relay.
+///
+/// The protocol is line-based until a follower is let in. A publisher sends
+/// `PUBLISH <id> <relay-token> <code>` followed by one JSONL record per line.
+/// A follower sends `FOLLOW <id-prefix>` (or just `FOLLOW` if only one
+/// session is live) and gets `OK`, then proves it knows the broadcast's code
+/// with the same SPAKE2 handshake as `mcc send`. After that it receives the
+/// session ID, the records so far and new records as they arrive, ending
+/// with `END`, each encrypted with the handshake's key. Errors are reported
+/// as `ERR <message>`.
+pub fn spawn_relay(addr: &str) -> Result<bool> {
+    let listener = match TcpListener::bind(addr) {
+        Ok(listener) => listener,
+        Err(e) if e.kind() == ErrorKind::AddrInUse => return Ok(false),
+        Err(e) => return Err(e).context(format!("Failed to listen on {}", addr)),
+    };
+
+    let mut bytes = [0u8; 24];
+    OsRng.fill_bytes(&mut bytes);
+    let token: Arc<str> = bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>().into();
+    write_token(&token_path(listener.local_addr()?.port())?, &token)?;
+
+    let streams = Streams::default();
+    std::thread::spawn(move || {
+        for connection in listener.incoming().flatten() {
+            let streams = streams.clone();
+            let token = token.clone();
+            std::thread::spawn(move || {
+                if let Err(e) = handle_connection(connection, &streams, &token) {
+                    eprintln!("Warning: Relay connection failed: {}", e);
+                }
+            });
+        }
+    });
+
+    Ok(true)
+}
+
+/// Write the relay token readable only by its owner
+fn write_token(path: &Path, token: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        if path.exists() {
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        }
+    }
+    options
+        .open(path)?
+        .write_all(token.as_bytes())
+        .context(format!("Failed to write {}", path.display()))
+}
+
+fn handle_connection(connection: TcpStream, streams: &Streams, token: &str) -> Result<()> {
+    let mut reader = BufReader::new(connection.try_clone()?);
+    let mut writer = connection;
+
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    let fields: Vec<&str> = request.split_whitespace().collect();
+
+    match fields.as_slice() {
+        ["PUBLISH", id, given, code] if *given == token => publish(reader, writer, streams, id, code),
+        ["PUBLISH", ..] => {
+            writeln!(writer, "ERR Wrong relay token")?;
+            Ok(())
+        }
+        ["FOLLOW"] => follow_stream(reader, writer, streams, ""),
+        ["FOLLOW", query] => follow_stream(reader, writer, streams, query),
+        _ => {
+            writeln!(writer, "ERR Unknown request: {}", request.trim_end())?;
+            Ok(())
+        }
+    }
+}
+
+fn publish(reader: BufReader<TcpStream>, mut writer: TcpStream, streams: &Streams, id: &str, code: &str) -> Result<()> {
+    {
+        let mut streams = streams.lock().unwrap_or_else(|e| e.into_inner());
+        if streams.contains_key(id) {
+            writeln!(writer, "ERR Session {} is already being broadcast", id)?;
+            return Ok(());
+        }
+        streams.insert(
+            id.to_string(),
+            Stream {
+                code: code.to_string(),
+                ..Stream::default()
+            },
+        );
+    }
+    writeln!(writer, "OK {}", id)?;
+
+    for line in reader.lines() {
+        let Ok(line) = line else {
+            break;
+        };
+
+        let mut streams = streams.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(stream) = streams.get_mut(id) {
+            stream.followers.retain(|follower| follower.send(line.clone()).is_ok());
+            stream.backlog.push(line);
+        }
+    }
+
+    // Dropping the senders tells every follower the broadcast ended
+    streams.lock().unwrap_or_else(|e| e.into_inner()).remove(id);
+    Ok(())
+}
+
+fn follow_stream(mut reader: BufReader<TcpStream>, mut writer: TcpStream, streams: &Streams, query: &str) -> Result<()> {
+    let (id, code) = {
+        let streams = streams.lock().unwrap_or_else(|e| e.into_inner());
+        let matches: Vec<(&String, &Stream)> = streams.iter().filter(|(id, _)| id.starts_with(query)).collect();
+        match matches.as_slice() {
+            [(_, stream)] if stream.wrong_codes >= MAX_WRONG_CODES => {
+                writeln!(writer, "ERR Too many wrong codes were tried for this session; ask for a new broadcast")?;
+                return Ok(());
+            }
+            [(id, stream)] => ((*id).clone(), stream.code.clone()),
+            [] => {
+                writeln!(writer, "ERR No live session matches {}", query)?;
+                return Ok(());
+            }
+            _ => {
+                let ids: Vec<&str> = matches.iter().map(|(id, _)| id.as_str()).collect();
+                writeln!(writer, "ERR {} is ambiguous, live sessions: {}", query, ids.join(", "))?;
+                return Ok(());
+            }
+        }
+    };
+    writeln!(writer, "OK")?;
+
+    // Nothing about the session is sent until the follower proves it knows
+    // the code
+    let handshake = Handshake::start(&code, Role::Sender);
+    let mut their_message = [0u8; 32];
+    reader
+        .read_exact(&mut their_message)
+        .context("Follower left during the handshake")?;
+    writer.write_all(&handshake.message)?;
+    let key = handshake.finish(&their_message)?;
+
+    let mut their_confirmation = [0u8; 32];
+    reader
+        .read_exact(&mut their_confirmation)
+        .context("Follower left during the handshake")?;
+    if their_confirmation != confirmation(&key, Role::Receiver) {
+        if let Some(stream) = streams.lock().unwrap_or_else(|e| e.into_inner()).get_mut(&id) {
+            stream.wrong_codes += 1;
+        }
+        anyhow::bail!("{} tried to follow {} with the wrong code", writer.peer_addr()?.ip(), id);
+    }
+    writer.write_all(&confirmation(&key, Role::Sender))?;
+
+    let (rx, backlog) = {
+        let (tx, rx) = mpsc::channel();
+        let mut streams = streams.lock().unwrap_or_else(|e| e.into_inner());
+        // The broadcast may have ended during the handshake, in which case
+        // the sender is dropped here and only END is sent
+        let backlog = match streams.get_mut(&id) {
+            Some(stream) => {
+                stream.followers.push(tx);
+                stream.backlog.clone()
+            }
+            None => Vec::new(),
+        };
+        (rx, backlog)
+    };
+
+    let mut channel = Channel::new(&key);
+    channel.send(&mut writer, &id)?;
+    for line in backlog {
+        channel.send(&mut writer, &line)?;
+    }
+    for line in rx {
+        channel.send(&mut writer, &line)?;
+    }
+    channel.send(&mut writer, END)?;
+    Ok(())
+}
+
+/// Records sent to a follower, each encrypted as its own length-prefixed
+/// frame. Nonces count up, so both ends stay in step without sending them.
+struct Channel {
+    cipher: ChaCha20Poly1305,
+    counter: u64,
+}
+
+impl Channel {
+    fn new(key: &Key) -> Self {
+        Self {
+            cipher: ChaCha20Poly1305::new(key),
+            counter: 0,
+        }
+    }
+
+    fn next_nonce(&mut self) -> Nonce {
+        let mut nonce = [0u8; 12];
+        nonce[4..].copy_from_slice(&self.counter.to_be_bytes());
+        self.counter += 1;
+        nonce.into()
+    }
+
+    fn send(&mut self, writer: &mut impl Write, record: &str) -> Result<()> {
+        let nonce = self.next_nonce();
+        let frame = self
+            .cipher
+            .encrypt(&nonce, record.as_bytes())
+            .map_err(|_| anyhow::anyhow!("Failed to encrypt record"))?;
+        writer.write_all(&(frame.len() as u32).to_be_bytes())?;
+        writer.write_all(&frame)?;
+        Ok(())
+    }
+
+    /// The next record, or None if the relay closed the connection between
+    /// records
+    fn receive(&mut self, reader: &mut impl Read) -> Result<Option<String>> {
+        let mut len = [0u8; 4];
+        match reader.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        let len = u32::from_be_bytes(len);
+        if len > MAX_FRAME {
+            anyhow::bail!("Relay sent a {} byte record, which is too large", len);
+        }
+
+        let mut frame = vec![0u8; len as usize];
+        reader.read_exact(&mut frame).context("Relay closed the connection")?;
+        let nonce = self.next_nonce();
+        let record = self
+            .cipher
+            .decrypt(&nonce, frame.as_slice())
+            .map_err(|_| anyhow::anyhow!("A record from the relay failed its integrity check"))?;
+        Ok(Some(String::from_utf8_lossy(&record).into_owned()))
+    }
+}
+
+/// Stream a session transcript to the relay at `relay`, sending what is
+/// already there and then each record Claude Code appends. Followers need
+/// `code`; `token` lets us publish to the relay. Runs until the process is
+/// stopped.
+pub fn broadcast(relay: &str, token: &str, session_id: &str, session_file: &Path, code: &str) -> Result<()> {
+    let connection = TcpStream::connect(relay).context(format!("Failed to connect to relay at {}", relay))?;
+    let mut reader = BufReader::new(connection.try_clone()?);
+    let mut writer = connection;
+
+    writeln!(writer, "PUBLISH {} {} {}", session_id, token, code)?;
+    let mut response = String::new();
+    reader.read_line(&mut response)?;
+    if let Some(error) = response.trim_end().strip_prefix("ERR ") {
+        anyhow::bail!("{}", error);
+    }
+
+    let mut offset = 0;
+    let mut partial = Vec::new();
+    loop {
+        let mut file = File::open(session_file).context("Failed to open session file")?;
+        let len = file.metadata()?.len();
+
+        // The transcript only grows; if it shrank it was replaced, so start over
+        if len < offset {
+            offset = 0;
+            partial.clear();
+        }
+
+        if len > offset {
+            file.seek(SeekFrom::Start(offset))?;
+            let mut chunk = Vec::new();
+            file.read_to_end(&mut chunk)?;
+            offset += chunk.len() as u64;
+            partial.extend_from_slice(&chunk);
+
+            for line in complete_lines(&mut partial) {
+                writeln!(writer, "{}", line).context("Lost connection to relay")?;
+            }
+        }
+
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Take the complete, non-empty records out of `partial`, leaving a
+/// half-written line for later. Lines are only decoded once complete, since
+/// a read can end part-way through a multibyte character.
+fn complete_lines(partial: &mut Vec<u8>) -> Vec<String> {
+    let Some(end) = partial.iter().rposition(|byte| *byte == b'\n') else {
+        return Vec::new();
+    };
+    let complete: Vec<u8> = partial.drain(..=end).collect();
+    complete
+        .split(|byte| *byte == b'\n')
+        .map(|line| String::from_utf8_lossy(line).trim_end().to_string())
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+/// Follow a live session on the relay at `relay`, printing each record as
+/// it arrives. Returns the session ID once the broadcast ends.
+pub fn follow(relay: &str, query: &str, code: &str) -> Result<String> {
+    let connection = TcpStream::connect(relay).context(format!("Failed to connect to relay at {}", relay))?;
+    let mut reader = BufReader::new(connection.try_clone()?);
+    let mut writer = connection;
+    writeln!(writer, "FOLLOW {}", query)?;
+
+    let mut response = String::new();
+    if reader.read_line(&mut response)? == 0 {
+        anyhow::bail!("Relay closed the connection");
+    }
+    if let Some(error) = response.trim_end().strip_prefix("ERR ") {
+        anyhow::bail!("{}", error);
+    }
+
+    let handshake = Handshake::start(code, Role::Receiver);
+    writer.write_all(&handshake.message)?;
+    let mut their_message = [0u8; 32];
+    reader
+        .read_exact(&mut their_message)
+        .context("Relay closed the connection")?;
+    let key = handshake.finish(&their_message)?;
+
+    writer.write_all(&confirmation(&key, Role::Receiver))?;
+    let mut their_confirmation = [0u8; 32];
+    if reader.read_exact(&mut their_confirmation).is_err() || their_confirmation != confirmation(&key, Role::Sender) {
+        anyhow::bail!("Wrong code for this broadcast");
+    }
+
+    let mut channel = Channel::new(&key);
+    let id = channel.receive(&mut reader)?.context("Relay closed the connection")?;
+    println!("Following session {} (Ctrl-C to stop)", id);
+
+    while let Some(line) = channel.receive(&mut reader)? {
+        if line == END {
+            break;
+        }
+
+        if let Ok(record) = serde_json::from_str::<serde_json::Value>(&line)
+            && let Some(rendered) = render_record(&record)
+        {
+            println!("{}", rendered);
+        }
+    }
+
+    Ok(id)
+}
+
+/// A transcript record as readable text, or None for bookkeeping records
+pub fn render_record(record: &serde_json::Value) -> Option<String> {
+    let content = record.get("message").and_then(|message| message.get("content"))?;
+
+    match record.get("type").and_then(|t| t.as_str())? {
+        "user" => {
+            if let Some(text) = content.as_str() {
+                return Some(format!("\n> {}", text));
+            }
+
+            // Tool results come back to the model as user messages
+            let results: Vec<String> = content
+                .as_array()?
+                .iter()
+                .filter(|block| block.get("type").and_then(|t| t.as_str()) == Some("tool_result"))
+                .map(|block| format!("  ↳ {}", first_line(&block_text(block.get("content")), 100)))
+                .collect();
+            (!results.is_empty()).then(|| results.join("\n"))
+        }
+        "assistant" => {
+            let parts: Vec<String> = content
+                .as_array()?
+                .iter()
+                .filter_map(|block| match block.get("type").and_then(|t| t.as_str())? {
+                    "text" => block.get("text").and_then(|t| t.as_str()).map(|t| t.to_string()),
+                    "tool_use" => Some(format!(
+                        "  ⚙ {} {}",
+                        block.get("name").and_then(|n| n.as_str()).unwrap_or("tool"),
+                        tool_summary(block.get("input"))
+                    )),
+                    _ => None,
+                })
+                .collect();
+            (!parts.is_empty()).then(|| parts.join("\n"))
+        }
+        _ => None,
+    }
+}
+
+/// The most telling argument of a tool call, e.g. the command or file path
+fn tool_summary(input: Option<&serde_json::Value>) -> String {
+    let Some(input) = input else {
+        return String::new();
+    };
+
+    for field in ["command", "file_path", "path", "pattern", "url", "description"] {
+        if let Some(value) = input.get(field).and_then(|v| v.as_str()) {
+            return first_line(value, 100);
+        }
+    }
+    first_line(&input.to_string(), 100)
+}
+
+/// Text of a tool result, which is either a string or a list of blocks
+fn block_text(content: Option<&serde_json::Value>) -> String {
+    match content {
+        Some(serde_json::Value::String(text)) => text.clone(),
+        Some(serde_json::Value::Array(blocks)) => blocks
+            .iter()
+            .filter_map(|block| block.get("text").and_then(|t| t.as_str()))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+fn first_line(text: &str, max_chars: usize) -> String {
+    let line = text.lines().next().unwrap_or_default();
+    let mut shortened: String = line.chars().take(max_chars).collect();
+    if line.chars().count() > max_chars || text.lines().nth(1).is_some() {
+        shortened.push('…');
+    }
+    shortened
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_half_written_lines() {
+        let mut partial = b"{\"a\":1}\n\n{\"b\":".to_vec();
+        assert_eq!(complete_lines(&mut partial), vec!["{\"a\":1}"]);
+        assert_eq!(partial, b"{\"b\":");
+
+        partial.extend_from_slice(b"2}\r\n");
+        assert_eq!(complete_lines(&mut partial), vec!["{\"b\":2}"]);
+        assert!(partial.is_empty());
+        assert!(complete_lines(&mut partial).is_empty());
+    }
+
+    #[test]
+    fn multibyte_characters_split_across_reads_survive() {
+        let record = "{\"text\":\"héllo 日本\"}\n".as_bytes();
+        let mut partial = Vec::new();
+        let mut lines = Vec::new();
+        // Feed one byte at a time, as a slow writer might
+        for byte in record {
+            partial.push(*byte);
+            lines.extend(complete_lines(&mut partial));
+        }
+        assert_eq!(lines, vec!["{\"text\":\"héllo 日本\"}"]);
+    }
+}
@@ -1,7 +1,26 @@
+mod backend;
 mod cloud;
+mod codec;
+mod container;
+mod context;
 mod export;
+mod files;
+mod filter;
+mod git;
 mod import;
+mod integrity;
+mod lan;
+mod live;
+mod manifest;
+mod progress;
+mod prune;
+mod remote;
+mod schema;
+mod serve;
 mod session;
+mod shrink;
+mod transfer;
+mod watch;
 
 use anyhow::{Context, Result};
 use session::find_all_sessions;
@@ -16,32 +35,94 @@ fn main() -> Result<()> {
     }
 
     match args[1].as_str() {
-        "export" => cmd_export(),
-        "import" => cmd_import(),
+        "export" => cmd_export(&args),
+        "import" => cmd_import(&args),
         "preview" => cmd_preview(&args),
         "config" => cmd_config(&args),
         "share" => cmd_share(&args),
         "fetch" => cmd_fetch(&args),
-        "sync" => cmd_sync(),
-        "restore" => cmd_restore(),
+        "sync" => cmd_sync(&args),
+        "restore" => cmd_restore(&args),
+        "remote" => cmd_remote(&args),
+        "prune" => cmd_prune(&args),
+        "watch" => cmd_watch(&args),
+        "broadcast" => cmd_broadcast(&args),
+        "follow" => cmd_follow(&args),
+        "serve" => cmd_serve(&args),
+        "hooks" => cmd_hooks(&args),
+        "blame" => cmd_blame(&args),
+        "send" => cmd_send(&args),
+        "receive" => cmd_receive(&args),
+        "extract-files" => cmd_extract_files(&args),
+        "schema" => cmd_schema(),
+        "verify" => cmd_verify(&args),
+        "dict" => cmd_dict(&args),
         "help" | "-h" | "--help" => cmd_help(),
         _ => cmd_unknown(&args[1]),
     }
 }
 
-fn cmd_export() -> Result<()> {
+fn cmd_export(args: &[String]) -> Result<()> {
     let current_dir = std::env::current_dir()?;
     let current_path = current_dir.to_str().context("Invalid current directory path")?;
 
     let sessions = find_all_sessions()?;
+    let options = match export::ExportOptions::from_args(args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("✗ {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if let Some(ids) = flag_value(args, "--sessions") {
+        return match select_sessions(&sessions, ids) {
+            Ok(selected) => {
+                // Sessions named by ID may belong to another project
+                let project_dir = PathBuf::from(&selected[0].project_path);
+                export_session_success(&selected, &current_dir, &project_dir, &options)
+            }
+            Err(e) => {
+                eprintln!("✗ {}", e);
+                std::process::exit(1);
+            }
+        };
+    }
 
     // Try to find a matching session with smart path matching
-    let current_session = find_matching_session(&sessions, current_path);
+    let Some(session) = find_matching_session(&sessions, current_path) else {
+        return export_session_not_found(current_path);
+    };
 
-    match current_session {
-        Some(session) => export_session_success(session, &current_dir),
-        None => export_session_not_found(current_path),
+    if args.iter().any(|arg| arg == "--all-for-project") {
+        // Newest first, so the primary session is the latest one
+        let project: Vec<&session::Session> = sessions
+            .iter()
+            .filter(|other| other.project_path == session.project_path)
+            .collect();
+        return export_session_success(&project, &current_dir, &current_dir, &options);
     }
+
+    export_session_success(&[session], &current_dir, &current_dir, &options)
+}
+
+/// Sessions named by a comma-separated list of IDs or ID prefixes
+fn select_sessions<'a>(sessions: &'a [session::Session], ids: &str) -> Result<Vec<&'a session::Session>> {
+    let mut selected: Vec<&session::Session> = Vec::new();
+    for id in ids.split(',').map(|id| id.trim()).filter(|id| !id.is_empty()) {
+        let matches: Vec<&session::Session> = sessions.iter().filter(|session| session.id.starts_with(id)).collect();
+        match matches.as_slice() {
+            [session] if !selected.iter().any(|s| s.id == session.id) => selected.push(session),
+            [_] => {}
+            [] => anyhow::bail!("No session matches {}", id),
+            _ => anyhow::bail!("{} matches {} sessions; use a longer prefix", id, matches.len()),
+        }
+    }
+
+    if selected.is_empty() {
+        anyhow::bail!("--sessions expects a comma-separated list of session IDs");
+    }
+    Ok(selected)
 }
 
 /// Find a matching session using smart path matching:
@@ -63,13 +144,10 @@ fn find_matching_session<'a>(sessions: &'a [session::Session], current_path: &st
         .file_name()
         .and_then(|s| s.to_str());
 
-    if current_basename.is_none() {
-        return None;
-    }
-    let current_basename = current_basename.unwrap();
+    let current_basename = current_basename?;
 
     // Get current git remote URL for better matching
-    let current_git_remote = get_git_remote_url().ok();
+    let current_git_remote = git::remote_url().ok();
 
     // Filter sessions by matching basename
     let basename_matches: Vec<&session::Session> = sessions.iter()
@@ -89,8 +167,8 @@ fn find_matching_session<'a>(sessions: &'a [session::Session], current_path: &st
         let git_match = basename_matches.iter()
             .filter(|s| {
                 // Try to get git remote from session's original path
-                if let Ok(session_remote) = get_git_remote_url_for_path(&s.project_path) {
-                    normalize_git_url(&session_remote) == normalize_git_url(current_remote)
+                if let Ok(session_remote) = git::remote_url_for_path(&s.project_path) {
+                    git::normalize_url(&session_remote) == git::normalize_url(current_remote)
                 } else {
                     false
                 }
@@ -108,50 +186,140 @@ fn find_matching_session<'a>(sessions: &'a [session::Session], current_path: &st
         .copied()
 }
 
-/// Get the git remote URL for the current directory
-fn get_git_remote_url() -> Result<String> {
-    let output = std::process::Command::new("git")
-        .args(["config", "--get", "remote.origin.url"])
-        .output()?;
+/// `project_dir` is the primary session's working tree, where its git state
+/// and context files are captured from
+fn export_session_success(
+    sessions: &[&session::Session],
+    current_dir: &std::path::Path,
+    project_dir: &std::path::Path,
+    options: &export::ExportOptions,
+) -> Result<()> {
+    let file_name = options.file_name();
+    let output_path = current_dir.join(file_name);
+    let dictionary = match options.dictionary.as_deref().map(container::Dictionary::find).transpose() {
+        Ok(dictionary) => dictionary,
+        Err(e) => {
+            eprintln!("✗ {}", e);
+            std::process::exit(1);
+        }
+    };
+    let mut exported = export::ExportedSession::from_sessions(sessions)?;
+    exported.session.git = git::GitState::capture(project_dir, options.git_changes);
+    for bundled in &mut exported.bundled {
+        bundled.git = git::GitState::capture(std::path::Path::new(&bundled.project_path), options.git_changes);
+    }
 
-    if output.status.success() {
-        Ok(String::from_utf8(output.stdout)?.trim().to_string())
+    if options.context {
+        // Bundled sessions are in the same order as `sessions`
+        let data = std::iter::once(&mut exported.session).chain(exported.bundled.iter_mut());
+        for (index, (data, session)) in data.zip(sessions).enumerate() {
+            let data_dir = if index == 0 {
+                project_dir.to_path_buf()
+            } else {
+                PathBuf::from(&data.project_path)
+            };
+            if let Some(session_dir) = session.file_path.parent() {
+                data.attachments.extend(context::capture(&data_dir, session_dir)?);
+            }
+        }
+    }
+    if let Some(snapshot) = &options.files {
+        let data = std::iter::once(&mut exported.session).chain(exported.bundled.iter_mut());
+        for data in data {
+            data.files = files::snapshot(&data.messages, &data.project_path, snapshot, &mut exported.blobs);
+        }
+    }
+    let mut shrunk = shrink::ShrinkReport::default();
+    if let Some(shrink) = &options.shrink {
+        let data = std::iter::once(&mut exported.session).chain(exported.bundled.iter_mut());
+        for data in data {
+            let mut shrinker = shrink::Shrinker::new(shrink);
+            shrinker.shrink_messages(&mut data.messages);
+            shrinker.shrink_attachments(&mut data.attachments);
+            shrunk.truncated += shrinker.report.truncated;
+            shrunk.deduplicated += shrinker.report.deduplicated;
+            shrunk.images += shrinker.report.images;
+            shrunk.bytes_before += shrinker.report.bytes_before;
+            shrunk.bytes_after += shrinker.report.bytes_after;
+        }
+    }
+    exported.seal()?;
+    let (value, dropped) = match &options.format_version {
+        Some(version) => match schema::downgrade(&exported, version) {
+            Ok(downgraded) => downgraded,
+            Err(e) => {
+                eprintln!("✗ {}", e);
+                std::process::exit(1);
+            }
+        },
+        None => (serde_json::to_value(&exported)?, Vec::new()),
+    };
+    if options.zstd {
+        container::write(&value, &output_path, dictionary.as_ref())?;
+    } else if options.format_version.is_some() {
+        export::write_export(&serde_json::to_string_pretty(&value)?, &output_path)?;
     } else {
-        anyhow::bail!("No git remote found")
+        exported.export_to_file(&output_path)?;
     }
-}
-
-/// Get the git remote URL for a specific path
-fn get_git_remote_url_for_path(path: &str) -> Result<String> {
-    let output = std::process::Command::new("git")
-        .args(["-C", path, "config", "--get", "remote.origin.url"])
-        .output()?;
 
-    if output.status.success() {
-        Ok(String::from_utf8(output.stdout)?.trim().to_string())
+    if exported.is_bundle() && !dropped.contains(&"bundled sessions") {
+        println!("✓ Bundled {} sessions into ./{}", exported.manifest.len(), file_name);
+        for entry in &exported.manifest {
+            println!("  {}  {}  {}", short_id(&entry.id), entry.project_path, entry.summary);
+        }
     } else {
-        anyhow::bail!("No git remote found")
+        println!("✓ Session exported to ./{}", file_name);
+    }
+    if options.zstd {
+        let size = std::fs::metadata(&output_path).map(|m| m.len()).unwrap_or(0);
+        match &dictionary {
+            Some(dictionary) => println!(
+                "  Compressed: {} (zstd, dictionary {})",
+                progress::format_bytes(size),
+                dictionary.short_id()
+            ),
+            None => println!("  Compressed: {} (zstd)", progress::format_bytes(size)),
+        }
+    }
+    if let Some(git) = &exported.session.git {
+        let changes = match (&git.diff, git.untracked.len()) {
+            (None, 0) if !git.changed_files.is_empty() => {
+                format!(" ({} uncommitted files not included; add --git-changes)", git.changed_files.len())
+            }
+            (None, 0) => String::new(),
+            (_, untracked) => format!(" with uncommitted changes and {} untracked files", untracked),
+        };
+        println!("  Git: {}{}", git.short_head(), changes);
+    }
+    let context_files = exported
+        .sessions()
+        .flat_map(|session| &session.attachments)
+        .filter(|attachment| attachment.kind.is_context())
+        .count();
+    if context_files > 0 {
+        println!("  Context: {} CLAUDE.md, memory and settings files", context_files);
+    }
+    if options.shrink.is_some() {
+        println!(
+            "  Shrunk: {} -> {} ({} outputs truncated, {} duplicates removed, {} images dropped)",
+            progress::format_bytes(shrunk.bytes_before),
+            progress::format_bytes(shrunk.bytes_after),
+            shrunk.truncated,
+            shrunk.deduplicated,
+            shrunk.images
+        );
+    }
+    if options.files.is_some() && !dropped.contains(&"file snapshots") {
+        print_file_snapshots(&exported);
+    }
+    if let Some(version) = &options.format_version {
+        println!("  Format: {}", version);
+        if !dropped.is_empty() {
+            println!("  Left out (not supported by format {}): {}", version, dropped.join(", "));
+        }
     }
-}
-
-/// Normalize git URL for comparison (handles https vs ssh formats)
-fn normalize_git_url(url: &str) -> String {
-    url.trim()
-        .trim_end_matches(".git")
-        .replace("git@github.com:", "github.com/")
-        .replace("https://github.com/", "github.com/")
-        .replace("http://github.com/", "github.com/")
-        .to_lowercase()
-}
-
-fn export_session_success(session: &session::Session, current_dir: &std::path::Path) -> Result<()> {
-    let output_path = current_dir.join("mcc-export.json.gz");
-    let exported = export::ExportedSession::from_session(session)?;
-    exported.export_to_file(&output_path)?;
-
-    println!("✓ Session exported to ./mcc-export.json.gz");
     println!("\nShare with teammate:");
-    println!("  1. Send mcc-export.json.gz via Slack/email");
+    println!("  1. Send {} via Slack/email", file_name);
     println!("  2. They drop it in their project folder");
     println!("  3. They run: mcc import");
     Ok(())
@@ -164,246 +332,1735 @@ fn export_session_not_found(current_path: &str) -> Result<()> {
     std::process::exit(1);
 }
 
-fn cmd_import() -> Result<()> {
+fn cmd_broadcast(args: &[String]) -> Result<()> {
     let current_dir = std::env::current_dir()?;
-    let file_path = current_dir.join("mcc-export.json.gz");
+    let current_path = current_dir.to_str().context("Invalid current directory path")?;
 
-    if !file_path.exists() {
-        eprintln!("✗ File not found: ./mcc-export.json.gz");
-        eprintln!("\nMake sure you have mcc-export.json.gz in the current directory.");
-        std::process::exit(1);
-    }
+    let sessions = find_all_sessions()?;
+    let Some(session) = find_matching_session(&sessions, current_path) else {
+        return export_session_not_found(current_path);
+    };
 
-    let target_path = current_dir.to_str().map(|s| s.to_string());
+    let port = match flag_value(args, "--port").map(|port| port.parse::<u16>()) {
+        Some(Ok(port)) => port,
+        Some(Err(_)) => {
+            eprintln!("✗ --port expects a port number");
+            std::process::exit(1);
+        }
+        None => live::DEFAULT_RELAY_PORT,
+    };
+    let lan = args.iter().any(|arg| arg == "--lan");
 
-    match import::import_session(&file_path, target_path) {
-        Ok(_session_file) => import_session_success(),
-        Err(e) => import_session_failed(e),
+    // Publish to a relay elsewhere, or run one in this process
+    let relay = match flag_value(args, "--relay") {
+        Some(relay) => relay.to_string(),
+        None => {
+            let bind = if lan { "0.0.0.0" } else { "127.0.0.1" };
+            if let Err(e) = live::spawn_relay(&format!("{}:{}", bind, port)) {
+                eprintln!("✗ {}", e);
+                std::process::exit(1);
+            }
+            format!("127.0.0.1:{}", port)
+        }
+    };
+    let relay_port = relay
+        .rsplit_once(':')
+        .and_then(|(_, port)| port.parse().ok())
+        .unwrap_or(port);
+    let Some(token) = flag_value(args, "--relay-token")
+        .map(|token| token.to_string())
+        .or_else(|| live::local_relay_token(relay_port))
+    else {
+        eprintln!("✗ No token for the relay at {}", relay);
+        eprintln!(
+            "  Pass it with --relay-token; it is in ~/.mcc/live/relay-{}.token on the relay's machine.",
+            relay_port
+        );
+        std::process::exit(1);
+    };
+    let code = live::generate_code();
+
+    let short_id: String = session.id.chars().take(8).collect();
+    println!("✓ Broadcasting session {}", session.id);
+    println!("  Project: {}", session.project_path);
+    println!("  Summary: {}", session.summary);
+    println!("\nFollow along with (only people you give the code to can follow):");
+    if let Some(relay) = flag_value(args, "--relay") {
+        println!("  mcc follow {} --code {} --relay {}", short_id, code, relay);
+    } else if lan {
+        let host = hostname::get()
+            .ok()
+            .and_then(|h| h.into_string().ok())
+            .unwrap_or_else(|| "<this-host>".to_string());
+        println!("  mcc follow {} --code {} --relay {}:{}", short_id, code, host, port);
+    } else {
+        println!("  mcc follow {} --code {}", short_id, code);
     }
-}
+    println!("\nPress Ctrl-C to stop broadcasting.");
 
-fn import_session_success() -> Result<()> {
-    println!("✓ Session imported!");
-    println!("\nOpen Claude Code and run /resume to continue the session.");
+    if let Err(e) = live::broadcast(&relay, &token, &session.id, &session.file_path, &code) {
+        eprintln!("✗ Broadcast failed: {}", e);
+        std::process::exit(1);
+    }
     Ok(())
 }
 
-fn import_session_failed(e: anyhow::Error) -> Result<()> {
-    eprintln!("✗ Import failed: {}", e);
-    std::process::exit(1);
+fn cmd_follow(args: &[String]) -> Result<()> {
+    // The session ID is optional when only one session is being broadcast
+    let query = args.get(2).filter(|arg| !arg.starts_with("--")).map(|s| s.as_str()).unwrap_or("");
+    let relay = flag_value(args, "--relay")
+        .map(|relay| relay.to_string())
+        .unwrap_or_else(|| format!("127.0.0.1:{}", live::DEFAULT_RELAY_PORT));
+    let Some(code) = flag_value(args, "--code") else {
+        eprintln!("✗ Missing --code. The broadcaster was shown it: mcc follow <id> --code <code>");
+        std::process::exit(1);
+    };
+
+    match live::follow(&relay, query, code) {
+        Ok(id) => {
+            println!("\n✓ Broadcast of {} ended", id);
+            println!("  Ask for an export to continue the session yourself.");
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!("✗ Follow failed: {}", e);
+            std::process::exit(1);
+        }
+    }
 }
 
-fn cmd_preview(args: &[String]) -> Result<()> {
-    if args.len() < 3 {
-        eprintln!("Usage: mcc preview <file.json.gz>");
-        std::process::exit(1);
+fn cmd_serve(args: &[String]) -> Result<()> {
+    let data_dir = match flag_value(args, "--data") {
+        Some(dir) => PathBuf::from(dir),
+        None => serve::default_data_dir()?,
+    };
+
+    match (args.get(2).map(|s| s.as_str()), args.get(3)) {
+        (Some("add-token"), Some(team)) => {
+            return match serve::add_token(&data_dir, team) {
+                Ok(token) => {
+                    println!("✓ Token for team {}:", team);
+                    println!("  {}", token);
+                    println!("\nIt is only shown once. Teammates configure it with:");
+                    println!("  mcc config set-token {}", token);
+                    Ok(())
+                }
+                Err(e) => {
+                    eprintln!("✗ {}", e);
+                    std::process::exit(1);
+                }
+            };
+        }
+        (Some("revoke-token"), Some(token)) => {
+            return match serve::revoke_token(&data_dir, token) {
+                Ok(true) => {
+                    println!("✓ Token revoked");
+                    Ok(())
+                }
+                Ok(false) => {
+                    eprintln!("✗ Unknown token");
+                    std::process::exit(1);
+                }
+                Err(e) => {
+                    eprintln!("✗ {}", e);
+                    std::process::exit(1);
+                }
+            };
+        }
+        (Some("add-token" | "revoke-token"), None) => {
+            eprintln!("Usage: mcc serve add-token <team>");
+            eprintln!("       mcc serve revoke-token <token>");
+            std::process::exit(1);
+        }
+        _ => {}
     }
-    let file_path = PathBuf::from(&args[2]);
 
-    match import::preview_session(&file_path) {
-        Ok(session) => preview_session_success(&session),
-        Err(e) => preview_session_failed(e),
+    let port = match flag_value(args, "--port").map(|port| port.parse::<u16>()) {
+        Some(Ok(port)) => port,
+        Some(Err(_)) => {
+            eprintln!("✗ --port expects a port number");
+            std::process::exit(1);
+        }
+        None => serve::DEFAULT_PORT,
+    };
+    let addr = format!("{}:{}", flag_value(args, "--bind").unwrap_or("127.0.0.1"), port);
+    let max_upload = match flag_value(args, "--max-size").map(prune::parse_size) {
+        Some(Ok(size)) => size,
+        Some(Err(e)) => {
+            eprintln!("✗ {}", e);
+            std::process::exit(1);
+        }
+        None => serve::DEFAULT_MAX_UPLOAD,
+    };
+
+    if serve::Tokens::load(&data_dir)?.tokens.is_empty() {
+        eprintln!("✗ No tokens configured. Create one with: mcc serve add-token <team>");
+        std::process::exit(1);
     }
-}
 
-fn preview_session_success(session: &export::ExportedSession) -> Result<()> {
-    println!("Session Preview:");
-    println!("  Version: {}", session.version);
-    println!("  Exported by: {}", session.exported_by);
-    println!("  Exported at: {}", session.exported_at);
-    println!("  Project: {}", session.session.project_path);
-    println!("  Summary: {}", session.session.summary);
-    println!("  Messages: {}", session.session.messages.len());
-    if let Some(branch) = &session.session.git_branch {
-        println!("  Git branch: {}", branch);
+    println!("✓ Serving {} on http://{}", data_dir.display(), addr);
+    println!("  Clients: mcc config set-remote http://{} && mcc config set-token <token>", addr);
+    if let Err(e) = serve::serve(&addr, &data_dir, max_upload) {
+        eprintln!("✗ {}", e);
+        std::process::exit(1);
     }
     Ok(())
 }
 
-fn preview_session_failed(e: anyhow::Error) -> Result<()> {
-    eprintln!("✗ Preview failed: {}", e);
-    std::process::exit(1);
-}
+/// Sessions modified longer ago than this aren't linked to new commits
+const ACTIVE_SESSION_WINDOW: u64 = 60 * 60;
 
-fn cmd_config(args: &[String]) -> Result<()> {
-    if args.len() < 4 || args[2] != "set-bucket" {
-        eprintln!("Usage: mcc config set-bucket <gs://bucket-name>");
+fn cmd_hooks(args: &[String]) -> Result<()> {
+    let current_dir = std::env::current_dir()?;
+    let Some(repo) = git::top_level(&current_dir) else {
+        eprintln!("✗ Not in a git repository");
         std::process::exit(1);
+    };
+
+    match args.get(2).map(|s| s.as_str()) {
+        Some("install") => {
+            let exe = std::env::current_exe().context("Failed to find the mcc executable")?;
+            match git::install_hook(&repo, &exe) {
+                Ok(hook) => {
+                    println!("✓ Installed post-commit hook at {}", hook.display());
+                    println!("\nCommits made while a Claude Code session is active are linked to it.");
+                    println!("See which session produced a commit with: mcc blame <commit>");
+                    println!("Share the links with your team: git push origin {}", git::NOTES_REF);
+                    Ok(())
+                }
+                Err(e) => {
+                    eprintln!("✗ {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some("uninstall") => match git::uninstall_hook(&repo) {
+            Ok(hook) => {
+                println!("✓ Removed the mcc post-commit hook from {}", hook.display());
+                Ok(())
+            }
+            Err(e) => {
+                eprintln!("✗ {}", e);
+                std::process::exit(1);
+            }
+        },
+        Some("post-commit") => hook_post_commit(&repo),
+        _ => {
+            eprintln!("Usage: mcc hooks <install | uninstall>");
+            std::process::exit(1);
+        }
     }
-    let bucket = &args[3];
-    if let Err(e) = cloud::configure_bucket(bucket) {
-        eprintln!("✗ Config failed: {}", e);
-        std::process::exit(1);
+}
+
+/// Run by the post-commit hook: link HEAD to the session being worked on
+/// in this repository, if there is one. Never fails the commit.
+fn hook_post_commit(repo: &std::path::Path) -> Result<()> {
+    let Some(repo_path) = repo.to_str() else {
+        return Ok(());
+    };
+    let sessions = find_all_sessions().unwrap_or_default();
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs();
+
+    let Some(session) = find_matching_session(&sessions, repo_path)
+        .filter(|session| now.saturating_sub(session.last_modified) <= ACTIVE_SESSION_WINDOW)
+    else {
+        return Ok(());
+    };
+
+    match git::add_session_note(repo, "HEAD", &session.id) {
+        Ok(()) => println!("mcc: linked commit to Claude session {}", session.id),
+        Err(e) => eprintln!("mcc: {}", e),
     }
     Ok(())
 }
 
-#[cfg_attr(not(feature = "gcs"), allow(unused_variables))]
-fn cmd_share(args: &[String]) -> Result<()> {
-    #[cfg(feature = "gcs")]
-    {
-        if args.len() < 3 {
-            eprintln!("Usage: mcc share <file.json.gz>");
+fn cmd_blame(args: &[String]) -> Result<()> {
+    if args.len() < 3 || args[2].starts_with("--") {
+        eprintln!("Usage: mcc blame <commit> [--export [file]]");
+        std::process::exit(1);
+    }
+    let current_dir = std::env::current_dir()?;
+    let Some(commit) = git::resolve_commit(&current_dir, &args[2]) else {
+        eprintln!("✗ Unknown commit: {}", args[2]);
+        std::process::exit(1);
+    };
+    let Some(session_id) = git::session_note(&current_dir, &commit) else {
+        eprintln!("✗ Commit {} isn't linked to a Claude session", git::short_sha(&commit));
+        eprintln!("\nLinks are recorded by: mcc hooks install");
+        eprintln!("If a teammate made the commit, fetch their links: git fetch origin {0}:{0}", git::NOTES_REF);
+        std::process::exit(1);
+    };
+
+    let mut exported = match find_linked_session(&session_id) {
+        Ok(exported) => exported,
+        Err(e) => {
+            eprintln!("✗ Commit {} was made in session {}, but {}", git::short_sha(&commit), session_id, e);
             std::process::exit(1);
         }
-        let file_path = PathBuf::from(&args[2]);
-        let config = cloud::CloudConfig::load()?;
+    };
 
-        if !config.enabled {
-            eprintln!("✗ GCS not configured. Run: mcc config set-bucket gs://your-bucket");
-            std::process::exit(1);
+    if args.iter().any(|arg| arg == "--export") {
+        let default_name = format!("mcc-{}.json.gz", git::short_sha(&commit));
+        let output = flag_value(args, "--export")
+            .filter(|path| !path.starts_with("--"))
+            .unwrap_or(&default_name);
+        exported.session.git = Some(git::GitState::at_commit(&current_dir, &commit));
+        exported.export_to_file(std::path::Path::new(output))?;
+        println!("✓ Session {} exported to {}", session_id, output);
+        println!("\nThe reviewer saves it as mcc-export.json.gz in their checkout and runs: mcc import --worktree");
+        return Ok(());
+    }
+
+    println!("Commit {} was made in Claude session {}", git::short_sha(&commit), session_id);
+    println!("  Project: {}", exported.session.project_path);
+    println!("  Summary: {}", exported.session.summary);
+    println!("  Messages: {}", exported.session.messages.len());
+
+    // Show where in the conversation the commit happened
+    let commit_time = git::commit_time(&current_dir, &commit);
+    let mut marked = commit_time.is_none();
+    for message in &exported.session.messages {
+        let timestamp = message
+            .data
+            .get("timestamp")
+            .and_then(|t| t.as_str())
+            .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok());
+        if !marked
+            && let (Some(timestamp), Some(commit_time)) = (timestamp, commit_time)
+            && timestamp > commit_time
+        {
+            println!("\n──── commit {} ────", git::short_sha(&commit));
+            marked = true;
         }
 
-        let runtime = tokio::runtime::Runtime::new()?;
-        match runtime.block_on(cloud::upload_session(&file_path, &config.bucket)) {
-            Ok(gcs_path) => share_upload_success(&gcs_path),
-            Err(e) => share_upload_failed(e),
+        if let Ok(record) = serde_json::to_value(message)
+            && let Some(rendered) = live::render_record(&record)
+        {
+            println!("{}", rendered);
         }
     }
-    #[cfg(not(feature = "gcs"))]
-    {
-        gcs_not_enabled()
-    }
+    Ok(())
 }
 
-#[allow(dead_code)]
-fn share_upload_success(gcs_path: &str) -> Result<()> {
-    println!("✓ Session uploaded!");
-    println!("  GCS path: {}", gcs_path);
-    println!("\nShare with your team:");
-    println!("  mcc fetch {}", gcs_path);
-    Ok(())
+/// A session by ID, from this machine or else from the remote
+fn find_linked_session(session_id: &str) -> Result<export::ExportedSession> {
+    let sessions = find_all_sessions()?;
+    if let Some(session) = sessions.iter().find(|session| session.id == session_id) {
+        return export::ExportedSession::from_session(session);
+    }
+
+    let config = cloud::CloudConfig::load()?;
+    if !config.enabled {
+        anyhow::bail!("it isn't on this machine and no remote is configured to look in");
+    }
+    let backend = config.backend()?;
+    let remote = remote_sessions(&backend, &config);
+    let Some(found) = remote.iter().filter(|session| session.id == session_id).max_by_key(|session| session.date) else {
+        anyhow::bail!("it isn't on this machine or the remote");
+    };
+
+    let codec = codec::Codec::load(config.encrypt)?;
+    match remote::fetch_preview(&backend, &codec, found)? {
+        remote::RemotePreview::Shared(exported) => Ok(*exported),
+        remote::RemotePreview::Synced(loaded) => export::ExportedSession::from_session(&loaded),
+    }
 }
 
-#[allow(dead_code)]
-fn share_upload_failed(e: anyhow::Error) -> Result<()> {
-    eprintln!("✗ Upload failed: {}", e);
-    std::process::exit(1);
+fn cmd_send(args: &[String]) -> Result<()> {
+    let file_path = match args.get(2) {
+        Some(path) => PathBuf::from(path),
+        None => export::find_export_file(&std::env::current_dir()?),
+    };
+    if !file_path.exists() {
+        eprintln!("✗ File not found: {}", file_path.display());
+        eprintln!("\nRun mcc export first, or pass the export to send: mcc send <file.json.gz>");
+        std::process::exit(1);
+    }
+
+    // Check it's a session before announcing it to the network
+    if let Err(e) = import::preview_session(&file_path) {
+        eprintln!("✗ Not a valid export: {}", e);
+        std::process::exit(1);
+    }
+
+    let offer = lan::offer()?;
+    println!("✓ Ready to send {}", file_path.display());
+    println!("\nOn the receiving machine, in the project directory:");
+    println!("  mcc receive {}", offer.code.as_str());
+    if let Ok(address) = offer.direct_address() {
+        println!("\nIf it can't find this machine, add: --from {}", address);
+    }
+    println!("\nWaiting for the receiver (Ctrl-C to cancel)...");
+
+    match lan::send(offer, &file_path) {
+        Ok(receiver) => {
+            println!("✓ Session sent to {}", receiver.ip());
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!("✗ {}", e);
+            std::process::exit(1);
+        }
+    }
 }
 
-#[cfg_attr(not(feature = "gcs"), allow(unused_variables))]
-fn cmd_fetch(args: &[String]) -> Result<()> {
-    #[cfg(feature = "gcs")]
-    {
-        if args.len() < 3 {
-            eprintln!("Usage: mcc fetch <gs://bucket/file.json.gz> [target-path]");
+fn cmd_receive(args: &[String]) -> Result<()> {
+    if args.len() < 3 || args[2].starts_with("--") {
+        eprintln!("Usage: mcc receive <code> [target-path] [--from host:port]");
+        std::process::exit(1);
+    }
+    let code = match lan::TransferCode::parse(&args[2]) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("✗ {}", e);
             std::process::exit(1);
         }
-        let gcs_path = &args[2];
-        let target_path = args.get(3).map(|s| s.to_string()).or_else(|| {
+    };
+    let from = match flag_value(args, "--from").map(|address| {
+        std::net::ToSocketAddrs::to_socket_addrs(address).map(|mut addresses| addresses.next())
+    }) {
+        Some(Ok(Some(address))) => Some(address),
+        Some(_) => {
+            eprintln!("✗ Invalid --from address (expected host:port)");
+            std::process::exit(1);
+        }
+        None => None,
+    };
+    let target_path = args
+        .get(3)
+        .filter(|arg| !arg.starts_with("--"))
+        .map(|s| s.to_string())
+        .or_else(|| {
             std::env::current_dir()
                 .ok()
                 .and_then(|p| p.to_str().map(|s| s.to_string()))
         });
 
-        let home = std::env::var("HOME")?;
-        let temp_file = PathBuf::from(home)
-            .join(".mcc/temp")
-            .join("downloaded-session.json.gz");
-        std::fs::create_dir_all(temp_file.parent().context("Invalid temp file path")?)?;
+    // Kept after the import, since the file snapshot hint points at it
+    let temp_file = transfer::temp_file("received-session.json.gz")?;
 
-        let runtime = tokio::runtime::Runtime::new()?;
-        if let Err(e) = runtime.block_on(cloud::download_session(gcs_path, &temp_file)) {
-            eprintln!("✗ Download failed: {}", e);
+    if from.is_none() {
+        println!("Looking for the sender on the local network...");
+    }
+    match lan::receive(&code, from, &temp_file) {
+        Ok(sender) => println!("✓ Received session from {}", sender.ip()),
+        Err(e) => {
+            eprintln!("✗ Receive failed: {}", e);
             std::process::exit(1);
         }
+    }
 
-        match import::import_session(&temp_file, target_path) {
-            Ok(session_file) => fetch_import_success(&session_file),
-            Err(e) => fetch_import_failed(e),
+    match import::import_session(&temp_file, target_path.clone()) {
+        Ok(imported) => {
+            fetch_import_success(&imported)?;
+            report_git_state(&temp_file, target_path.as_deref());
+            report_context(&temp_file, target_path.as_deref(), install_context(args));
+            report_file_snapshots(&temp_file);
+            Ok(())
         }
-    }
-    #[cfg(not(feature = "gcs"))]
-    {
-        gcs_not_enabled()
+        Err(e) => fetch_import_failed(e),
     }
 }
 
-#[allow(dead_code)]
-fn fetch_import_success(session_file: &std::path::Path) -> Result<()> {
-    println!("✓ Session fetched and imported!");
-    println!("  File: {}", session_file.display());
-    println!("\nYou can now open Claude Code and use /resume to load this session.");
+fn cmd_schema() -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(&schema::json_schema())?);
     Ok(())
 }
 
-#[allow(dead_code)]
-fn fetch_import_failed(e: anyhow::Error) -> Result<()> {
-    eprintln!("✗ Import failed: {}", e);
-    std::process::exit(1);
+fn cmd_dict(args: &[String]) -> Result<()> {
+    match args.get(2).map(|s| s.as_str()) {
+        Some("train") => {
+            let max_size = match flag_value(args, "--size").map(prune::parse_size).transpose() {
+                Ok(size) => size.map_or(container::DEFAULT_DICTIONARY_SIZE, |size| size as usize),
+                Err(e) => {
+                    eprintln!("✗ {}", e);
+                    std::process::exit(1);
+                }
+            };
+            let transcripts: Vec<PathBuf> = find_all_sessions()?.into_iter().map(|session| session.file_path).collect();
+            let dictionary = match container::Dictionary::train(&transcripts, max_size) {
+                Ok(dictionary) => dictionary,
+                Err(e) => {
+                    eprintln!("✗ {}", e);
+                    std::process::exit(1);
+                }
+            };
+            let path = dictionary.install()?;
+            println!(
+                "✓ Trained dictionary {} on {} sessions ({})",
+                dictionary.short_id(),
+                transcripts.len(),
+                progress::format_bytes(dictionary.data.len() as u64)
+            );
+            println!("  Saved to {}", path.display());
+            println!("\nExport with it: mcc export --dict {}", dictionary.short_id());
+            println!("Teammates need the same file to import those exports: mcc dict add <file>");
+        }
+        Some("add") => {
+            let Some(file) = args.get(3) else {
+                eprintln!("Usage: mcc dict add <file.dict>");
+                std::process::exit(1);
+            };
+            match container::Dictionary::install_file(std::path::Path::new(file)) {
+                Ok(dictionary) => println!("✓ Installed dictionary {}", dictionary.short_id()),
+                Err(e) => {
+                    eprintln!("✗ {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some("ls") | None => {
+            let dictionaries = container::Dictionary::installed()?;
+            if dictionaries.is_empty() {
+                println!("No dictionaries installed. Train one with: mcc dict train");
+            }
+            for dictionary in dictionaries {
+                println!("  {}  {}", dictionary.short_id(), progress::format_bytes(dictionary.data.len() as u64));
+            }
+        }
+        Some(other) => {
+            eprintln!("Unknown dict command: {}", other);
+            eprintln!("Usage: mcc dict train [--size 112K] | add <file> | ls");
+            std::process::exit(1);
+        }
+    }
+    Ok(())
 }
 
-fn gcs_not_enabled() -> Result<()> {
-    eprintln!("✗ GCS support not enabled");
-    eprintln!("Rebuild with: cargo build --release --features gcs");
-    std::process::exit(1);
+fn cmd_verify(args: &[String]) -> Result<()> {
+    let file_path = match args.get(2) {
+        Some(path) => PathBuf::from(path),
+        None => export::find_export_file(&std::env::current_dir()?),
+    };
+    let name = file_path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+
+    let value = match container::read(&file_path) {
+        Ok(value) => value,
+        Err(e) => {
+            eprintln!("✗ {}: {}", name, e);
+            std::process::exit(1);
+        }
+    };
+    let verdict = integrity::verify(&value);
+    if let integrity::Verdict::Modified(problems) = &verdict {
+        eprintln!("✗ {} doesn't match the checksums it was exported with; it was changed or damaged since:", name);
+        for problem in problems {
+            eprintln!("  - {}", problem);
+        }
+        std::process::exit(1);
+    }
+    let exported = match schema::load(value) {
+        Ok(exported) => exported,
+        Err(e) => {
+            eprintln!("✗ {}: {}", name, e);
+            std::process::exit(1);
+        }
+    };
+
+    let messages: usize = exported.sessions().map(|session| session.messages.len()).sum();
+    match verdict {
+        integrity::Verdict::Intact(integrity) => {
+            println!("✓ {} is intact", name);
+            println!(
+                "  Format {}, {} messages, {} sections checked",
+                exported.version,
+                messages,
+                integrity.sections.len()
+            );
+            println!("  SHA-256: {}", integrity.total);
+        }
+        _ => {
+            println!("✓ {} reads correctly ({} messages)", name, messages);
+            println!("  It has no checksums to check (exported by an older mcc, or in format 1.1.0 or earlier)");
+        }
+    }
+    Ok(())
 }
 
-fn cmd_sync() -> Result<()> {
-    #[cfg(feature = "gcs")]
-    {
-        let config = cloud::CloudConfig::load()?;
+fn cmd_extract_files(args: &[String]) -> Result<()> {
+    let current_dir = std::env::current_dir()?;
+    let file_path = match args.get(2).filter(|arg| !arg.starts_with("--")) {
+        Some(path) => PathBuf::from(path),
+        None => export::find_export_file(&current_dir),
+    };
+    let into = current_dir.join(flag_value(args, "--into").unwrap_or("mcc-files"));
+    let force = args.iter().any(|arg| arg == "--force");
 
-        if !config.enabled {
-            eprintln!("✗ GCS not configured. Run: mcc config set-bucket gs://your-bucket");
+    let exported = match import::preview_session(&file_path) {
+        Ok(exported) => exported,
+        Err(e) => {
+            eprintln!("✗ Failed to read {}: {}", file_path.display(), e);
             std::process::exit(1);
         }
+    };
+    if exported.sessions().all(|session| session.files.is_empty()) {
+        eprintln!("✗ This export has no file snapshots (export with --files to include them)");
+        std::process::exit(1);
+    }
 
-        println!("Syncing all sessions to GCS...");
+    let (mut written, mut unchanged) = (0, 0);
+    for session in exported.sessions() {
+        // Bundled sessions from other projects get a directory of their own
+        let root = if session.project_path == exported.session.project_path {
+            into.clone()
+        } else {
+            let name = std::path::Path::new(&session.project_path)
+                .file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or("project");
+            into.join(name)
+        };
 
-        let runtime = tokio::runtime::Runtime::new()?;
-        match runtime.block_on(cloud::sync_sessions(&config.bucket)) {
-            Ok(uploaded_files) => {
-                println!("✓ Synced {} sessions to {}", uploaded_files.len(), config.bucket);
-                println!("\nYour sessions are now backed up to GCS.");
-                println!("Run 'mcc restore' to restore them on another machine.");
-                Ok(())
-            }
-            Err(e) => {
-                eprintln!("✗ Sync failed: {}", e);
-                std::process::exit(1);
+        for file in &session.files {
+            let content = file.sha256.as_ref().and_then(|sha256| exported.blobs.get(sha256));
+            let Some(content) = content else {
+                println!("  - {} (not in the export: {})", file.path, file.skipped.as_deref().unwrap_or("missing"));
+                continue;
+            };
+            let result = files::extract_path(file, &session.project_path, &root)
+                .and_then(|dest| Ok((files::extract(content, &dest, force)?, dest)));
+            match result {
+                Ok((files::Extracted::Written, _)) => written += 1,
+                Ok((files::Extracted::Unchanged, _)) => unchanged += 1,
+                Ok((files::Extracted::Kept, dest)) => {
+                    println!("  ✗ Kept your {} (it differs; --force to overwrite)", dest.display())
+                }
+                Err(e) => println!("  ✗ {}: {}", file.path, e),
             }
         }
     }
-    #[cfg(not(feature = "gcs"))]
-    {
-        gcs_not_enabled()
+
+    println!("✓ Extracted {} files to {}", written, into.display());
+    if unchanged > 0 {
+        println!("  {} were already there", unchanged);
     }
+    Ok(())
 }
 
-fn cmd_restore() -> Result<()> {
-    #[cfg(feature = "gcs")]
-    {
-        let config = cloud::CloudConfig::load()?;
+fn cmd_import(args: &[String]) -> Result<()> {
+    let current_dir = std::env::current_dir()?;
+    let file_path = export::find_export_file(&current_dir);
 
-        if !config.enabled {
-            eprintln!("✗ GCS not configured. Run: mcc config set-bucket gs://your-bucket");
-            std::process::exit(1);
+    if !file_path.exists() {
+        eprintln!("✗ File not found: ./{} or ./{}", export::EXPORT_FILE_NAME, export::ZSTD_EXPORT_FILE_NAME);
+        eprintln!(
+            "\nMake sure you have {} or {} in the current directory.",
+            export::EXPORT_FILE_NAME,
+            export::ZSTD_EXPORT_FILE_NAME
+        );
+        std::process::exit(1);
+    }
+
+    if args.iter().any(|arg| arg == "--worktree") {
+        let path = flag_value(args, "--worktree").filter(|path| !path.starts_with("--"));
+        return import_into_worktree(&file_path, &current_dir, path);
+    }
+
+    let target_path = current_dir.to_str().map(|s| s.to_string());
+
+    match import::import_session(&file_path, target_path.clone()) {
+        Ok(imported) => {
+            import_session_success(&imported)?;
+            report_git_state(&file_path, target_path.as_deref());
+            report_context(&file_path, target_path.as_deref(), install_context(args));
+            report_file_snapshots(&file_path);
+            Ok(())
         }
+        Err(e) => import_session_failed(e),
+    }
+}
 
-        println!("Restoring sessions from GCS...");
+/// Recreate the exporter's working tree as a git worktree and import the
+/// session into it
+fn import_into_worktree(file_path: &std::path::Path, repo: &std::path::Path, path: Option<&str>) -> Result<()> {
+    let exported = match import::preview_session(file_path) {
+        Ok(exported) => exported,
+        Err(e) => return import_session_failed(e),
+    };
+    let Some(state) = &exported.session.git else {
+        eprintln!("✗ This export has no git state (it was made outside a repository or by an older mcc)");
+        std::process::exit(1);
+    };
 
-        let runtime = tokio::runtime::Runtime::new()?;
-        match runtime.block_on(cloud::restore_sessions(&config.bucket)) {
-            Ok(restored_files) => {
-                if restored_files.is_empty() {
-                    println!("✓ No sessions found in GCS bucket");
-                } else {
-                    println!("✓ Restored {} sessions from {}", restored_files.len(), config.bucket);
-                    println!("\nYour sessions are now available locally.");
-                    println!("Run 'claude' and use /resume to continue a session.");
-                }
-                Ok(())
-            }
-            Err(e) => {
-                eprintln!("✗ Restore failed: {}", e);
-                std::process::exit(1);
+    let worktree_path = match path {
+        Some(path) => std::path::absolute(path)?,
+        None => match git::default_worktree_path(repo, state) {
+            Ok(path) => path,
+            Err(e) => return import_session_failed(e),
+        },
+    };
+
+    let worktree = match git::create_worktree(repo, state, &worktree_path) {
+        Ok(worktree) => worktree,
+        Err(e) => {
+            eprintln!("✗ Failed to create worktree: {}", e);
+            std::process::exit(1);
+        }
+    };
+    println!("✓ Created worktree at {} ({})", worktree.path.display(), state.short_head());
+    if state.diff.is_some() {
+        if worktree.rejected.is_empty() {
+            println!("  Applied uncommitted changes");
+        } else {
+            println!("  Applied uncommitted changes, except {} hunks (see the .rej files):", worktree.rejected.len());
+            for hunk in &worktree.rejected {
+                println!("    ✗ {}", hunk);
             }
         }
+    } else if !state.changed_files.is_empty() {
+        println!(
+            "  The exporter had uncommitted changes in {} files that weren't exported (mcc export --git-changes)",
+            state.changed_files.len()
+        );
     }
-    #[cfg(not(feature = "gcs"))]
+    if worktree.untracked_written > 0 {
+        println!("  Restored {} untracked files", worktree.untracked_written);
+    }
+    for path in &worktree.unsafe_untracked {
+        println!("  ✗ Skipped untracked file with unsafe path: {}", path);
+    }
+    for skipped in &state.skipped_untracked {
+        println!("  Not included in the export: {}", skipped);
+    }
+
+    let target_path = worktree.path.to_str().map(|s| s.to_string());
+    if let Err(e) = import::import_session(file_path, target_path) {
+        return import_session_failed(e);
+    }
+
+    println!("✓ Session imported!");
+    warn_if_newer_format(&exported.version);
+    println!("\nContinue where they left off:");
+    println!("  cd {}", worktree.path.display());
+    println!("  claude -> /resume");
+    if let Some(branch) = &state.branch {
+        println!("\nThe worktree is detached; to keep working on a branch: git switch -c {}", branch);
+    }
+    Ok(())
+}
+
+fn import_session_success(imported: &[import::ImportedSession]) -> Result<()> {
+    println!("✓ Session imported!");
+    print_imported_bundle(imported);
+    println!("\nOpen Claude Code and run /resume to continue the session.");
+    Ok(())
+}
+
+/// Exports from a newer mcc load, but whatever that version added is lost
+fn warn_if_newer_format(version: &str) {
+    if schema::is_newer(version) {
+        println!(
+            "  ⚠ This export uses format version {}, newer than this mcc's {}; anything it added was ignored. Upgrade mcc to keep it.",
+            version,
+            schema::CURRENT_VERSION
+        );
+    }
+}
+
+/// List what a bundle unpacked to and how many attachments were restored
+fn print_imported_bundle(imported: &[import::ImportedSession]) {
+    if let Some(primary) = imported.first() {
+        warn_if_newer_format(&primary.format_version);
+    }
+    let attachments: usize = imported.iter().map(|session| session.attachments).sum();
+    if attachments > 0 {
+        println!("  Restored {} subagent transcripts, todo lists and other session files", attachments);
+    }
+    if imported.len() < 2 {
+        return;
+    }
+
+    let messages: usize = imported.iter().map(|session| session.messages).sum();
+    println!("  {} sessions, {} messages:", imported.len(), messages);
+    for session in imported {
+        println!(
+            "    {}  {}  ({} messages) {}",
+            short_id(&session.id),
+            session.project_path,
+            session.messages,
+            session.summary
+        );
+    }
+}
+
+/// Counts by kind, e.g. "2 session files, 1 todo list"
+fn describe_attachments(attachments: &[&export::Attachment]) -> String {
+    let mut counts: Vec<(export::AttachmentKind, usize)> = Vec::new();
+    for attachment in attachments {
+        match counts.iter_mut().find(|(kind, _)| *kind == attachment.kind) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((attachment.kind, 1)),
+        }
+    }
+
+    counts
+        .iter()
+        .map(|(kind, count)| format!("{} {}{}", count, kind.label(), if *count == 1 { "" } else { "s" }))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// How many referenced files an export snapshotted, and which it didn't
+fn print_file_snapshots(exported: &export::ExportedSession) {
+    let files: Vec<&files::FileRef> = exported.sessions().flat_map(|session| &session.files).collect();
+    let size: u64 = exported.blobs.values().map(|content| content.len() as u64).sum();
+    let skipped: Vec<&&files::FileRef> = files.iter().filter(|file| file.skipped.is_some()).collect();
+
+    println!(
+        "  Files: {} snapshotted ({}){}",
+        files.len() - skipped.len(),
+        progress::format_bytes(size),
+        if skipped.is_empty() { String::new() } else { format!(", {} skipped", skipped.len()) }
+    );
+    for file in skipped {
+        println!("    - {} ({})", file.path, file.skipped.as_deref().unwrap_or_default());
+    }
+}
+
+/// First 8 characters of a session ID, which comes from an export and may
+/// not be ASCII
+fn short_id(id: &str) -> String {
+    id.chars().take(8).collect()
+}
+
+fn import_session_failed(e: anyhow::Error) -> Result<()> {
+    eprintln!("✗ Import failed: {}", e);
+    std::process::exit(1);
+}
+
+fn cmd_preview(args: &[String]) -> Result<()> {
+    if args.len() < 3 {
+        eprintln!("Usage: mcc preview <file.json.gz>");
+        std::process::exit(1);
+    }
+    let file_path = PathBuf::from(&args[2]);
+
+    match import::preview_session(&file_path) {
+        Ok(session) => preview_session_success(&session, args.iter().any(|arg| arg == "--diff")),
+        Err(e) => preview_session_failed(e),
+    }
+}
+
+fn preview_session_success(session: &export::ExportedSession, show_diff: bool) -> Result<()> {
+    println!("Session Preview:");
+    if schema::is_newer(&session.version) {
+        println!(
+            "  Version: {} (newer than this mcc's {}; anything it added isn't shown)",
+            session.version,
+            schema::CURRENT_VERSION
+        );
+    } else {
+        println!("  Version: {}", session.version);
+    }
+    println!("  Exported by: {}", session.exported_by);
+    println!("  Exported at: {}", session.exported_at);
+    println!("  Project: {}", session.session.project_path);
+    println!("  Summary: {}", session.session.summary);
+    println!("  Messages: {}", session.session.messages.len());
+    if session.integrity.is_some() {
+        println!("  Checksums: verified");
+    }
+    if let Some(branch) = &session.session.git_branch {
+        println!("  Git branch: {}", branch);
+    }
+    let attachments: Vec<&export::Attachment> = session
+        .sessions()
+        .flat_map(|session| &session.attachments)
+        .collect();
+    if !attachments.is_empty() {
+        println!("  Attachments: {}", describe_attachments(&attachments));
+    }
+    if session.sessions().any(|session| !session.files.is_empty()) {
+        print_file_snapshots(session);
+    }
+    if session.is_bundle() {
+        println!("  Bundled sessions: {}", session.manifest.len());
+        for entry in &session.manifest {
+            println!(
+                "    {}  {}  ({} messages) {}",
+                short_id(&entry.id),
+                entry.project_path,
+                entry.messages,
+                entry.summary
+            );
+        }
+    }
+
+    if let Some(git) = &session.session.git {
+        println!("  Git commit: {}", git.head);
+        if let Some(remote_url) = &git.remote_url {
+            println!("  Git remote: {}", remote_url);
+        }
+        if !git.changed_files.is_empty() {
+            let included = if git.diff.is_some() || !git.untracked.is_empty() {
+                "diff included"
+            } else {
+                "not included"
+            };
+            println!("  Uncommitted: {} files ({})", git.changed_files.len(), included);
+        }
+
+        if let Ok(current_dir) = std::env::current_dir() {
+            print_git_differences(git, &current_dir);
+        }
+    }
+
+    if let Ok(current_dir) = std::env::current_dir()
+        && let Some(current_path) = current_dir.to_str()
+        && let Ok(files) = context::compare(&session.session.attachments, current_path)
+        && !files.is_empty()
     {
-        gcs_not_enabled()
+        print_context_status(&files);
+        if show_diff {
+            for file in files.iter().filter(|file| matches!(file.status, context::ContextStatus::Differs { .. })) {
+                match context::diff(file) {
+                    Ok(diff) => print!("\n{}", diff),
+                    Err(e) => eprintln!("Warning: {}", e),
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Show how the working tree at `dir` differs from the exporter's
+fn print_git_differences(git: &git::GitState, dir: &std::path::Path) {
+    let differences = git.differences(dir);
+    if differences.is_empty() {
+        println!("\n✓ Working tree matches the export ({})", git.short_head());
+        return;
+    }
+
+    println!("\nCompared with {}:", dir.display());
+    for difference in differences {
+        println!("  - {}", difference);
+    }
+}
+
+fn install_context(args: &[String]) -> bool {
+    args.iter().any(|arg| arg == "--install-context")
+}
+
+/// After importing `export_file` into `project_path`, compare the exported
+/// CLAUDE.md, memory and settings with the importer's, installing them side
+/// by side if asked to
+fn report_context(export_file: &std::path::Path, project_path: Option<&str>, install: bool) {
+    let (Ok(exported), Some(project_path)) = (import::preview_session(export_file), project_path) else {
+        return;
+    };
+    let files = match context::compare(&exported.session.attachments, project_path) {
+        Ok(files) if !files.is_empty() => files,
+        Ok(_) => return,
+        Err(e) => {
+            eprintln!("Warning: {}", e);
+            return;
+        }
+    };
+
+    print_context_status(&files);
+    if !install {
+        println!("\nInstall them next to yours with --install-context");
+        return;
+    }
+
+    match context::install(&files) {
+        Ok(written) => {
+            for path in written {
+                println!("  ✓ Wrote {}", path.display());
+            }
+        }
+        Err(e) => eprintln!("✗ Failed to install context: {}", e),
+    }
+}
+
+fn print_context_status(files: &[context::ContextFile]) {
+    println!("\nProject context from the export:");
+    for file in files {
+        let status = match file.status {
+            context::ContextStatus::New => "new".to_string(),
+            context::ContextStatus::Same => "same as yours".to_string(),
+            context::ContextStatus::Differs { added, removed } => {
+                format!("differs from yours (+{} -{} lines)", added, removed)
+            }
+        };
+        println!("  {}: {}", file.local_path.display(), status);
+    }
+}
+
+/// After importing `export_file` into `project_path`, show how that working
+/// tree differs from the exporter's
+fn report_git_state(export_file: &std::path::Path, project_path: Option<&str>) {
+    if let Ok(exported) = import::preview_session(export_file)
+        && let Some(git) = &exported.session.git
+        && let Some(project_path) = project_path
+    {
+        print_git_differences(git, std::path::Path::new(project_path));
+    }
+}
+
+/// Point at `mcc extract-files` when the export carries file snapshots
+fn report_file_snapshots(export_file: &std::path::Path) {
+    let Ok(exported) = import::preview_session(export_file) else {
+        return;
+    };
+    let snapshots = exported
+        .sessions()
+        .flat_map(|session| &session.files)
+        .filter(|file| file.sha256.is_some())
+        .count();
+    if snapshots == 0 {
+        return;
+    }
+
+    let is_default = std::env::current_dir().is_ok_and(|dir| export::find_export_file(&dir) == export_file);
+    println!("\nThe export includes snapshots of {} files Claude read or wrote. Write them out with:", snapshots);
+    if is_default {
+        println!("  mcc extract-files");
+    } else {
+        println!("  mcc extract-files {}", export_file.display());
+    }
+}
+
+fn preview_session_failed(e: anyhow::Error) -> Result<()> {
+    eprintln!("✗ Preview failed: {}", e);
+    std::process::exit(1);
+}
+
+fn cmd_config(args: &[String]) -> Result<()> {
+    let result = match (args.get(2).map(|s| s.as_str()), args.get(3)) {
+        // set-bucket predates non-GCS remotes and is kept as an alias
+        (Some("set-remote" | "set-bucket"), Some(remote)) => cloud::configure_remote(remote),
+        (Some("set-workers"), Some(workers)) => match workers.parse() {
+            Ok(workers) => cloud::configure_workers(workers),
+            Err(_) => Err(anyhow::anyhow!("Invalid worker count: {}", workers)),
+        },
+        (Some("set-encryption"), Some(setting)) => match setting.as_str() {
+            "on" => cloud::configure_encryption(true),
+            "off" => cloud::configure_encryption(false),
+            _ => Err(anyhow::anyhow!("Expected on or off, got: {}", setting)),
+        },
+        (Some("set-token"), Some(token)) if token == "off" => cloud::configure_token(None),
+        (Some("set-token"), Some(token)) => cloud::configure_token(Some(token)),
+        (Some("set-team"), Some(team)) if team == "off" => cloud::configure_team(None),
+        (Some("set-team"), Some(team)) => cloud::configure_team(Some(team)),
+        (Some("set-user"), Some(user)) => cloud::configure_user(user),
+        (Some("set-layout"), Some(layout)) if layout == "default" => cloud::configure_layout(None),
+        (Some("set-layout"), Some(layout)) => cloud::configure_layout(Some(layout)),
+        (Some("set-prune"), Some(setting)) if setting == "off" => cloud::configure_prune(None),
+        (Some("set-prune"), Some(_)) => match prune::PrunePolicy::from_args(args) {
+            Ok(policy) if policy.is_empty() => Err(anyhow::anyhow!("Expected --keep, --older-than or --larger-than")),
+            Ok(policy) => cloud::configure_prune(Some(policy)),
+            Err(e) => Err(e),
+        },
+        _ => {
+            eprintln!("Usage: mcc config set-remote <gs://bucket-name | ssh://user@host/path | https://relay>");
+            eprintln!("       mcc config set-token <token|off>");
+            eprintln!("       mcc config set-workers <count>");
+            eprintln!("       mcc config set-encryption <on|off>");
+            eprintln!("       mcc config set-team <name|off>");
+            eprintln!("       mcc config set-user <name>");
+            eprintln!("       mcc config set-layout <template|default>");
+            eprintln!("       mcc config set-prune <--keep N | --older-than 90d | --larger-than 20M | off>");
+            std::process::exit(1);
+        }
+    };
+
+    if let Err(e) = result {
+        eprintln!("✗ Config failed: {}", e);
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Value following `flag` in the arguments, e.g. `--jobs 8`
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|arg| arg == flag)
+        .and_then(|i| args.get(i + 1))
+        .map(|s| s.as_str())
+}
+
+/// Transfer settings from the config, with the worker count overridable
+/// by `--jobs`
+fn transfer_options(args: &[String], config: &cloud::CloudConfig) -> transfer::TransferOptions {
+    let workers = match flag_value(args, "--jobs").map(|jobs| jobs.parse::<usize>()) {
+        Some(Ok(jobs)) if jobs > 0 => jobs,
+        Some(_) => {
+            eprintln!("✗ --jobs expects a positive number");
+            std::process::exit(1);
+        }
+        None => config.workers,
+    };
+
+    match codec::Codec::load(config.encrypt) {
+        Ok(codec) => transfer::TransferOptions { workers, codec },
+        Err(e) => {
+            eprintln!("✗ {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Load the configured remote, exiting with a hint if there isn't one
+fn configured_backend() -> Result<backend::Backend> {
+    let config = cloud::CloudConfig::load()?;
+
+    if !config.enabled {
+        eprintln!("✗ No remote configured. Run: mcc config set-remote <gs://bucket | ssh://user@host/path | https://relay>");
+        std::process::exit(1);
+    }
+
+    match config.backend() {
+        Ok(backend) => Ok(backend),
+        Err(e) => {
+            eprintln!("✗ Invalid remote {}: {}", config.remote, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn cmd_share(args: &[String]) -> Result<()> {
+    if args.len() < 3 || args[2].starts_with("--") {
+        eprintln!("Usage: mcc share <file.json.gz> [--expires 24h] [--once | --max-downloads N]");
+        std::process::exit(1);
+    }
+    let file_path = PathBuf::from(&args[2]);
+    let config = cloud::CloudConfig::load()?;
+    let backend = configured_backend()?;
+
+    let expires_at = match flag_value(args, "--expires").map(filter::parse_age).transpose() {
+        Ok(age) => age.map(|age| chrono::Utc::now() + age),
+        Err(e) => {
+            eprintln!("✗ {}", e);
+            std::process::exit(1);
+        }
+    };
+    let max_downloads = match flag_value(args, "--max-downloads").map(|n| n.parse::<u32>()) {
+        Some(Ok(n)) if n > 0 => Some(n),
+        Some(_) => {
+            eprintln!("✗ --max-downloads expects a positive number");
+            std::process::exit(1);
+        }
+        None if args.iter().any(|arg| arg == "--once") => Some(1),
+        None => None,
+    };
+    let wants_link = expires_at.is_some() || max_downloads.is_some();
+
+    // Check before uploading, so a refused link doesn't leave an object behind
+    if wants_link && !matches!(backend, backend::Backend::Http { .. }) {
+        eprintln!("✗ --expires, --once and --max-downloads need an mcc serve relay remote (https://...)");
+        eprintln!("  {} can't expire links; share without them, or set a relay with: mcc config set-remote https://...", config.remote);
+        std::process::exit(1);
+    }
+
+    let runtime = tokio::runtime::Runtime::new()?;
+    let key = match runtime.block_on(cloud::upload_session(&file_path, &backend, &config)) {
+        Ok(key) => key,
+        Err(e) => return share_upload_failed(e),
+    };
+
+    if !wants_link {
+        return share_upload_success(&backend.url(&key));
+    }
+
+    match backend.create_link(&key, expires_at, max_downloads) {
+        Ok(link) => share_link_success(&link, expires_at, max_downloads),
+        Err(e) => {
+            eprintln!("✗ Session uploaded to {}, but creating the link failed: {}", backend.url(&key), e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn share_upload_success(remote_path: &str) -> Result<()> {
+    println!("✓ Session uploaded!");
+    println!("  Remote path: {}", remote_path);
+    println!("\nShare with your team:");
+    println!("  mcc fetch {}", remote_path);
+    Ok(())
+}
+
+fn share_link_success(
+    link: &str,
+    expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    max_downloads: Option<u32>,
+) -> Result<()> {
+    println!("✓ Session uploaded!");
+    println!("  Link: {}", link);
+    if let Some(expires_at) = expires_at {
+        println!(
+            "  Expires: {}",
+            expires_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M")
+        );
+    }
+    match max_downloads {
+        Some(1) => println!("  Works once"),
+        Some(n) => println!("  Works {} times", n),
+        None => {}
+    }
+    println!("\nAnyone with the link can fetch it, no access to the remote needed:");
+    println!("  mcc fetch {}", link);
+    Ok(())
+}
+
+fn share_upload_failed(e: anyhow::Error) -> Result<()> {
+    eprintln!("✗ Upload failed: {}", e);
+    std::process::exit(1);
+}
+
+fn cmd_fetch(args: &[String]) -> Result<()> {
+    if args.len() < 3 {
+        eprintln!("Usage: mcc fetch <url | id | user/name> [target-path]");
+        std::process::exit(1);
+    }
+    let remote_path = if args[2].contains("://") {
+        args[2].clone()
+    } else {
+        resolve_share(&args[2])?
+    };
+    let target_path = args
+        .get(3)
+        .filter(|arg| !arg.starts_with("--"))
+        .map(|s| s.to_string())
+        .or_else(|| {
+            std::env::current_dir()
+                .ok()
+                .and_then(|p| p.to_str().map(|s| s.to_string()))
+        });
+
+    let home = std::env::var("HOME")?;
+    let temp_file = PathBuf::from(home)
+        .join(".mcc/temp")
+        .join("downloaded-session.json.gz");
+    std::fs::create_dir_all(temp_file.parent().context("Invalid temp file path")?)?;
+
+    let runtime = tokio::runtime::Runtime::new()?;
+    if let Err(e) = runtime.block_on(cloud::download_session(&remote_path, &temp_file)) {
+        eprintln!("✗ Download failed: {}", e);
+        std::process::exit(1);
+    }
+
+    match import::import_session(&temp_file, target_path.clone()) {
+        Ok(imported) => {
+            fetch_import_success(&imported)?;
+            report_git_state(&temp_file, target_path.as_deref());
+            report_context(&temp_file, target_path.as_deref(), install_context(args));
+            report_file_snapshots(&temp_file);
+            Ok(())
+        }
+        Err(e) => fetch_import_failed(e),
+    }
+}
+
+/// Full URL of the share a short ID or `user/name` reference points at
+fn resolve_share(reference: &str) -> Result<String> {
+    let config = cloud::CloudConfig::load()?;
+    let backend = configured_backend()?;
+    let shares: Vec<remote::RemoteSession> = remote_sessions(&backend, &config)
+        .into_iter()
+        .filter(|session| session.kind == remote::RemoteKind::Shared)
+        .collect();
+
+    match remote::find_remote_session(&shares, reference) {
+        Ok(share) => Ok(backend.url(&share.key)),
+        Err(e) => {
+            eprintln!("✗ {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn fetch_import_success(imported: &[import::ImportedSession]) -> Result<()> {
+    println!("✓ Session fetched and imported!");
+    if let Some(primary) = imported.first() {
+        println!("  File: {}", primary.session_file.display());
+    }
+    print_imported_bundle(imported);
+    println!("\nYou can now open Claude Code and use /resume to load this session.");
+    Ok(())
+}
+
+fn fetch_import_failed(e: anyhow::Error) -> Result<()> {
+    eprintln!("✗ Import failed: {}", e);
+    std::process::exit(1);
+}
+
+fn cmd_sync(args: &[String]) -> Result<()> {
+    let config = cloud::CloudConfig::load()?;
+    let backend = configured_backend()?;
+    let options = transfer_options(args, &config);
+
+    if args.iter().any(|arg| arg == "--bidirectional") {
+        return sync_bidirectional(&backend, &config, &options);
+    }
+
+    println!("Syncing all sessions to {}...", config.remote);
+
+    let runtime = tokio::runtime::Runtime::new()?;
+    match runtime.block_on(cloud::sync_sessions(&backend, &options)) {
+        Ok(summary) => {
+            print_transfer_failures(&summary.failed);
+            println!(
+                "✓ Synced to {}: {} uploaded, {} unchanged, {} failed",
+                config.remote,
+                summary.transferred.len(),
+                summary.skipped.len(),
+                summary.failed.len()
+            );
+            auto_prune(&backend, &config, &options.codec);
+            println!("\nYour sessions are now backed up.");
+            println!("Run 'mcc restore' to restore them on another machine.");
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!("✗ Sync failed: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn sync_bidirectional(
+    backend: &backend::Backend,
+    config: &cloud::CloudConfig,
+    options: &transfer::TransferOptions,
+) -> Result<()> {
+    println!("Syncing sessions both ways with {}...", config.remote);
+
+    let runtime = tokio::runtime::Runtime::new()?;
+    match runtime.block_on(cloud::sync_bidirectional(backend, options)) {
+        Ok(summary) => {
+            print_transfer_failures(&summary.failed);
+            for (key, conflict_key) in &summary.conflicts {
+                eprintln!("⚠ Conflict: {} diverged on both machines", key);
+                eprintln!("  Kept local copy; remote copy saved as {}", conflict_key);
+            }
+            println!(
+                "✓ Synced with {}: {} uploaded, {} downloaded, {} unchanged, {} conflicts, {} failed",
+                config.remote,
+                summary.uploaded.len(),
+                summary.downloaded.len(),
+                summary.skipped.len(),
+                summary.conflicts.len(),
+                summary.failed.len()
+            );
+            auto_prune(backend, config, &options.codec);
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!("✗ Sync failed: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn cmd_restore(args: &[String]) -> Result<()> {
+    let config = cloud::CloudConfig::load()?;
+    let backend = configured_backend()?;
+    let options = transfer_options(args, &config);
+    let filter = match filter::SessionFilter::from_args(args) {
+        Ok(filter) => filter,
+        Err(e) => {
+            eprintln!("✗ {}", e);
+            std::process::exit(1);
+        }
+    };
+    let into = match flag_value(args, "--into").map(std::path::absolute).transpose() {
+        Ok(into) => into.map(|path| path.to_string_lossy().trim_end_matches('/').to_string()),
+        Err(e) => {
+            eprintln!("✗ Invalid --into path: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    match &into {
+        Some(into) => println!("Restoring sessions from {} into {}...", config.remote, into),
+        None => println!("Restoring sessions from {}...", config.remote),
+    }
+
+    let runtime = tokio::runtime::Runtime::new()?;
+    match runtime.block_on(cloud::restore_sessions(&backend, &options, &filter, into.as_deref())) {
+        Ok(summary) => {
+            print_transfer_failures(&summary.failed);
+            if summary.transferred.is_empty() && summary.skipped.is_empty() && summary.failed.is_empty() {
+                if filter.is_empty() {
+                    println!("✓ No sessions found in {}", config.remote);
+                } else {
+                    println!("✓ No sessions in {} match the filters", config.remote);
+                }
+            } else {
+                println!(
+                    "✓ Restored from {}: {} downloaded, {} unchanged, {} failed",
+                    config.remote,
+                    summary.transferred.len(),
+                    summary.skipped.len(),
+                    summary.failed.len()
+                );
+                println!("\nYour sessions are now available locally.");
+                println!("Run 'claude' and use /resume to continue a session.");
+            }
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!("✗ Restore failed: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Apply the configured retention policy to the remote after a sync
+fn auto_prune(backend: &backend::Backend, config: &cloud::CloudConfig, codec: &codec::Codec) {
+    let Some(policy) = &config.prune else {
+        return;
+    };
+
+    let result = prune::remote_candidates(backend, codec)
+        .and_then(|candidates| policy.select(&candidates))
+        .and_then(|selected| prune::delete(Some(backend), codec, selected));
+
+    match result {
+        Ok(summary) => {
+            print_prune_failures(&summary);
+            if !summary.deleted.is_empty() {
+                println!(
+                    "✓ Pruned {} remote sessions ({})",
+                    summary.deleted.len(),
+                    progress::format_bytes(summary.deleted.iter().map(|c| c.size).sum())
+                );
+            }
+        }
+        Err(e) => eprintln!("Warning: Automatic prune failed: {}", e),
+    }
+}
+
+fn cmd_prune(args: &[String]) -> Result<()> {
+    let policy = match prune::PrunePolicy::from_args(args) {
+        Ok(policy) => policy,
+        Err(e) => {
+            eprintln!("✗ {}", e);
+            std::process::exit(1);
+        }
+    };
+    let policy = if policy.is_empty() {
+        cloud::CloudConfig::load()?.prune.unwrap_or_default()
+    } else {
+        policy
+    };
+
+    if policy.is_empty() {
+        eprintln!("Usage: mcc prune [--keep N] [--older-than 30d] [--larger-than 10M] [--dry-run] [--local | --remote]");
+        eprintln!("\nWith no rules, the policy from 'mcc config set-prune' is used.");
+        std::process::exit(1);
+    }
+
+    let dry_run = args.iter().any(|arg| arg == "--dry-run");
+    let local = !args.iter().any(|arg| arg == "--remote");
+    let remote = !args.iter().any(|arg| arg == "--local");
+
+    let config = cloud::CloudConfig::load()?;
+    let codec = match codec::Codec::load(config.encrypt) {
+        Ok(codec) => codec,
+        Err(e) => {
+            eprintln!("✗ {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // Local files and remote objects are ranked separately, so --keep
+    // keeps N of each
+    let mut selected = Vec::new();
+    if local {
+        selected.extend(policy.select(&prune::local_candidates()?)?);
+    }
+
+    // Without a remote, plain `mcc prune` still cleans up local files
+    let backend = if remote && (config.enabled || !local) {
+        Some(configured_backend()?)
+    } else {
+        None
+    };
+    if let Some(backend) = &backend {
+        match prune::remote_candidates(backend, &codec) {
+            Ok(candidates) => selected.extend(policy.select(&candidates)?),
+            Err(e) => {
+                eprintln!("✗ Failed to list remote sessions: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if selected.is_empty() {
+        println!("✓ Nothing to prune");
+        return Ok(());
+    }
+
+    for candidate in &selected {
+        let date = candidate
+            .date
+            .map(|date| date.format("%Y-%m-%d").to_string())
+            .unwrap_or_else(|| "unknown".to_string());
+        println!(
+            "  {}  {:>9}  {}",
+            date,
+            progress::format_bytes(candidate.size),
+            candidate.location
+        );
+    }
+
+    let total_bytes: u64 = selected.iter().map(|candidate| candidate.size).sum();
+    if dry_run {
+        println!(
+            "\n✓ Would delete {} sessions ({}). Run without --dry-run to delete them.",
+            selected.len(),
+            progress::format_bytes(total_bytes)
+        );
+        return Ok(());
+    }
+
+    match prune::delete(backend.as_ref(), &codec, selected) {
+        Ok(summary) => {
+            print_prune_failures(&summary);
+            println!(
+                "\n✓ Deleted {} sessions ({}), {} failed",
+                summary.deleted.len(),
+                progress::format_bytes(summary.deleted.iter().map(|c| c.size).sum()),
+                summary.failed.len()
+            );
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!("✗ Prune failed: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn cmd_watch(args: &[String]) -> Result<()> {
+    match args.get(2).map(|s| s.as_str()) {
+        Some("status") => return cmd_watch_status(),
+        Some("install") => {
+            // Pass the remaining flags (--debounce, --jobs) through to the service
+            return match watch::install_service(&args[3..]) {
+                Ok(service_path) => {
+                    println!("✓ Installed and started {}", service_path.display());
+                    println!("  Check on it with: mcc watch status");
+                    Ok(())
+                }
+                Err(e) => {
+                    eprintln!("✗ Install failed: {}", e);
+                    std::process::exit(1);
+                }
+            };
+        }
+        Some("uninstall") => {
+            return match watch::uninstall_service() {
+                Ok(()) => {
+                    println!("✓ Stopped and removed the mcc watch service");
+                    Ok(())
+                }
+                Err(e) => {
+                    eprintln!("✗ Uninstall failed: {}", e);
+                    std::process::exit(1);
+                }
+            };
+        }
+        _ => {}
+    }
+
+    let config = cloud::CloudConfig::load()?;
+    let backend = configured_backend()?;
+    let options = transfer_options(args, &config);
+    let debounce = match flag_value(args, "--debounce").map(|secs| secs.parse::<u64>()) {
+        Some(Ok(secs)) if secs > 0 => std::time::Duration::from_secs(secs),
+        Some(_) => {
+            eprintln!("✗ --debounce expects a number of seconds");
+            std::process::exit(1);
+        }
+        None => watch::DEFAULT_DEBOUNCE,
+    };
+
+    if let Err(e) = watch::run(&backend, &config.remote, &options, debounce) {
+        eprintln!("✗ Watch failed: {}", e);
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn cmd_watch_status() -> Result<()> {
+    let format_time = |time: chrono::DateTime<chrono::Utc>| {
+        time.with_timezone(&chrono::Local)
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string()
+    };
+
+    let Some(status) = watch::WatchStatus::load()? else {
+        println!("✗ mcc watch has never run");
+        println!("  Start it with: mcc watch (or mcc watch install for a systemd service)");
+        return Ok(());
+    };
+
+    if status.is_running() {
+        println!("✓ Watching (pid {}), syncing to {}", status.pid, status.remote);
+    } else {
+        println!("✗ Not running (last pid {})", status.pid);
+    }
+    if let Some(started_at) = status.started_at {
+        println!("  Started: {}", format_time(started_at));
+    }
+    match status.last_sync {
+        Some(last_sync) => println!(
+            "  Last sync: {} ({} uploaded)",
+            format_time(last_sync),
+            status.last_uploaded
+        ),
+        None => println!("  Last sync: never"),
+    }
+    if let (Some(error), Some(at)) = (&status.last_error, status.last_error_at) {
+        println!("  Last error: {} ({})", error, format_time(at));
+    }
+    println!(
+        "  Service: {}",
+        if watch::service_installed() { "installed" } else { "not installed" }
+    );
+    Ok(())
+}
+
+fn print_prune_failures(summary: &prune::PruneSummary) {
+    for (candidate, error) in &summary.failed {
+        eprintln!("Warning: Failed to delete {}: {}", candidate.location, error);
+    }
+}
+
+fn print_transfer_failures(failed: &[(String, String)]) {
+    for (key, error) in failed {
+        eprintln!("Warning: Failed to transfer {}: {}", key, error);
+    }
+}
+
+fn cmd_remote(args: &[String]) -> Result<()> {
+    match args.get(2).map(|s| s.as_str()) {
+        Some("ls") => cmd_remote_ls(args),
+        Some("show") if args.len() > 3 => cmd_remote_show(&args[3]),
+        _ => {
+            eprintln!("Usage: mcc remote ls [--project <path>] [--branch <name>] [--since <date|7d>]");
+            eprintln!("       mcc remote show <id>");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Remote sessions from the configured backend, exiting on failure
+fn remote_sessions(backend: &backend::Backend, config: &cloud::CloudConfig) -> Vec<remote::RemoteSession> {
+    let listed = codec::Codec::load(config.encrypt)
+        .and_then(|codec| remote::list_remote_sessions(backend, &codec));
+
+    match listed {
+        Ok(sessions) => sessions,
+        Err(e) => {
+            eprintln!("✗ Failed to list {}: {}", config.remote, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn cmd_remote_ls(args: &[String]) -> Result<()> {
+    let config = cloud::CloudConfig::load()?;
+    let backend = configured_backend()?;
+    let filter = match filter::SessionFilter::from_args(args) {
+        Ok(filter) => filter,
+        Err(e) => {
+            eprintln!("✗ {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let sessions: Vec<remote::RemoteSession> = remote_sessions(&backend, &config)
+        .into_iter()
+        .filter(|s| filter.matches(&s.id, &s.project_path, s.git_branch.as_deref(), s.date))
+        .collect();
+
+    if sessions.is_empty() {
+        println!("No remote sessions found in {}", config.remote);
+        return Ok(());
+    }
+
+    println!(
+        "{:<10} {:<7} {:<16} {:>9}  {:<16} {:<20} PROJECT",
+        "ID", "KIND", "DATE", "SIZE", "BRANCH", "BY"
+    );
+    for session in &sessions {
+        let kind = match session.kind {
+            remote::RemoteKind::Shared => "shared",
+            remote::RemoteKind::Synced => "synced",
+        };
+        let date = session
+            .date
+            .map(|date| date.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_else(|| "-".to_string());
+        println!(
+            "{:<10} {:<7} {:<16} {:>9}  {:<16} {:<20} {}",
+            session.id.chars().take(8).collect::<String>(),
+            kind,
+            date,
+            progress::format_bytes(session.size),
+            session.git_branch.as_deref().unwrap_or("-"),
+            session.exported_by,
+            session.project_path
+        );
+        if !session.summary.is_empty() {
+            println!("{:<10} {}", "", session.summary);
+        }
+    }
+    println!("\n{} sessions. Preview one with: mcc remote show <id>", sessions.len());
+    Ok(())
+}
+
+fn cmd_remote_show(query: &str) -> Result<()> {
+    let config = cloud::CloudConfig::load()?;
+    let backend = configured_backend()?;
+    let sessions = remote_sessions(&backend, &config);
+
+    let session = match remote::find_remote_session(&sessions, query) {
+        Ok(session) => session,
+        Err(e) => {
+            eprintln!("✗ {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let preview = codec::Codec::load(config.encrypt)
+        .and_then(|codec| remote::fetch_preview(&backend, &codec, session));
+
+    match preview {
+        Ok(remote::RemotePreview::Shared(exported)) => {
+            preview_session_success(&exported, false)?;
+            println!("  Remote path: {}", backend.url(&session.key));
+            println!("\nImport with: mcc fetch {}", backend.url(&session.key));
+            Ok(())
+        }
+        Ok(remote::RemotePreview::Synced(loaded)) => {
+            println!("Synced Session Preview:");
+            println!("  ID: {}", loaded.id);
+            println!("  Project: {}", loaded.project_path);
+            println!("  Summary: {}", loaded.summary);
+            println!("  Messages: {}", loaded.message_count());
+            if let Some(branch) = &loaded.git_branch {
+                println!("  Git branch: {}", branch);
+            }
+            if !session.exported_by.is_empty() {
+                println!("  Synced by: {}", session.exported_by);
+            }
+            if let Some(date) = session.date {
+                println!("  Last modified: {}", date.to_rfc3339());
+            }
+            println!("  Remote path: {}", backend.url(&session.key));
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!("✗ Preview failed: {}", e);
+            std::process::exit(1);
+        }
     }
 }
 
@@ -422,19 +2079,71 @@ fn show_help() {
     println!("MCC - Multi-Claude Code");
     println!("\nUsage:");
     println!("  mcc export        Export session to ./mcc-export.json.gz");
-    println!("  mcc import        Import session from ./mcc-export.json.gz");
+    println!("    --git-changes   Include uncommitted changes and untracked files");
+    println!("    --all-for-project  Bundle every session of this project into one export");
+    println!("    --sessions a,b  Bundle the given sessions (IDs or ID prefixes, any project)");
+    println!("    --context       Include CLAUDE.md files, project memory and settings");
+    println!("    --files         Snapshot the files Claude read or wrote (--include/--exclude <globs>,");
+    println!("                    --max-file-size 1M, --max-total-size 50M)");
+    println!("    --shrink        Truncate long tool output, drop repeated output and images");
+    println!("    --max-output 16K  Longest tool output kept whole with --shrink");
+    println!("    --format-version <v>  Write an older export format, e.g. 1.0.0");
+    println!("    --zstd          Write ./mcc-export.mccz instead: smaller, compact, needs this mcc to read");
+    println!("    --dict <id|file>  Compress with a shared zstd dictionary (implies --zstd)");
+    println!("  mcc import        Import session from ./mcc-export.json.gz (or ./mcc-export.mccz)");
+    println!("    --worktree [path]  Check out the exported commit and changes in a new git worktree");
+    println!("    --install-context  Install exported CLAUDE.md/memory/settings next to yours");
+    println!("  mcc extract-files [file]  Write an export's file snapshots to ./mcc-files");
+    println!("    --into <dir>    Somewhere else, e.g. . for the project itself");
+    println!("    --force         Overwrite files that differ");
     println!("\nWorkflow:");
     println!("  1. cd /my/project && mcc export");
     println!("  2. Send mcc-export.json.gz to teammate via Slack");
     println!("  3. Teammate drops file in their project folder");
     println!("  4. cd /my/project && mcc import");
     println!("  5. claude -> /resume");
-    println!("\nCloud Backup (requires GCS):");
-    println!("  mcc config set-bucket <gs://bucket>  Configure GCS bucket");
-    println!("  mcc sync                             Backup all sessions to GCS");
-    println!("  mcc restore                          Restore all sessions from GCS");
+    println!("\nLive:");
+    println!("  mcc broadcast [--lan] [--relay H:P --relay-token T]  Stream the current session to followers as it happens");
+    println!("  mcc follow [id] --code C [--relay H:P]  Watch a broadcast session live");
+    println!("\nGit:");
+    println!("  mcc hooks install|uninstall   Link new commits to the active session (git notes)");
+    println!("  mcc blame <commit>            Show the session a commit came from");
+    println!("  mcc blame <commit> --export [file]  Export it for a reviewer");
+    println!("\nLocal network:");
+    println!("  mcc send [file]               Offer an export to the local network and print a code");
+    println!("  mcc receive <code>            Fetch it directly from the sender and import it");
+    println!("\nCloud Backup (GCS, SSH or an mcc relay):");
+    println!("  mcc config set-remote <url>   Configure gs://bucket, ssh://user@host/path or https://relay");
+    println!("  mcc config set-token <token>  Token for an mcc serve relay");
+    println!("  mcc share <file>              Upload an export to the remote");
+    println!("  mcc share <file> --expires 24h --once   Share through a link that expires (relay only)");
+    println!("  mcc fetch <url|id|user/name>  Download and import a shared export");
+    println!("  mcc sync [--jobs N]           Backup all sessions to the remote");
+    println!("  mcc sync --bidirectional      Push and pull changes, keeping both copies on conflict");
+    println!("  mcc restore [--jobs N]        Restore all sessions from the remote");
+    println!("  mcc restore [filters]         Restore some (--project <glob>, --session <id>, --since,");
+    println!("                                --until, --branch), optionally --into <project-path>");
+    println!("  mcc remote ls [filters]       List shared and synced sessions on the remote");
+    println!("  mcc remote show <id>          Preview a remote session without importing it");
+    println!("  mcc watch [--debounce SECS]   Sync sessions automatically as they change");
+    println!("  mcc watch install|uninstall   Run mcc watch as a systemd user service");
+    println!("  mcc watch status              Show the last sync time and any errors");
+    println!("  mcc prune [rules] [--dry-run] Delete old sessions (--keep N, --older-than 30d, --larger-than 10M)");
+    println!("  mcc config set-workers <N>    Default number of parallel transfers");
+    println!("  mcc config set-encryption on  Encrypt synced sessions with ~/.mcc/sync.key");
+    println!("  mcc config set-team <name>    Share under teams/<name>/shares/<user>/");
+    println!("  mcc config set-layout <tmpl>  Object key template for shares, e.g. {{user}}/{{id}}.mcc");
+    println!("  mcc config set-prune <rules>  Prune the remote after each sync (or 'off')");
+    println!("\nSelf-hosted relay:");
+    println!("  mcc serve [--port N] [--bind ADDR] [--data DIR] [--max-size 100M]  Serve shares and backups over HTTP");
+    println!("  mcc serve add-token <team>    Create an API token for a team");
+    println!("  mcc serve revoke-token <tok>  Revoke a token");
     println!("\nAdvanced:");
-    println!("  mcc preview <file>        Preview session details");
+    println!("  mcc preview <file> [--diff]  Preview session details (--diff: context file diffs)");
+    println!("  mcc dict train [--size 112K] Train a zstd dictionary on your transcripts");
+    println!("  mcc dict add <file> | ls     Install a teammate's dictionary, list installed ones");
+    println!("  mcc verify [file]            Check an export isn't truncated, corrupted or modified");
+    println!("  mcc schema                   Print the JSON Schema of the export format");
     println!("\nOther:");
     println!("  mcc help                  Show this help");
 }
@@ -0,0 +1,142 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::export::local_identity;
+use crate::session::Session;
+
+/// Remote key of the manifest describing everything under sessions/
+pub const REMOTE_MANIFEST_KEY: &str = "sessions/manifest.json";
+
+/// Record of synced session files, keyed by remote key
+/// (`sessions/<project-name>/<session-id>.jsonl`)
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SyncManifest {
+    pub entries: BTreeMap<String, ManifestEntry>,
+    /// Sessions deleted from the remote by `mcc prune`, with the hash they
+    /// had, so sync doesn't upload them again unless they change
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub pruned: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub size: u64,
+    pub mtime: u64,
+    pub sha256: String,
+    /// What the session is about, so the remote can be browsed without
+    /// downloading it. Missing from manifests written by older versions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub info: Option<SessionInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionInfo {
+    pub project_path: String,
+    pub summary: String,
+    pub git_branch: Option<String>,
+    pub synced_by: String,
+}
+
+impl SyncManifest {
+    /// Whether `entry` is a copy of a session that was pruned from the remote
+    pub fn was_pruned(&self, key: &str, entry: &ManifestEntry) -> bool {
+        self.pruned.get(key) == Some(&entry.sha256)
+    }
+
+    /// Path of the local manifest, ~/.mcc/sync-manifest.json
+    pub fn local_path() -> Result<PathBuf> {
+        let home = std::env::var("HOME")?;
+        Ok(PathBuf::from(home).join(".mcc/sync-manifest.json"))
+    }
+
+    /// Load a manifest file, treating a missing file as an empty manifest
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read_to_string(path).context("Failed to read sync manifest")?;
+        serde_json::from_str(&content).context("Failed to parse sync manifest")
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Describe a local file, reusing the cached hash when size and mtime
+    /// are unchanged since it was last recorded under `key`
+    pub fn entry_for(&self, key: &str, file_path: &Path) -> Result<ManifestEntry> {
+        let (size, mtime) = size_and_mtime(file_path)?;
+
+        if let Some(cached) = self.entries.get(key)
+            && cached.size == size
+            && cached.mtime == mtime
+        {
+            return Ok(cached.clone());
+        }
+
+        ManifestEntry::from_file(file_path)
+    }
+}
+
+impl ManifestEntry {
+    /// Describe a local file, always hashing its contents
+    pub fn from_file(file_path: &Path) -> Result<Self> {
+        let (size, mtime) = size_and_mtime(file_path)?;
+
+        // The directory name is only a lossy fallback; Session::load prefers
+        // the cwd recorded in the transcript
+        let project_fallback = file_path
+            .parent()
+            .and_then(|p| p.file_name())
+            .and_then(|s| s.to_str())
+            .unwrap_or("")
+            .replace('-', "/");
+        let info = Session::load(file_path.to_path_buf(), project_fallback)
+            .ok()
+            .map(|session| SessionInfo {
+                project_path: session.project_path,
+                summary: session.summary,
+                git_branch: session.git_branch,
+                synced_by: local_identity(),
+            });
+
+        Ok(Self {
+            size,
+            mtime,
+            sha256: sha256_file(file_path)?,
+            info,
+        })
+    }
+}
+
+fn size_and_mtime(file_path: &Path) -> Result<(u64, u64)> {
+    let metadata = fs::metadata(file_path)?;
+    let mtime = metadata
+        .modified()?
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs();
+    Ok((metadata.len(), mtime))
+}
+
+/// Hex-encoded SHA-256 of a byte slice
+pub fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Hex-encoded SHA-256 of a file's contents
+pub fn sha256_file(path: &Path) -> Result<String> {
+    let data = fs::read(path).context(format!("Failed to read {}", path.display()))?;
+    Ok(sha256_hex(&data))
+}
@@ -0,0 +1,126 @@
+use std::io::{IsTerminal, Write};
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Progress of a batch of file transfers. On a terminal this redraws a single
+/// status line; otherwise each finished file is logged on its own line.
+pub struct Progress {
+    verb: &'static str,
+    total_files: usize,
+    total_bytes: u64,
+    started: Instant,
+    interactive: bool,
+    state: Mutex<ProgressState>,
+}
+
+#[derive(Default)]
+struct ProgressState {
+    done_files: usize,
+    done_bytes: u64,
+    failed_files: usize,
+}
+
+impl Progress {
+    pub fn new(verb: &'static str, total_files: usize, total_bytes: u64) -> Self {
+        Self {
+            verb,
+            total_files,
+            total_bytes,
+            started: Instant::now(),
+            interactive: std::io::stdout().is_terminal(),
+            state: Mutex::new(ProgressState::default()),
+        }
+    }
+
+    /// Record a finished transfer
+    pub fn file_done(&self, key: &str, bytes: u64, succeeded: bool) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.done_files += 1;
+        if succeeded {
+            state.done_bytes += bytes;
+        } else {
+            state.failed_files += 1;
+        }
+
+        let mut stdout = std::io::stdout().lock();
+        if self.interactive {
+            let failed = if state.failed_files > 0 {
+                format!(", {} failed", state.failed_files)
+            } else {
+                String::new()
+            };
+            write!(
+                stdout,
+                "\r\x1b[K  {} {}/{} files, {} / {}{}, ETA {}",
+                self.verb,
+                state.done_files,
+                self.total_files,
+                format_bytes(state.done_bytes),
+                format_bytes(self.total_bytes),
+                failed,
+                self.eta(&state)
+            )
+            .ok();
+            stdout.flush().ok();
+        } else {
+            let status = if succeeded { self.verb } else { "failed" };
+            writeln!(
+                stdout,
+                "  [{}/{}] {} {} ({})",
+                state.done_files,
+                self.total_files,
+                status,
+                key,
+                format_bytes(bytes)
+            )
+            .ok();
+        }
+    }
+
+    /// End the status line so later output starts on a fresh line
+    pub fn finish(&self) {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if self.interactive && state.done_files > 0 {
+            println!();
+        }
+    }
+
+    fn eta(&self, state: &ProgressState) -> String {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        if state.done_bytes == 0 || elapsed == 0.0 {
+            return "--".to_string();
+        }
+
+        let rate = state.done_bytes as f64 / elapsed;
+        let remaining = self.total_bytes.saturating_sub(state.done_bytes) as f64 / rate;
+        format_duration(remaining as u64)
+    }
+}
+
+/// Human readable byte count, e.g. "1.4 MB"
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+fn format_duration(secs: u64) -> String {
+    if secs < 60 {
+        format!("{}s", secs)
+    } else if secs < 3600 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}h{:02}m", secs / 3600, (secs % 3600) / 60)
+    }
+}
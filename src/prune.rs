@@ -0,0 +1,378 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::backend::Backend;
+use crate::cloud::{load_remote_manifest, save_remote_manifest};
+use crate::codec::Codec;
+use crate::filter::parse_age;
+use crate::import::preview_session;
+use crate::remote::{RemoteKind, ShareIndex, list_remote_sessions};
+use crate::transfer::with_retries;
+
+/// Which sessions `mcc prune` deletes. `older_than` and `larger_than` select
+/// sessions to delete; `keep` on its own deletes all but the newest N per
+/// project, and alongside the other rules protects the newest N instead.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PrunePolicy {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keep: Option<usize>,
+    /// Age such as `90d`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub older_than: Option<String>,
+    /// Size such as `20M`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub larger_than: Option<String>,
+}
+
+/// Something `mcc prune` could delete
+#[derive(Debug, Clone)]
+pub struct PruneCandidate {
+    /// Remote URL or local path, for display
+    pub location: String,
+    /// Sessions are ranked for `keep` within their group (the project)
+    pub group: String,
+    pub date: Option<DateTime<Utc>>,
+    pub size: u64,
+    target: PruneTarget,
+}
+
+#[derive(Debug, Clone)]
+enum PruneTarget {
+    Remote { key: String, kind: RemoteKind },
+    Local(PathBuf),
+}
+
+#[derive(Debug, Default)]
+pub struct PruneSummary {
+    pub deleted: Vec<PruneCandidate>,
+    pub failed: Vec<(PruneCandidate, String)>,
+}
+
+impl PrunePolicy {
+    pub fn from_args(args: &[String]) -> Result<Self> {
+        let value = |flag: &str| {
+            args.iter()
+                .position(|arg| arg == flag)
+                .and_then(|i| args.get(i + 1))
+                .cloned()
+        };
+
+        let policy = Self {
+            keep: value("--keep")
+                .map(|keep| keep.parse().context(format!("Invalid --keep value: {}", keep)))
+                .transpose()?,
+            older_than: value("--older-than"),
+            larger_than: value("--larger-than"),
+        };
+
+        // Validate now rather than when the policy is applied after a sync
+        policy.max_age()?;
+        policy.max_size()?;
+        Ok(policy)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keep.is_none() && self.older_than.is_none() && self.larger_than.is_none()
+    }
+
+    /// A zero age would put the cutoff at now and select everything
+    fn max_age(&self) -> Result<Option<chrono::Duration>> {
+        let age = self.older_than.as_deref().map(parse_age).transpose()?;
+        if age.is_some_and(|age| age <= chrono::Duration::zero()) {
+            anyhow::bail!("--older-than must be more than 0");
+        }
+        Ok(age)
+    }
+
+    fn max_size(&self) -> Result<Option<u64>> {
+        self.larger_than.as_deref().map(parse_size).transpose()
+    }
+
+    /// The candidates this policy deletes
+    pub fn select(&self, candidates: &[PruneCandidate]) -> Result<Vec<PruneCandidate>> {
+        let cutoff = self.max_age()?.map(|age| Utc::now() - age);
+        let max_size = self.max_size()?;
+        let has_rules = cutoff.is_some() || max_size.is_some();
+
+        // Rank each candidate within its group, newest first
+        let mut by_group: HashMap<&str, Vec<&PruneCandidate>> = HashMap::new();
+        for candidate in candidates {
+            by_group.entry(&candidate.group).or_default().push(candidate);
+        }
+
+        let mut selected = Vec::new();
+        for mut group in by_group.into_values() {
+            group.sort_by_key(|candidate| std::cmp::Reverse(candidate.date));
+
+            for (rank, candidate) in group.into_iter().enumerate() {
+                let protected = self.keep.is_some_and(|keep| rank < keep);
+                let matched = if has_rules {
+                    cutoff.is_some_and(|cutoff| candidate.date.is_some_and(|date| date < cutoff))
+                        || max_size.is_some_and(|max| candidate.size > max)
+                } else {
+                    self.keep.is_some()
+                };
+
+                if matched && !protected {
+                    selected.push(candidate.clone());
+                }
+            }
+        }
+
+        selected.sort_by(|a, b| a.location.cmp(&b.location));
+        Ok(selected)
+    }
+}
+
+/// Shared and synced sessions on the remote
+pub fn remote_candidates(backend: &Backend, codec: &Codec) -> Result<Vec<PruneCandidate>> {
+    Ok(list_remote_sessions(backend, codec)?
+        .into_iter()
+        .map(|session| PruneCandidate {
+            location: backend.url(&session.key),
+            group: session.project_path,
+            date: session.date,
+            size: session.size,
+            target: PruneTarget::Remote {
+                key: session.key,
+                kind: session.kind,
+            },
+        })
+        .collect())
+}
+
+/// Temp files modified more recently than this may belong to a transfer
+/// that's still running, such as a sync started by `mcc watch`
+const TEMP_GRACE_HOURS: i64 = 24;
+
+/// Files in ~/.mcc/exports and ~/.mcc/temp. Exports are grouped by the
+/// project they were exported from; temp files form a group of their own
+/// and are left alone until they're a day old.
+pub fn local_candidates() -> Result<Vec<PruneCandidate>> {
+    let home = std::env::var("HOME")?;
+    let mcc_dir = PathBuf::from(home).join(".mcc");
+    let mut candidates = Vec::new();
+
+    for dir_name in ["exports", "temp"] {
+        let dir = mcc_dir.join(dir_name);
+        if !dir.exists() {
+            continue;
+        }
+
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if !path.is_file() {
+                continue;
+            }
+
+            let metadata = fs::metadata(&path)?;
+            let date = metadata.modified().ok().map(DateTime::<Utc>::from);
+            if dir_name == "temp"
+                && date.is_none_or(|date| date > Utc::now() - chrono::Duration::hours(TEMP_GRACE_HOURS))
+            {
+                continue;
+            }
+
+            let group = if dir_name == "exports" {
+                preview_session(&path)
+                    .map(|exported| exported.session.project_path)
+                    .unwrap_or_else(|_| dir_name.to_string())
+            } else {
+                dir_name.to_string()
+            };
+
+            candidates.push(PruneCandidate {
+                location: path.display().to_string(),
+                group,
+                date,
+                size: metadata.len(),
+                target: PruneTarget::Local(path),
+            });
+        }
+    }
+
+    Ok(candidates)
+}
+
+/// Delete the selected candidates. Pruned synced sessions are recorded in
+/// the remote manifest so the next sync doesn't upload them again.
+pub fn delete(backend: Option<&Backend>, codec: &Codec, selected: Vec<PruneCandidate>) -> Result<PruneSummary> {
+    let mut summary = PruneSummary::default();
+    let mut synced_keys = Vec::new();
+    let mut shared_keys = Vec::new();
+
+    for candidate in selected {
+        let result = match &candidate.target {
+            PruneTarget::Local(path) => fs::remove_file(path).map_err(anyhow::Error::from),
+            PruneTarget::Remote { key, kind } => {
+                let backend = backend.context("No remote configured")?;
+                let result = with_retries(|| backend.delete(key));
+                if result.is_ok() {
+                    match kind {
+                        RemoteKind::Synced => synced_keys.push(key.clone()),
+                        RemoteKind::Shared => shared_keys.push(key.clone()),
+                    }
+                }
+                result
+            }
+        };
+
+        match result {
+            Ok(()) => summary.deleted.push(candidate),
+            Err(e) => summary.failed.push((candidate, format!("{:#}", e))),
+        }
+    }
+
+    if let Some(backend) = backend {
+        if !synced_keys.is_empty()
+            && let Some(mut manifest) = load_remote_manifest(backend, codec)?
+        {
+            for key in synced_keys {
+                if let Some(entry) = manifest.entries.remove(&key) {
+                    manifest.pruned.insert(key, entry.sha256);
+                }
+            }
+            save_remote_manifest(backend, codec, &manifest)?;
+        }
+
+        if !shared_keys.is_empty() {
+            ShareIndex::update(backend, |index| {
+                for key in shared_keys {
+                    index.shares.remove(&key);
+                }
+            })?;
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Parse a size such as `500K`, `20M`, `1G` or a plain byte count
+pub fn parse_size(value: &str) -> Result<u64> {
+    let upper = value.trim().to_uppercase();
+    let upper = upper.trim_end_matches('B');
+    let (number, multiplier) = match upper.chars().last() {
+        Some('K') => (&upper[..upper.len() - 1], 1024),
+        Some('M') => (&upper[..upper.len() - 1], 1024 * 1024),
+        Some('G') => (&upper[..upper.len() - 1], 1024 * 1024 * 1024),
+        _ => (upper, 1),
+    };
+
+    let number: f64 = number
+        .trim()
+        .parse()
+        .ok()
+        .filter(|number: &f64| number.is_finite() && *number >= 0.0)
+        .context(format!("Invalid size: {} (expected e.g. 500K, 20M, 1G)", value))?;
+    Ok((number * multiplier as f64) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(location: &str, group: &str, days_old: Option<i64>, size: u64) -> PruneCandidate {
+        PruneCandidate {
+            location: location.to_string(),
+            group: group.to_string(),
+            date: days_old.map(|days| Utc::now() - chrono::Duration::days(days)),
+            size,
+            target: PruneTarget::Local(PathBuf::from(location)),
+        }
+    }
+
+    fn policy(keep: Option<usize>, older_than: Option<&str>, larger_than: Option<&str>) -> PrunePolicy {
+        PrunePolicy {
+            keep,
+            older_than: older_than.map(String::from),
+            larger_than: larger_than.map(String::from),
+        }
+    }
+
+    fn selected(policy: &PrunePolicy, candidates: &[PruneCandidate]) -> Vec<String> {
+        policy
+            .select(candidates)
+            .unwrap()
+            .into_iter()
+            .map(|candidate| candidate.location)
+            .collect()
+    }
+
+    /// Two projects: api with sessions 1, 10, 100 and 200 days old, web with
+    /// one 300 days old and one with no date
+    fn candidates() -> Vec<PruneCandidate> {
+        vec![
+            candidate("api-1", "api", Some(1), 30 * 1024 * 1024),
+            candidate("api-10", "api", Some(10), 1024),
+            candidate("api-100", "api", Some(100), 1024),
+            candidate("api-200", "api", Some(200), 1024),
+            candidate("web-300", "web", Some(300), 1024),
+            candidate("web-undated", "web", None, 1024),
+        ]
+    }
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_size("1234").unwrap(), 1234);
+        assert_eq!(parse_size("500K").unwrap(), 500 * 1024);
+        assert_eq!(parse_size("20m").unwrap(), 20 * 1024 * 1024);
+        assert_eq!(parse_size("1GB").unwrap(), 1024 * 1024 * 1024);
+        assert_eq!(parse_size("1.5M").unwrap(), 1536 * 1024);
+        assert_eq!(parse_size(" 2 K ").unwrap(), 2048);
+    }
+
+    #[test]
+    fn rejects_invalid_sizes() {
+        for size in ["", "M", "big", "12Q", "-1M", "NaN", "inf"] {
+            assert!(parse_size(size).is_err(), "{:?} was accepted", size);
+        }
+    }
+
+    #[test]
+    fn empty_policy_selects_nothing() {
+        assert!(selected(&PrunePolicy::default(), &candidates()).is_empty());
+    }
+
+    #[test]
+    fn keep_alone_deletes_all_but_the_newest_per_group() {
+        assert_eq!(
+            selected(&policy(Some(2), None, None), &candidates()),
+            vec!["api-100", "api-200"]
+        );
+    }
+
+    #[test]
+    fn older_than_skips_undated_sessions() {
+        assert_eq!(
+            selected(&policy(None, Some("90d"), None), &candidates()),
+            vec!["api-100", "api-200", "web-300"]
+        );
+    }
+
+    #[test]
+    fn larger_than_selects_by_size() {
+        assert_eq!(selected(&policy(None, None, Some("20M")), &candidates()), vec!["api-1"]);
+    }
+
+    #[test]
+    fn keep_protects_the_newest_from_other_rules() {
+        assert_eq!(
+            selected(&policy(Some(3), Some("5d"), Some("20M")), &candidates()),
+            vec!["api-200"]
+        );
+        assert!(selected(&policy(Some(1), None, Some("20M")), &candidates()).is_empty());
+    }
+
+    #[test]
+    fn invalid_rules_are_reported() {
+        assert!(policy(None, Some("soon"), None).select(&candidates()).is_err());
+        assert!(policy(None, None, Some("big")).select(&candidates()).is_err());
+        for age in ["-30d", "0d", "0m"] {
+            assert!(policy(None, Some(age), None).select(&candidates()).is_err(), "{} was accepted", age);
+        }
+    }
+}
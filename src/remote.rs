@@ -0,0 +1,266 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+
+use crate::backend::Backend;
+use crate::cloud::load_remote_entries;
+use crate::codec::Codec;
+use crate::export::ExportedSession;
+use crate::import::preview_session;
+use crate::manifest::ManifestEntry;
+use crate::session::Session;
+use crate::transfer::{download_decoded, temp_file, with_retries};
+
+/// Remote key of the index describing every `mcc share` upload
+pub const SHARE_INDEX_KEY: &str = "shares/index.json";
+
+/// Metadata about shared exports, keyed by object key
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ShareIndex {
+    pub shares: BTreeMap<String, ShareEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShareEntry {
+    pub id: String,
+    pub project_path: String,
+    pub summary: String,
+    pub exported_by: String,
+    pub exported_at: String,
+    pub size: u64,
+    pub git_branch: Option<String>,
+    /// User namespace the share was uploaded under
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RemoteKind {
+    /// An export uploaded with `mcc share`
+    Shared,
+    /// A raw transcript uploaded by `mcc sync`
+    Synced,
+}
+
+/// A session stored on the remote, described without downloading it
+#[derive(Debug, Clone)]
+pub struct RemoteSession {
+    pub key: String,
+    pub kind: RemoteKind,
+    pub id: String,
+    pub project_path: String,
+    pub summary: String,
+    pub exported_by: String,
+    /// User namespace, used to resolve `user/name` references
+    pub user: String,
+    pub date: Option<DateTime<Utc>>,
+    pub size: u64,
+    pub git_branch: Option<String>,
+}
+
+/// A remote session downloaded for previewing
+pub enum RemotePreview {
+    Shared(Box<ExportedSession>),
+    Synced(Session),
+}
+
+impl ShareIndex {
+    pub fn load(backend: &Backend) -> Result<Self> {
+        if !with_retries(|| backend.exists(SHARE_INDEX_KEY))? {
+            return Ok(Self::default());
+        }
+
+        let temp_file = temp_file("share-index.json")?;
+        with_retries(|| backend.download(SHARE_INDEX_KEY, &temp_file))?;
+        let content = fs::read_to_string(&temp_file)?;
+        fs::remove_file(&temp_file).ok();
+        serde_json::from_str(&content).context("Failed to parse share index")
+    }
+
+    /// Change the index on the remote. It is re-read right before writing so
+    /// concurrent shares from teammates are only lost if they land in the
+    /// seconds between the two; remotes have no locking to rule that out.
+    pub fn update(backend: &Backend, change: impl FnOnce(&mut Self)) -> Result<()> {
+        let mut index = Self::load(backend)?;
+        change(&mut index);
+        index.save(backend)
+    }
+
+    fn save(&self, backend: &Backend) -> Result<()> {
+        let temp_file = temp_file("share-index.json")?;
+        fs::write(&temp_file, serde_json::to_string_pretty(self)?)?;
+        let result = with_retries(|| backend.upload(&temp_file, SHARE_INDEX_KEY));
+        fs::remove_file(&temp_file).ok();
+        result.map(|_| ())
+    }
+}
+
+/// Add an uploaded export to the share index
+pub fn record_share(backend: &Backend, key: &str, exported: &ExportedSession, size: u64, user: &str) -> Result<()> {
+    let entry = ShareEntry {
+        id: exported.session.id.clone(),
+        project_path: exported.session.project_path.clone(),
+        summary: exported.session.summary.clone(),
+        exported_by: exported.exported_by.clone(),
+        exported_at: exported.exported_at.clone(),
+        size,
+        git_branch: exported.session.git_branch.clone(),
+        user: Some(user.to_string()),
+    };
+    ShareIndex::update(backend, |index| {
+        index.shares.insert(key.to_string(), entry);
+    })
+}
+
+/// Every shared and synced session on the remote, newest first
+pub fn list_remote_sessions(backend: &Backend, codec: &Codec) -> Result<Vec<RemoteSession>> {
+    let mut sessions = Vec::new();
+
+    let index = ShareIndex::load(backend)?;
+    for (key, share) in &index.shares {
+        sessions.push(RemoteSession {
+            key: key.clone(),
+            kind: RemoteKind::Shared,
+            id: share.id.clone(),
+            project_path: share.project_path.clone(),
+            summary: share.summary.clone(),
+            exported_by: share.exported_by.clone(),
+            user: share
+                .user
+                .clone()
+                .unwrap_or_else(|| user_of(&share.exported_by)),
+            date: DateTime::parse_from_rfc3339(&share.exported_at)
+                .ok()
+                .map(|date| date.with_timezone(&Utc)),
+            size: share.size,
+            git_branch: share.git_branch.clone(),
+        });
+    }
+
+    // Shares uploaded before the index existed live at the remote root
+    for key in with_retries(|| backend.list(""))? {
+        if !key.contains('/') && key.ends_with(".json.gz") && !index.shares.contains_key(&key) {
+            sessions.push(RemoteSession {
+                id: key.trim_end_matches(".json.gz").to_string(),
+                key,
+                kind: RemoteKind::Shared,
+                project_path: String::new(),
+                summary: String::new(),
+                exported_by: String::new(),
+                user: String::new(),
+                date: None,
+                size: 0,
+                git_branch: None,
+            });
+        }
+    }
+
+    let (_, entries) = load_remote_entries(backend, codec)?;
+    sessions.extend(
+        entries
+            .into_iter()
+            .filter_map(|(key, entry)| synced_session(key, entry.as_ref())),
+    );
+
+    sessions.sort_by_key(|session| std::cmp::Reverse(session.date));
+    Ok(sessions)
+}
+
+/// Describe a synced session from its key and manifest entry, or None if
+/// the key isn't of the form sessions/<project-name>/<session-id>.jsonl
+pub fn synced_session(key: String, entry: Option<&ManifestEntry>) -> Option<RemoteSession> {
+    let (project_name, filename) = key
+        .strip_prefix("sessions/")
+        .and_then(|rest| rest.split_once('/'))?;
+
+    let info = entry.and_then(|entry| entry.info.clone());
+    Some(RemoteSession {
+        id: filename.trim_end_matches(".jsonl").to_string(),
+        project_path: info
+            .as_ref()
+            .map(|info| info.project_path.clone())
+            .unwrap_or_else(|| project_name.replace('-', "/")),
+        summary: info.as_ref().map(|info| info.summary.clone()).unwrap_or_default(),
+        exported_by: info.as_ref().map(|info| info.synced_by.clone()).unwrap_or_default(),
+        user: info.as_ref().map(|info| user_of(&info.synced_by)).unwrap_or_default(),
+        date: entry.and_then(|entry| DateTime::from_timestamp(entry.mtime as i64, 0)),
+        size: entry.map(|entry| entry.size).unwrap_or(0),
+        git_branch: info.and_then(|info| info.git_branch),
+        kind: RemoteKind::Synced,
+        key,
+    })
+}
+
+/// The user part of a `user@host` identity
+fn user_of(identity: &str) -> String {
+    identity.split('@').next().unwrap_or_default().to_string()
+}
+
+/// Find a session by ID, ID prefix, `user/name` reference or full object
+/// key. In a `user/name` reference, name is a prefix of the session ID or
+/// of the object's file name.
+pub fn find_remote_session<'a>(sessions: &'a [RemoteSession], query: &str) -> Result<&'a RemoteSession> {
+    if let Some(session) = sessions.iter().find(|s| s.key == query) {
+        return Ok(session);
+    }
+
+    let (candidates, name): (Vec<&RemoteSession>, &str) = match query.split_once('/') {
+        Some((user, name)) => (sessions.iter().filter(|s| s.user == user).collect(), name),
+        None => (sessions.iter().collect(), query),
+    };
+
+    // Prefer exact IDs so a full ID is never ambiguous with a longer one
+    let mut matches: Vec<&RemoteSession> = candidates.iter().copied().filter(|s| s.id == name).collect();
+    if matches.is_empty() {
+        matches = candidates
+            .iter()
+            .copied()
+            .filter(|s| {
+                let filename = s.key.rsplit('/').next().unwrap_or_default();
+                s.id.starts_with(name) || (query.contains('/') && filename.starts_with(name))
+            })
+            .collect();
+    }
+
+    // The same session shared several times resolves to the latest share
+    let same_session = matches
+        .windows(2)
+        .all(|pair| pair[0].id == pair[1].id && pair[0].kind == pair[1].kind);
+    if same_session && let Some(latest) = matches.iter().max_by_key(|s| (s.date, &s.key)) {
+        return Ok(latest);
+    }
+
+    match matches.as_slice() {
+        [session] => Ok(session),
+        [] => anyhow::bail!("No remote session matches {}", query),
+        _ => {
+            let keys: Vec<&str> = matches.iter().map(|s| s.key.as_str()).collect();
+            anyhow::bail!("{} is ambiguous, it matches:\n  {}", query, keys.join("\n  "))
+        }
+    }
+}
+
+/// Download a remote session into a temp file and parse it for previewing
+pub fn fetch_preview(backend: &Backend, codec: &Codec, session: &RemoteSession) -> Result<RemotePreview> {
+    match session.kind {
+        RemoteKind::Shared => {
+            let temp_file = temp_file("remote-preview.json.gz")?;
+            with_retries(|| backend.download(&session.key, &temp_file))?;
+            let exported = preview_session(&temp_file);
+            fs::remove_file(&temp_file).ok();
+            Ok(RemotePreview::Shared(Box::new(exported?)))
+        }
+        RemoteKind::Synced => {
+            // Session::load takes the session ID from the file name
+            let temp_dir = temp_file("preview")?;
+            fs::create_dir_all(&temp_dir)?;
+            let temp_file = temp_dir.join(format!("{}.jsonl", session.id));
+            let loaded = download_decoded(backend, codec, &session.key, &temp_file)
+                .and_then(|_| Session::load(temp_file.clone(), session.project_path.clone()));
+            fs::remove_dir_all(&temp_dir).ok();
+            Ok(RemotePreview::Synced(loaded?))
+        }
+    }
+}
@@ -0,0 +1,535 @@
+use anyhow::{Context, Result};
+use serde_json::{Value, json};
+use std::fmt;
+
+use crate::export::ExportedSession;
+
+/// Format version written by this version of mcc. Bump the minor version
+/// for additions older readers can ignore and the major version for
+/// anything else, and add a migration from the previous version.
+pub const CURRENT_VERSION: &str = "1.2.0";
+
+/// Exports without a version field predate versioning
+const UNVERSIONED: &str = "1.0.0";
+
+/// A step between two adjacent format versions
+struct Migration {
+    from: &'static str,
+    to: &'static str,
+    upgrade: fn(&mut Value),
+    /// Returns what had to be dropped
+    downgrade: fn(&mut Value) -> Vec<&'static str>,
+}
+
+/// Every format version mcc has written, oldest first
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: "1.0.0",
+        to: "1.1.0",
+        upgrade: upgrade_to_1_1_0,
+        downgrade: downgrade_to_1_0_0,
+    },
+    Migration {
+        from: "1.1.0",
+        to: "1.2.0",
+        upgrade: upgrade_to_1_2_0,
+        downgrade: downgrade_to_1_1_0,
+    },
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Version {
+    major: u32,
+    minor: u32,
+    patch: u32,
+}
+
+impl Version {
+    fn parse(value: &str) -> Result<Self> {
+        let parts: Vec<u32> = value
+            .split('.')
+            .map(|part| part.parse())
+            .collect::<Result<_, _>>()
+            .ok()
+            .filter(|parts: &Vec<u32>| parts.len() == 3)
+            .context(format!("Unrecognised export format version: {}", value))?;
+        Ok(Self {
+            major: parts[0],
+            minor: parts[1],
+            patch: parts[2],
+        })
+    }
+
+    fn current() -> Self {
+        Self::parse(CURRENT_VERSION).expect("CURRENT_VERSION is a valid version")
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Whether an export was written by a newer mcc with a format this one only
+/// partly understands
+pub fn is_newer(version: &str) -> bool {
+    Version::parse(version).is_ok_and(|version| version > Version::current())
+}
+
+/// Read an export of any supported format version, migrating older ones to
+/// the current format. Newer minor versions only add fields, so they load
+/// with those fields ignored; newer major versions are refused. The
+/// returned export keeps the version it was written with.
+pub fn load(mut value: Value) -> Result<ExportedSession> {
+    let declared = match value.get("version") {
+        None => UNVERSIONED.to_string(),
+        Some(Value::String(version)) => version.clone(),
+        Some(_) => anyhow::bail!("The export's version field isn't a string"),
+    };
+    let written = Version::parse(&declared)?;
+    let current = Version::current();
+    if written.major > current.major {
+        anyhow::bail!(
+            "This export uses format version {}, but this version of mcc reads up to {}. Upgrade mcc to open it.",
+            written,
+            current
+        );
+    }
+
+    let mut version = written;
+    for migration in MIGRATIONS {
+        if version < Version::parse(migration.to)? {
+            (migration.upgrade)(&mut value);
+            version = Version::parse(migration.to)?;
+        }
+    }
+
+    let invalid = |e: anyhow::Error| anyhow::anyhow!("Invalid export (format version {}): {}", written, e);
+    validate_shape(&value).map_err(invalid)?;
+    let mut exported: ExportedSession = serde_json::from_value(value).map_err(|e| invalid(e.into()))?;
+    validate(&exported).map_err(invalid)?;
+
+    exported.version = declared;
+    Ok(exported)
+}
+
+/// Convert an export to an older format version for tools that only read
+/// that one. Returns the converted export and what it had to drop.
+pub fn downgrade(exported: &ExportedSession, target: &str) -> Result<(Value, Vec<&'static str>)> {
+    let target_version = Version::parse(target)?;
+    if !supported_versions().any(|version| version == target) {
+        anyhow::bail!(
+            "Unknown format version {}; supported versions are {}",
+            target,
+            supported_versions().collect::<Vec<_>>().join(", ")
+        );
+    }
+
+    let mut value = serde_json::to_value(exported)?;
+    let mut dropped = Vec::new();
+    for migration in MIGRATIONS.iter().rev() {
+        if target_version < Version::parse(migration.to)? {
+            dropped.extend((migration.downgrade)(&mut value));
+            value["version"] = json!(migration.from);
+        }
+    }
+    Ok((value, dropped))
+}
+
+/// Every format version, oldest first
+pub fn supported_versions() -> impl Iterator<Item = &'static str> {
+    MIGRATIONS
+        .iter()
+        .map(|migration| migration.from)
+        .chain(std::iter::once(CURRENT_VERSION))
+}
+
+/// 1.1.0 added bundles, git state, attachments and file snapshots, all
+/// optional, so there's nothing to convert
+fn upgrade_to_1_1_0(value: &mut Value) {
+    value["version"] = json!("1.1.0");
+}
+
+fn downgrade_to_1_0_0(value: &mut Value) -> Vec<&'static str> {
+    let mut dropped = Vec::new();
+    let mut remove = |object: &mut Value, key: &str, description: &'static str| {
+        let removed = object.as_object_mut().and_then(|object| object.remove(key));
+        if removed.is_some() && !dropped.contains(&description) {
+            dropped.push(description);
+        }
+    };
+
+    remove(value, "bundled", "bundled sessions");
+    remove(value, "manifest", "bundled sessions");
+    remove(value, "blobs", "file snapshots");
+    let session = &mut value["session"];
+    remove(session, "git", "git state");
+    remove(session, "attachments", "attachments");
+    remove(session, "files", "file snapshots");
+    dropped
+}
+
+/// 1.2.0 added the optional integrity manifest
+fn upgrade_to_1_2_0(value: &mut Value) {
+    value["version"] = json!("1.2.0");
+}
+
+fn downgrade_to_1_1_0(value: &mut Value) -> Vec<&'static str> {
+    match value.as_object_mut().and_then(|object| object.remove("integrity")) {
+        Some(_) => vec!["integrity manifest"],
+        None => Vec::new(),
+    }
+}
+
+/// Check the parts serde would otherwise reject with an unhelpful message
+fn validate_shape(value: &Value) -> Result<()> {
+    let session = value
+        .get("session")
+        .filter(|session| session.is_object())
+        .context("Missing session")?;
+    for field in ["id", "project_path", "summary"] {
+        if !session.get(field).is_some_and(|value| value.is_string()) {
+            anyhow::bail!("session.{} must be a string", field);
+        }
+    }
+
+    let messages = session
+        .get("messages")
+        .and_then(|messages| messages.as_array())
+        .context("session.messages must be an array")?;
+    for (index, message) in messages.iter().enumerate() {
+        if !message.get("type").is_some_and(|kind| kind.is_string()) {
+            anyhow::bail!("session.messages[{}] has no type", index);
+        }
+    }
+    Ok(())
+}
+
+/// Consistency checks across the parts of an export
+fn validate(exported: &ExportedSession) -> Result<()> {
+    for session in exported.sessions() {
+        check_id(&session.id)?;
+        // The head is passed to git on import
+        if let Some(git) = &session.git
+            && (git.head.is_empty() || !git.head.chars().all(|c| c.is_ascii_hexdigit()))
+        {
+            anyhow::bail!("Session {} has an invalid git commit {:?}", session.id, git.head);
+        }
+        for file in &session.files {
+            if let Some(sha256) = &file.sha256
+                && !exported.blobs.contains_key(sha256)
+            {
+                anyhow::bail!("The snapshot of {} is missing", file.path);
+            }
+        }
+    }
+
+    for entry in &exported.manifest {
+        check_id(&entry.id)?;
+    }
+    if exported.is_bundle() {
+        let ids: Vec<&str> = exported.sessions().map(|session| session.id.as_str()).collect();
+        let listed: Vec<&str> = exported.manifest.iter().map(|entry| entry.id.as_str()).collect();
+        if ids != listed {
+            anyhow::bail!("The bundle manifest doesn't match its sessions");
+        }
+    }
+    Ok(())
+}
+
+/// Session IDs name the transcript file on import, so they must not reach
+/// outside Claude Code's projects directory
+fn check_id(id: &str) -> Result<()> {
+    if id.is_empty() {
+        anyhow::bail!("A session has an empty ID");
+    }
+    if id.contains('/') || id.contains('\\') || id.contains("..") {
+        anyhow::bail!("Session ID {:?} isn't a valid file name", id);
+    }
+    Ok(())
+}
+
+/// JSON Schema for the current format. schema/mcc-export.schema.json is
+/// `mcc schema`'s output; regenerate it when this changes.
+pub fn json_schema() -> Value {
+    let string = json!({ "type": "string" });
+    let nullable_string = json!({ "type": ["string", "null"] });
+
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "$id": "https://raw.githubusercontent.com/lyledean1/mcc/main/schema/mcc-export.schema.json",
+        "title": "mcc export",
+        "description": "A Claude Code session exported by mcc, stored gzip-compressed as mcc-export.json.gz. The zstd container (mcc-export.mccz) holds the same document with the sessions' messages split out as JSON Lines.",
+        "type": "object",
+        "required": ["version", "exported_at", "exported_by", "session"],
+        "properties": {
+            "version": {
+                "description": "Format version (semver). Readers accept newer minor versions and ignore what they don't know.",
+                "type": "string",
+                "pattern": "^[0-9]+\\.[0-9]+\\.[0-9]+$"
+            },
+            "exported_at": { "type": "string", "format": "date-time" },
+            "exported_by": { "description": "user@hostname", "type": "string" },
+            "session": {
+                "description": "The only session, or the most recent one of a bundle",
+                "$ref": "#/$defs/session"
+            },
+            "bundled": {
+                "description": "The rest of a bundle's sessions",
+                "type": "array",
+                "items": { "$ref": "#/$defs/session" }
+            },
+            "manifest": {
+                "description": "Every session of a bundle, primary first",
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "required": ["id", "project_path", "summary", "messages"],
+                    "properties": {
+                        "id": string,
+                        "project_path": string,
+                        "summary": string,
+                        "messages": { "type": "integer", "minimum": 0 },
+                        "git_branch": nullable_string
+                    }
+                }
+            },
+            "blobs": {
+                "description": "File snapshots keyed by the SHA-256 of their content",
+                "type": "object",
+                "propertyNames": { "pattern": "^[0-9a-f]{64}$" },
+                "additionalProperties": string
+            },
+            "integrity": {
+                "description": "Checksums of the other fields, each hashed as compact JSON with sorted keys",
+                "type": "object",
+                "required": ["messages", "sections", "total"],
+                "properties": {
+                    "messages": { "description": "Messages across all sessions", "type": "integer", "minimum": 0 },
+                    "sections": {
+                        "description": "SHA-256 of each top-level field; bundled sessions are listed one by one as bundled[0], bundled[1], ...",
+                        "type": "object",
+                        "additionalProperties": { "type": "string", "pattern": "^[0-9a-f]{64}$" }
+                    },
+                    "total": {
+                        "description": "SHA-256 of the lines \"<section> <sha256>\\n\" in section order",
+                        "type": "string"
+                    }
+                }
+            }
+        },
+        "$defs": {
+            "session": {
+                "type": "object",
+                "required": ["id", "project_path", "messages", "summary"],
+                "properties": {
+                    "id": string,
+                    "project_path": { "description": "The exporter's project directory", "type": "string" },
+                    "messages": {
+                        "description": "Transcript records as Claude Code writes them to its .jsonl files",
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "required": ["type"],
+                            "properties": { "type": string }
+                        }
+                    },
+                    "summary": string,
+                    "git_branch": nullable_string,
+                    "git": { "$ref": "#/$defs/git" },
+                    "attachments": {
+                        "type": "array",
+                        "items": { "$ref": "#/$defs/attachment" }
+                    },
+                    "files": {
+                        "description": "Files the session's tool calls read or wrote",
+                        "type": "array",
+                        "items": { "$ref": "#/$defs/file" }
+                    }
+                }
+            },
+            "git": {
+                "type": "object",
+                "required": ["head"],
+                "properties": {
+                    "head": { "description": "Commit SHA", "type": "string", "pattern": "^[0-9a-fA-F]+$" },
+                    "branch": nullable_string,
+                    "remote_url": nullable_string,
+                    "changed_files": { "type": "array", "items": string },
+                    "diff": { "description": "git diff HEAD --binary", "type": "string" },
+                    "untracked": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "required": ["path", "content"],
+                            "properties": { "path": string, "content": string }
+                        }
+                    },
+                    "skipped_untracked": { "type": "array", "items": string }
+                }
+            },
+            "attachment": {
+                "type": "object",
+                "required": ["kind", "path", "content"],
+                "properties": {
+                    "kind": {
+                        "enum": ["session_file", "todo", "project_file", "memory", "user_file"]
+                    },
+                    "path": { "description": "Relative to the directory the kind lives in", "type": "string" },
+                    "content": string
+                }
+            },
+            "file": {
+                "type": "object",
+                "required": ["path", "size"],
+                "properties": {
+                    "path": string,
+                    "sha256": { "description": "Key into blobs; missing when not snapshotted", "type": "string" },
+                    "size": { "type": "integer", "minimum": 0 },
+                    "skipped": { "description": "Why the file wasn't snapshotted", "type": "string" }
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn export(version: Option<&str>) -> Value {
+        let mut value = json!({
+            "exported_at": "2025-01-01T00:00:00Z",
+            "exported_by": "alice@laptop",
+            "session": {
+                "id": "abc-123",
+                "project_path": "/home/alice/project",
+                "summary": "Fix the build",
+                "git_branch": "main",
+                "messages": [
+                    { "type": "user", "cwd": "/home/alice/project" },
+                    { "type": "assistant" }
+                ]
+            }
+        });
+        if let Some(version) = version {
+            value["version"] = json!(version);
+        }
+        value
+    }
+
+    /// A current export using everything 1.0.0 can't hold
+    fn full_export() -> Value {
+        let mut value = export(Some(CURRENT_VERSION));
+        let sha256 = "0".repeat(64);
+        value["session"]["git"] = json!({ "head": "deadbeef", "branch": "main", "remote_url": null });
+        value["session"]["files"] = json!([{ "path": "src/main.rs", "sha256": sha256, "size": 4 }]);
+        value["blobs"] = json!({ sha256: "fn main() {}" });
+        value["integrity"] = json!({ "messages": 2, "sections": {}, "total": "" });
+        value
+    }
+
+    #[test]
+    fn loads_every_supported_version() {
+        for version in supported_versions() {
+            let exported = load(export(Some(version))).unwrap();
+            assert_eq!(exported.version, version);
+            assert_eq!(exported.session.messages.len(), 2);
+        }
+    }
+
+    #[test]
+    fn unversioned_exports_are_1_0_0() {
+        let exported = load(export(None)).unwrap();
+        assert_eq!(exported.version, "1.0.0");
+    }
+
+    #[test]
+    fn newer_minor_version_loads() {
+        let mut value = export(Some("1.99.0"));
+        value["something_new"] = json!(true);
+        let exported = load(value).unwrap();
+        assert_eq!(exported.version, "1.99.0");
+        assert!(is_newer(&exported.version));
+        assert!(!is_newer(CURRENT_VERSION));
+    }
+
+    #[test]
+    fn newer_major_version_is_refused() {
+        let error = load(export(Some("2.0.0"))).unwrap_err().to_string();
+        assert!(error.contains("Upgrade mcc"), "{}", error);
+    }
+
+    #[test]
+    fn malformed_versions_are_refused() {
+        assert!(load(export(Some("1.2"))).is_err());
+        assert!(load(export(Some("one"))).is_err());
+        let mut value = export(None);
+        value["version"] = json!(1);
+        assert!(load(value).is_err());
+    }
+
+    #[test]
+    fn downgrade_drops_what_older_versions_lack() {
+        let exported = load(full_export()).unwrap();
+
+        let (value, dropped) = downgrade(&exported, "1.1.0").unwrap();
+        assert_eq!(value["version"], "1.1.0");
+        assert_eq!(dropped, vec!["integrity manifest"]);
+        assert!(value["session"]["git"].is_object());
+
+        let (value, dropped) = downgrade(&exported, "1.0.0").unwrap();
+        assert_eq!(value["version"], "1.0.0");
+        assert_eq!(dropped, vec!["integrity manifest", "file snapshots", "git state"]);
+        assert!(value.get("blobs").is_none());
+        assert!(value["session"].get("git").is_none());
+        assert!(value["session"].get("files").is_none());
+
+        // What's left still loads, as the version it was converted to
+        let reloaded = load(value).unwrap();
+        assert_eq!(reloaded.version, "1.0.0");
+        assert_eq!(reloaded.session.messages.len(), 2);
+    }
+
+    #[test]
+    fn downgrade_to_current_version_keeps_everything() {
+        let exported = load(full_export()).unwrap();
+        let (value, dropped) = downgrade(&exported, CURRENT_VERSION).unwrap();
+        assert!(dropped.is_empty());
+        assert_eq!(load(value).unwrap().blobs, exported.blobs);
+    }
+
+    #[test]
+    fn downgrade_to_unknown_version_is_refused() {
+        let exported = load(export(None)).unwrap();
+        assert!(downgrade(&exported, "0.9.0").is_err());
+        assert!(downgrade(&exported, "1.1").is_err());
+    }
+
+    #[test]
+    fn session_ids_must_be_file_names() {
+        for id in ["", "../escape", "a/b", "a\\b", ".."] {
+            let mut value = export(None);
+            value["session"]["id"] = json!(id);
+            assert!(load(value).is_err(), "{:?} was accepted", id);
+        }
+    }
+
+    #[test]
+    fn git_heads_must_be_commit_ids() {
+        for head in ["", "--upload-pack=touch x", "é1b2c3d4e5", "HEAD"] {
+            let mut value = full_export();
+            value["session"]["git"]["head"] = json!(head);
+            assert!(load(value).is_err(), "{:?} was accepted", head);
+        }
+    }
+
+    #[test]
+    fn missing_snapshots_are_refused() {
+        let mut value = full_export();
+        value["blobs"] = json!({});
+        let error = load(value).unwrap_err().to_string();
+        assert!(error.contains("snapshot of src/main.rs"), "{}", error);
+    }
+}
@@ -0,0 +1,581 @@
+use anyhow::{Context, Result};
+use chacha20poly1305::aead::OsRng;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::manifest::sha256_hex;
+
+/// Port `mcc serve` listens on unless `--port` says otherwise
+pub const DEFAULT_PORT: u16 = 8740;
+
+/// Requests handled at once
+const WORKERS: usize = 4;
+
+/// Largest object accepted unless `--max-size` says otherwise
+pub const DEFAULT_MAX_UPLOAD: u64 = 100 * 1024 * 1024;
+
+/// API tokens, stored as SHA-256 hashes so the file can't be used to
+/// authenticate if it leaks. Each token belongs to one team.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Tokens {
+    pub tokens: BTreeMap<String, String>,
+}
+
+impl Tokens {
+    fn path(data_dir: &Path) -> PathBuf {
+        data_dir.join("tokens.json")
+    }
+
+    pub fn load(data_dir: &Path) -> Result<Self> {
+        let path = Self::path(data_dir);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read_to_string(&path)?;
+        serde_json::from_str(&content).context("Failed to parse tokens.json")
+    }
+
+    fn save(&self, data_dir: &Path) -> Result<()> {
+        write_private(&Self::path(data_dir), &serde_json::to_string_pretty(self)?)
+    }
+
+    /// Team a token belongs to, if it is valid
+    fn team_for(&self, token: &str) -> Option<&str> {
+        self.tokens.get(&sha256_hex(token.as_bytes())).map(|team| team.as_str())
+    }
+}
+
+/// Share links that let someone without a token download one object, a
+/// limited number of times or until they expire. Keyed by link code.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Links {
+    links: BTreeMap<String, ShareLink>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ShareLink {
+    team: String,
+    key: String,
+    expires_at: Option<DateTime<Utc>>,
+    max_downloads: Option<u32>,
+    #[serde(default)]
+    downloads: u32,
+}
+
+/// Request body for creating a link
+#[derive(Debug, Deserialize)]
+struct NewLink {
+    key: String,
+    expires_at: Option<DateTime<Utc>>,
+    max_downloads: Option<u32>,
+}
+
+/// Serializes read-modify-write of links.json across worker threads. Holds
+/// the number of downloads still being sent for each link code.
+static LINKS_LOCK: Mutex<BTreeMap<String, u32>> = Mutex::new(BTreeMap::new());
+
+/// Characters used in link codes, leaving out ones that are easy to misread
+const CODE_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+const CODE_LEN: usize = 10;
+
+impl Links {
+    fn path(data_dir: &Path) -> PathBuf {
+        data_dir.join("links.json")
+    }
+
+    fn load(data_dir: &Path) -> Result<Self> {
+        let path = Self::path(data_dir);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read_to_string(&path)?;
+        serde_json::from_str(&content).context("Failed to parse links.json")
+    }
+
+    fn save(&self, data_dir: &Path) -> Result<()> {
+        write_private(&Self::path(data_dir), &serde_json::to_string_pretty(self)?)
+    }
+
+    /// Drop links that can no longer be used
+    fn remove_stale(&mut self) {
+        self.links.retain(|_, link| link.usable(0));
+    }
+}
+
+impl ShareLink {
+    /// Whether the link allows another download while `sending` others are
+    /// still in progress
+    fn usable(&self, sending: u32) -> bool {
+        self.expires_at.is_none_or(|expires_at| expires_at > Utc::now())
+            && self.max_downloads.is_none_or(|max| self.downloads + sending < max)
+    }
+}
+
+/// Write a file only its owner can read; tokens.json and links.json hold
+/// credentials
+fn write_private(path: &Path, content: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // Files written by older versions weren't created private
+        if path.exists() {
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        }
+    }
+    options.open(path)?.write_all(content.as_bytes())?;
+    Ok(())
+}
+
+/// Default server storage, ~/.mcc/server
+pub fn default_data_dir() -> Result<PathBuf> {
+    let home = std::env::var("HOME")?;
+    Ok(PathBuf::from(home).join(".mcc/server"))
+}
+
+/// Create a token for `team`, returning it. Only its hash is stored.
+pub fn add_token(data_dir: &Path, team: &str) -> Result<String> {
+    if !valid_team(team) {
+        anyhow::bail!("Invalid team name: {}", team);
+    }
+
+    let mut bytes = [0u8; 24];
+    OsRng.fill_bytes(&mut bytes);
+    let token: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+
+    let mut tokens = Tokens::load(data_dir)?;
+    tokens.tokens.insert(sha256_hex(token.as_bytes()), team.to_string());
+    tokens.save(data_dir)?;
+    Ok(token)
+}
+
+/// Revoke a token. Returns false if it wasn't known.
+pub fn revoke_token(data_dir: &Path, token: &str) -> Result<bool> {
+    let mut tokens = Tokens::load(data_dir)?;
+    let removed = tokens.tokens.remove(&sha256_hex(token.as_bytes())).is_some();
+    tokens.save(data_dir)?;
+    Ok(removed)
+}
+
+/// Serve the object API until the process is stopped. Every team's
+/// objects live under `<data_dir>/teams/<team>/`.
+///
+///   GET    /v1/health                 no auth, for load balancers
+///   GET    /v1/objects?prefix=<p>     JSON array of keys
+///   GET    /v1/objects/<key>          object contents
+///   HEAD   /v1/objects/<key>          200 if it exists, 404 if not
+///   PUT    /v1/objects/<key>          store the request body
+///   DELETE /v1/objects/<key>          remove
+///   POST   /v1/links                  create a share link for an object
+///   GET    /v1/links/<code>           download through a share link
+///   HEAD   /v1/links/<code>           200 if the link can be used, 410 if not
+///
+/// Everything but the health check and link downloads needs
+/// `Authorization: Bearer <token>`. Uploads larger than `max_upload` bytes
+/// are refused.
+pub fn serve(addr: &str, data_dir: &Path, max_upload: u64) -> Result<()> {
+    let server = Arc::new(
+        Server::http(addr).map_err(|e| anyhow::anyhow!("Failed to listen on {}: {}", addr, e))?,
+    );
+
+    let mut workers = Vec::new();
+    for _ in 0..WORKERS {
+        let server = server.clone();
+        let data_dir = data_dir.to_path_buf();
+        workers.push(std::thread::spawn(move || {
+            for request in server.incoming_requests() {
+                let method = request.method().clone();
+                let url = request.url().to_string();
+                match handle(request, &data_dir, max_upload) {
+                    Ok(status) => println!("{} {} {}", method, url, status),
+                    Err(e) => eprintln!("{} {} failed: {}", method, url, e),
+                }
+            }
+        }));
+    }
+
+    for worker in workers {
+        worker.join().ok();
+    }
+    Ok(())
+}
+
+fn handle(mut request: Request, data_dir: &Path, max_upload: u64) -> Result<u16> {
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+
+    if path == "/v1/health" {
+        return respond(request, Response::from_string("ok"));
+    }
+
+    if let Some(code) = path.strip_prefix("/v1/links/")
+        && matches!(request.method(), Method::Get | Method::Head)
+    {
+        return download_link(request, data_dir, code);
+    }
+
+    // Tokens are re-read on every request so add-token and revoke-token
+    // take effect without a restart
+    let tokens = Tokens::load(data_dir)?;
+    let team = request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("Authorization"))
+        .and_then(|header| header.value.as_str().strip_prefix("Bearer "))
+        .and_then(|token| tokens.team_for(token.trim()))
+        // tokens.json from older versions may name a team outside teams/
+        .filter(|team| valid_team(team))
+        .map(|team| team.to_string());
+    let Some(team) = team else {
+        return respond(request, Response::from_string("Unauthorized").with_status_code(401));
+    };
+    let team_dir = data_dir.join("teams").join(&team);
+
+    if path == "/v1/links" && *request.method() == Method::Post {
+        return create_link(request, data_dir, &team);
+    }
+
+    if path == "/v1/objects" && *request.method() == Method::Get {
+        let prefix = query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("prefix="))
+            .map(percent_decode)
+            .unwrap_or_default();
+        let mut keys = Vec::new();
+        list_keys(&team_dir, &team_dir, &mut keys)?;
+        keys.retain(|key| key.starts_with(&prefix));
+        keys.sort();
+        return respond(request, json_response(serde_json::to_string(&keys)?));
+    }
+
+    let Some(key) = path.strip_prefix("/v1/objects/").map(percent_decode) else {
+        return respond(request, Response::from_string("Not Found").with_status_code(404));
+    };
+    if !key.split('/').all(valid_segment) {
+        return respond(request, Response::from_string("Invalid key").with_status_code(400));
+    }
+    let object_path = team_dir.join(&key);
+
+    match request.method() {
+        Method::Get | Method::Head if !object_path.is_file() => {
+            respond(request, Response::from_string("Not Found").with_status_code(404))
+        }
+        Method::Get => {
+            let file = fs::File::open(&object_path)?;
+            respond(request, Response::from_file(file))
+        }
+        Method::Head => respond(request, Response::empty(200)),
+        Method::Put => {
+            // Check the declared length up front, and the actual length in
+            // case the client didn't declare one or lied
+            let mut body = Vec::new();
+            if request.body_length().is_some_and(|length| length as u64 > max_upload)
+                || request.as_reader().take(max_upload + 1).read_to_end(&mut body)? as u64 > max_upload
+            {
+                return respond(request, Response::from_string("Payload Too Large").with_status_code(413));
+            }
+
+            let parent = object_path.parent().context("Invalid key")?;
+            fs::create_dir_all(parent)?;
+
+            // Write to a temp file and rename so readers never see half an object
+            let temp_path = parent.join(format!(".upload-{}", OsRng.next_u64()));
+            fs::write(&temp_path, &body)?;
+            fs::rename(&temp_path, &object_path)?;
+            respond(request, Response::from_string("Created").with_status_code(201))
+        }
+        Method::Delete => {
+            if object_path.is_file() {
+                fs::remove_file(&object_path)?;
+            }
+            respond(request, Response::empty(204))
+        }
+        _ => respond(request, Response::from_string("Method Not Allowed").with_status_code(405)),
+    }
+}
+
+fn create_link(mut request: Request, data_dir: &Path, team: &str) -> Result<u16> {
+    let mut body = String::new();
+    request.as_reader().take(64 * 1024).read_to_string(&mut body)?;
+    let Ok(new_link) = serde_json::from_str::<NewLink>(&body) else {
+        return respond(request, Response::from_string("Invalid link request").with_status_code(400));
+    };
+
+    let object_path = data_dir.join("teams").join(team).join(&new_link.key);
+    if !new_link.key.split('/').all(valid_segment) || !object_path.is_file() {
+        return respond(request, Response::from_string("Not Found").with_status_code(404));
+    }
+
+    let code: String = (0..CODE_LEN)
+        .map(|_| CODE_ALPHABET[OsRng.next_u32() as usize % CODE_ALPHABET.len()] as char)
+        .collect();
+
+    {
+        let _guard = LINKS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let mut links = Links::load(data_dir)?;
+        links.remove_stale();
+        links.links.insert(
+            code.clone(),
+            ShareLink {
+                team: team.to_string(),
+                key: new_link.key,
+                expires_at: new_link.expires_at,
+                max_downloads: new_link.max_downloads,
+                downloads: 0,
+            },
+        );
+        links.save(data_dir)?;
+    }
+
+    let body = serde_json::json!({ "code": code }).to_string();
+    respond(request, json_response(body).with_status_code(201))
+}
+
+fn download_link(request: Request, data_dir: &Path, code: &str) -> Result<u16> {
+    let head = *request.method() == Method::Head;
+    let Some(link) = reserve_download(data_dir, code, !head)? else {
+        return respond(request, Response::from_string("Link expired or not found").with_status_code(410));
+    };
+    if head {
+        return respond(request, Response::empty(200));
+    }
+
+    let object_path = data_dir.join("teams").join(&link.team).join(&link.key);
+    let sent = match fs::File::open(&object_path) {
+        Ok(file) => respond(request, Response::from_file(file)),
+        Err(_) => respond(request, Response::from_string("Not Found").with_status_code(404)),
+    };
+    finish_download(data_dir, code, matches!(sent, Ok(200)))?;
+    sent
+}
+
+/// The link for `code` if it can be used. With `reserve`, holds a download
+/// while it is sent, so a once-only link can't be used twice by requests
+/// racing each other; `finish_download` must follow.
+fn reserve_download(data_dir: &Path, code: &str, reserve: bool) -> Result<Option<ShareLink>> {
+    let mut sending = LINKS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let in_progress = sending.get(code).copied().unwrap_or(0);
+    let link = Links::load(data_dir)?
+        .links
+        .remove(code)
+        .filter(|link| link.usable(in_progress));
+    if link.is_some() && reserve {
+        *sending.entry(code.to_string()).or_default() += 1;
+    }
+    Ok(link)
+}
+
+/// Release a reservation. The download only counts if the whole body went
+/// out, so a dropped connection doesn't use the link up.
+fn finish_download(data_dir: &Path, code: &str, sent: bool) -> Result<()> {
+    let mut sending = LINKS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(count) = sending.get_mut(code) {
+        *count -= 1;
+        if *count == 0 {
+            sending.remove(code);
+        }
+    }
+    if sent {
+        let mut links = Links::load(data_dir)?;
+        if let Some(link) = links.links.get_mut(code) {
+            link.downloads += 1;
+        }
+        links.remove_stale();
+        links.save(data_dir)?;
+    }
+    Ok(())
+}
+
+fn respond<R: Read>(request: Request, response: Response<R>) -> Result<u16> {
+    let status = response.status_code().0;
+    request.respond(response)?;
+    Ok(status)
+}
+
+fn json_response(body: String) -> Response<std::io::Cursor<Vec<u8>>> {
+    let header = Header::from_bytes("Content-Type", "application/json").expect("static header is valid");
+    Response::from_string(body).with_header(header)
+}
+
+/// Every object under `dir`, as keys relative to `root`
+fn list_keys(root: &Path, dir: &Path, keys: &mut Vec<String>) -> Result<()> {
+    if !dir.exists() {
+        return Ok(());
+    }
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            list_keys(root, &path, keys)?;
+        } else if let Ok(relative) = path.strip_prefix(root)
+            && let Some(key) = relative.to_str()
+            && !key.rsplit('/').next().unwrap_or_default().starts_with(".upload-")
+        {
+            keys.push(key.to_string());
+        }
+    }
+    Ok(())
+}
+
+/// A path segment that can't escape the team directory
+fn valid_segment(segment: &str) -> bool {
+    !segment.is_empty() && segment != "." && segment != ".." && !segment.contains(['\\', '\0'])
+}
+
+/// A team name, which names a single directory under teams/
+fn valid_team(team: &str) -> bool {
+    valid_segment(team) && !team.contains('/')
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(byte) = value.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            decoded.push(byte);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A data dir holding one link per `(code, link)`
+    fn data_dir_with(links: &[(&str, ShareLink)]) -> PathBuf {
+        let data_dir = crate::transfer::temp_file("serve-test").unwrap();
+        Links {
+            links: links.iter().map(|(code, link)| (code.to_string(), link.clone())).collect(),
+        }
+        .save(&data_dir)
+        .unwrap();
+        data_dir
+    }
+
+    fn link(expires_at: Option<DateTime<Utc>>, max_downloads: Option<u32>) -> ShareLink {
+        ShareLink {
+            team: "platform".to_string(),
+            key: "shares/abc.mcc".to_string(),
+            expires_at,
+            max_downloads,
+            downloads: 0,
+        }
+    }
+
+    #[test]
+    fn tokens_authenticate_their_team() {
+        let data_dir = crate::transfer::temp_file("serve-test").unwrap();
+        let token = add_token(&data_dir, "platform").unwrap();
+
+        let tokens = Tokens::load(&data_dir).unwrap();
+        assert_eq!(tokens.team_for(&token), Some("platform"));
+        assert_eq!(tokens.team_for("not-a-token"), None);
+        // Only the hash is stored
+        assert!(!fs::read_to_string(Tokens::path(&data_dir)).unwrap().contains(&token));
+
+        assert!(revoke_token(&data_dir, &token).unwrap());
+        assert!(!revoke_token(&data_dir, &token).unwrap());
+        assert_eq!(Tokens::load(&data_dir).unwrap().team_for(&token), None);
+        fs::remove_dir_all(&data_dir).unwrap();
+    }
+
+    #[test]
+    fn team_names_stay_inside_teams() {
+        let data_dir = crate::transfer::temp_file("serve-test").unwrap();
+        for team in ["", ".", "..", "../../x", "a/b", "a\\b"] {
+            assert!(add_token(&data_dir, team).is_err(), "{:?} was accepted", team);
+        }
+        assert!(!data_dir.exists());
+    }
+
+    #[test]
+    fn validates_key_segments() {
+        for segment in ["abc.mcc", "shares", "a..b", ".hidden"] {
+            assert!(valid_segment(segment), "{:?} was refused", segment);
+        }
+        for segment in ["", ".", "..", "a\\b", "a\0b"] {
+            assert!(!valid_segment(segment), "{:?} was accepted", segment);
+        }
+    }
+
+    #[test]
+    fn decodes_percent_escapes() {
+        assert_eq!(percent_decode("shares%2Falice%2Fabc.mcc"), "shares/alice/abc.mcc");
+        assert_eq!(percent_decode("caf%C3%A9"), "café");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz%4"), "%zz%4");
+        assert_eq!(percent_decode("%2E%2E"), "..");
+    }
+
+    #[test]
+    fn expired_links_are_refused() {
+        let data_dir = data_dir_with(&[
+            ("expired", link(Some(Utc::now() - chrono::Duration::minutes(1)), None)),
+            ("current", link(Some(Utc::now() + chrono::Duration::hours(1)), None)),
+        ]);
+        assert!(reserve_download(&data_dir, "expired", false).unwrap().is_none());
+        assert!(reserve_download(&data_dir, "current", false).unwrap().is_some());
+        assert!(reserve_download(&data_dir, "unknown", false).unwrap().is_none());
+        fs::remove_dir_all(&data_dir).unwrap();
+    }
+
+    #[test]
+    fn once_only_links_work_once() {
+        let data_dir = data_dir_with(&[("once", link(None, Some(1)))]);
+
+        assert!(reserve_download(&data_dir, "once", true).unwrap().is_some());
+        // A racing request is refused while the first is being sent
+        assert!(reserve_download(&data_dir, "once", true).unwrap().is_none());
+        finish_download(&data_dir, "once", true).unwrap();
+
+        assert!(reserve_download(&data_dir, "once", false).unwrap().is_none());
+        assert!(Links::load(&data_dir).unwrap().links.is_empty());
+        fs::remove_dir_all(&data_dir).unwrap();
+    }
+
+    #[test]
+    fn dropped_downloads_dont_use_up_links() {
+        let data_dir = data_dir_with(&[("once", link(None, Some(1)))]);
+
+        assert!(reserve_download(&data_dir, "once", true).unwrap().is_some());
+        finish_download(&data_dir, "once", false).unwrap();
+
+        assert!(reserve_download(&data_dir, "once", true).unwrap().is_some());
+        finish_download(&data_dir, "once", true).unwrap();
+        assert!(reserve_download(&data_dir, "once", false).unwrap().is_none());
+        fs::remove_dir_all(&data_dir).unwrap();
+    }
+
+    #[test]
+    fn checking_a_link_doesnt_use_it_up() {
+        let data_dir = data_dir_with(&[("twice", link(None, Some(2)))]);
+        for _ in 0..3 {
+            assert!(reserve_download(&data_dir, "twice", false).unwrap().is_some());
+        }
+        assert_eq!(Links::load(&data_dir).unwrap().links["twice"].downloads, 0);
+        fs::remove_dir_all(&data_dir).unwrap();
+    }
+}
@@ -15,7 +15,6 @@ pub struct SessionMessage {
 pub struct Session {
     pub id: String,
     pub project_path: String,
-    #[allow(dead_code)]
     pub file_path: PathBuf,
     pub messages: Vec<SessionMessage>,
     pub last_modified: u64,
@@ -56,30 +55,29 @@ impl Session {
         for msg in &messages {
             // Extract cwd (project path) from user messages
             if msg.msg_type == "user" {
-                if let Some(cwd) = msg.data.get("cwd").and_then(|v| v.as_str()) {
-                    if actual_project_path.is_none() {
-                        actual_project_path = Some(cwd.to_string());
-                    }
+                if let Some(cwd) = msg.data.get("cwd").and_then(|v| v.as_str())
+                    && actual_project_path.is_none()
+                {
+                    actual_project_path = Some(cwd.to_string());
                 }
 
                 // Try to get first user message as summary
-                if summary == "No messages" {
-                    if let Some(message) = msg.data.get("message") {
-                        if let Some(content) = message.get("content").and_then(|v| v.as_str()) {
-                            summary = content.chars().take(60).collect();
-                            if content.len() > 60 {
-                                summary.push_str("...");
-                            }
-                        }
+                if summary == "No messages"
+                    && let Some(message) = msg.data.get("message")
+                    && let Some(content) = message.get("content").and_then(|v| v.as_str())
+                {
+                    summary = content.chars().take(60).collect();
+                    if content.len() > 60 {
+                        summary.push_str("...");
                     }
                 }
             }
 
             // Extract git branch
-            if git_branch.is_none() {
-                if let Some(branch) = msg.data.get("gitBranch").and_then(|v| v.as_str()) {
-                    git_branch = Some(branch.to_string());
-                }
+            if git_branch.is_none()
+                && let Some(branch) = msg.data.get("gitBranch").and_then(|v| v.as_str())
+            {
+                git_branch = Some(branch.to_string());
             }
         }
 
@@ -98,7 +96,6 @@ impl Session {
     }
 
     /// Get the number of messages in this session
-    #[allow(dead_code)]
     pub fn message_count(&self) -> usize {
         self.messages.len()
     }
@@ -156,7 +153,14 @@ pub fn find_all_sessions() -> Result<Vec<Session>> {
             let session_entry = session_entry?;
             let session_path = session_entry.path();
 
+            // agent-*.jsonl are subagent sidechains of another session
+            let is_sidechain = session_path
+                .file_name()
+                .and_then(|s| s.to_str())
+                .is_some_and(|name| name.starts_with("agent-"));
+
             if session_path.extension().and_then(|s| s.to_str()) == Some("jsonl")
+                && !is_sidechain
                 && let Ok(session) = Session::load(session_path, project_name.clone())
             {
                 sessions.push(session);
@@ -165,7 +169,7 @@ pub fn find_all_sessions() -> Result<Vec<Session>> {
     }
 
     // Sort by last modified (most recent first)
-    sessions.sort_by(|a, b| b.last_modified.cmp(&a.last_modified));
+    sessions.sort_by_key(|s| std::cmp::Reverse(s.last_modified));
 
     Ok(sessions)
 }
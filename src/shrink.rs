@@ -0,0 +1,351 @@
+use anyhow::Result;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+
+use crate::export::{Attachment, AttachmentKind};
+use crate::manifest::sha256_hex;
+use crate::progress::format_bytes;
+use crate::prune::parse_size;
+use crate::session::SessionMessage;
+
+/// Tool output longer than this is truncated unless `--max-output` says
+/// otherwise
+const DEFAULT_MAX_OUTPUT: usize = 16 * 1024;
+
+/// Output shorter than this isn't worth replacing with a reference
+const MIN_DEDUPE_SIZE: usize = 1024;
+
+/// How `mcc export --shrink` slims down tool output
+#[derive(Debug, Clone)]
+pub struct ShrinkOptions {
+    /// Longest tool output kept whole; longer output keeps its start and end
+    pub max_output: usize,
+}
+
+impl ShrinkOptions {
+    /// None unless `--shrink` or `--max-output` was given
+    pub fn from_args(args: &[String]) -> Result<Option<Self>> {
+        let max_output = args
+            .iter()
+            .position(|arg| arg == "--max-output")
+            .and_then(|i| args.get(i + 1));
+        if max_output.is_none() && !args.iter().any(|arg| arg == "--shrink") {
+            return Ok(None);
+        }
+
+        Ok(Some(Self {
+            max_output: match max_output {
+                Some(size) => parse_size(size)? as usize,
+                None => DEFAULT_MAX_OUTPUT,
+            },
+        }))
+    }
+}
+
+/// What shrinking did, for the export summary
+#[derive(Debug, Default)]
+pub struct ShrinkReport {
+    pub truncated: usize,
+    pub deduplicated: usize,
+    pub images: usize,
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+}
+
+/// Shrinks the tool output of one session. Duplicates are only replaced
+/// within the same transcript, where the earlier output is still in view.
+pub struct Shrinker<'a> {
+    options: &'a ShrinkOptions,
+    /// Hash of each tool output seen, and the tool call that produced it
+    seen: HashMap<String, String>,
+    pub report: ShrinkReport,
+}
+
+impl<'a> Shrinker<'a> {
+    pub fn new(options: &'a ShrinkOptions) -> Self {
+        Self {
+            options,
+            seen: HashMap::new(),
+            report: ShrinkReport::default(),
+        }
+    }
+
+    pub fn shrink_messages(&mut self, messages: &mut [SessionMessage]) {
+        for message in messages {
+            self.report.bytes_before += serde_json::to_string(&message).map_or(0, |json| json.len() as u64);
+            self.shrink_record(&mut message.data);
+            self.report.bytes_after += serde_json::to_string(&message).map_or(0, |json| json.len() as u64);
+        }
+    }
+
+    /// Subagent transcripts are JSONL like the session itself
+    pub fn shrink_attachments(&mut self, attachments: &mut [Attachment]) {
+        for attachment in attachments
+            .iter_mut()
+            .filter(|attachment| attachment.kind == AttachmentKind::SessionFile && attachment.path.ends_with(".jsonl"))
+        {
+            self.report.bytes_before += attachment.content.len() as u64;
+            let seen = std::mem::take(&mut self.seen);
+            let mut shrunk = String::with_capacity(attachment.content.len());
+            for line in attachment.content.lines() {
+                match serde_json::from_str::<Value>(line) {
+                    Ok(mut record) => {
+                        self.shrink_record(&mut record);
+                        shrunk.push_str(&record.to_string());
+                    }
+                    Err(_) => shrunk.push_str(line),
+                }
+                shrunk.push('\n');
+            }
+            attachment.content = shrunk;
+            self.seen = seen;
+            self.report.bytes_after += attachment.content.len() as u64;
+        }
+    }
+
+    /// Shrink one transcript record. Tool results keep their `tool_use_id`
+    /// and shape, so the transcript still resumes; only the text changes.
+    fn shrink_record(&mut self, record: &mut Value) {
+        let mut replaced_result = false;
+        if let Some(content) = record.pointer_mut("/message/content").and_then(|content| content.as_array_mut()) {
+            for block in content {
+                match block.get("type").and_then(|kind| kind.as_str()) {
+                    Some("image") => {
+                        *block = self.image_placeholder();
+                        replaced_result = true;
+                    }
+                    Some("tool_result") => {
+                        let id = block
+                            .get("tool_use_id")
+                            .and_then(|id| id.as_str())
+                            .unwrap_or("unknown")
+                            .to_string();
+                        replaced_result |= self.shrink_tool_result(block, &id);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        // Claude Code keeps a structured copy of each tool result for its UI
+        if let Some(result) = record.get_mut("toolUseResult") {
+            if replaced_result || contains_key(result, "base64") {
+                *result = json!("[Removed by mcc export --shrink]");
+            } else {
+                self.truncate_strings(result);
+            }
+        }
+    }
+
+    /// Returns whether the output was replaced outright, rather than kept
+    /// or truncated
+    fn shrink_tool_result(&mut self, block: &mut Value, id: &str) -> bool {
+        let Some(content) = block.get_mut("content") else {
+            return false;
+        };
+        match content {
+            Value::String(text) => self.shrink_text(text, id),
+            Value::Array(items) => {
+                let mut replaced = false;
+                for item in items {
+                    match item.get("type").and_then(|kind| kind.as_str()) {
+                        Some("image") => {
+                            *item = self.image_placeholder();
+                            replaced = true;
+                        }
+                        Some("text") => {
+                            if let Some(Value::String(text)) = item.get_mut("text") {
+                                replaced |= self.shrink_text(text, id);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                replaced
+            }
+            _ => false,
+        }
+    }
+
+    /// Replace output seen earlier in the session with a reference to it and
+    /// truncate anything too long. Returns whether it was a duplicate.
+    fn shrink_text(&mut self, text: &mut String, id: &str) -> bool {
+        if text.len() >= MIN_DEDUPE_SIZE {
+            let hash = sha256_hex(text.as_bytes());
+            if let Some(earlier) = self.seen.get(&hash) {
+                *text = format!(
+                    "[Identical to the earlier output of tool call {}; removed by mcc export --shrink]",
+                    earlier
+                );
+                self.report.deduplicated += 1;
+                return true;
+            }
+            self.seen.insert(hash, id.to_string());
+        }
+
+        if text.len() > self.options.max_output {
+            *text = truncate(text, self.options.max_output);
+            self.report.truncated += 1;
+        }
+        false
+    }
+
+    fn truncate_strings(&self, value: &mut Value) {
+        match value {
+            Value::String(text) if text.len() > self.options.max_output => {
+                *text = truncate(text, self.options.max_output);
+            }
+            Value::Array(items) => items.iter_mut().for_each(|item| self.truncate_strings(item)),
+            Value::Object(fields) => fields.values_mut().for_each(|field| self.truncate_strings(field)),
+            _ => {}
+        }
+    }
+
+    fn image_placeholder(&mut self) -> Value {
+        self.report.images += 1;
+        json!({ "type": "text", "text": "[Image removed by mcc export --shrink]" })
+    }
+}
+
+fn contains_key(value: &Value, key: &str) -> bool {
+    match value {
+        Value::Object(fields) => fields.contains_key(key) || fields.values().any(|field| contains_key(field, key)),
+        Value::Array(items) => items.iter().any(|item| contains_key(item, key)),
+        _ => false,
+    }
+}
+
+/// Keep roughly the first two thirds and last third of `max` bytes of
+/// `text`, cut at line breaks where possible, with a note of what's missing
+fn truncate(text: &str, max: usize) -> String {
+    let mut head_end = (max * 2 / 3).min(text.len());
+    while !text.is_char_boundary(head_end) {
+        head_end -= 1;
+    }
+    if let Some(newline) = text[..head_end].rfind('\n') {
+        head_end = newline + 1;
+    }
+
+    let mut tail_start = text.len().saturating_sub(max / 3).max(head_end);
+    while !text.is_char_boundary(tail_start) {
+        tail_start += 1;
+    }
+    if let Some(newline) = text[tail_start..].find('\n')
+        && tail_start + newline + 1 < text.len()
+    {
+        tail_start += newline + 1;
+    }
+
+    let (head, omitted, tail) = (&text[..head_end], &text[head_end..tail_start], &text[tail_start..]);
+    format!(
+        "{}{}[... {} lines ({}) omitted by mcc export --shrink ...]\n{}",
+        head,
+        if head.ends_with('\n') || head.is_empty() { "" } else { "\n" },
+        omitted.lines().count(),
+        format_bytes(omitted.len() as u64),
+        tail
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tool_result(id: &str, output: &str) -> SessionMessage {
+        serde_json::from_value(json!({
+            "type": "user",
+            "message": {
+                "role": "user",
+                "content": [{ "type": "tool_result", "tool_use_id": id, "content": output }]
+            },
+            "toolUseResult": { "stdout": output }
+        }))
+        .unwrap()
+    }
+
+    fn output_of(message: &SessionMessage) -> &str {
+        message.data["message"]["content"][0]["content"].as_str().unwrap()
+    }
+
+    #[test]
+    fn truncate_keeps_head_and_tail() {
+        let text: String = (0..100).map(|line| format!("line {}\n", line)).collect();
+        let truncated = truncate(&text, 300);
+        assert!(truncated.len() < text.len());
+        assert!(truncated.starts_with("line 0\n"));
+        assert!(truncated.ends_with("line 99\n"));
+        assert!(truncated.contains("omitted by mcc export --shrink"));
+    }
+
+    #[test]
+    fn truncate_cuts_on_char_boundaries() {
+        let text = "é".repeat(1000) + &"日本".repeat(1000);
+        for max in [1, 2, 3, 100, 101, 999, 1001] {
+            // Slicing mid-character would panic
+            let truncated = truncate(&text, max);
+            assert!(truncated.contains("omitted by mcc export --shrink"));
+        }
+    }
+
+    #[test]
+    fn truncate_to_zero_keeps_only_the_note() {
+        let truncated = truncate("one\ntwo\nthree\n", 0);
+        assert_eq!(truncated, "[... 3 lines (14 B) omitted by mcc export --shrink ...]\n");
+    }
+
+    #[test]
+    fn repeated_output_is_deduplicated() {
+        let options = ShrinkOptions { max_output: 1 << 20 };
+        let output = "x".repeat(MIN_DEDUPE_SIZE);
+        let mut messages = vec![tool_result("first", &output), tool_result("second", &output)];
+
+        let mut shrinker = Shrinker::new(&options);
+        shrinker.shrink_messages(&mut messages);
+
+        assert_eq!(output_of(&messages[0]), output);
+        assert!(output_of(&messages[1]).contains("tool call first"));
+        assert_eq!(messages[1].data["toolUseResult"], "[Removed by mcc export --shrink]");
+        assert_eq!(shrinker.report.deduplicated, 1);
+        assert!(shrinker.report.bytes_after < shrinker.report.bytes_before);
+    }
+
+    #[test]
+    fn short_output_is_left_alone() {
+        let options = ShrinkOptions { max_output: 16 };
+        let mut messages = vec![tool_result("first", "ok"), tool_result("second", "ok")];
+
+        let mut shrinker = Shrinker::new(&options);
+        shrinker.shrink_messages(&mut messages);
+
+        assert_eq!(output_of(&messages[1]), "ok");
+        assert_eq!(shrinker.report.deduplicated, 0);
+        assert_eq!(shrinker.report.truncated, 0);
+    }
+
+    #[test]
+    fn output_below_the_dedupe_size_is_still_truncated() {
+        let options = ShrinkOptions { max_output: 64 };
+        let output = "line\n".repeat(100);
+        let mut messages = vec![tool_result("first", &output)];
+
+        let mut shrinker = Shrinker::new(&options);
+        shrinker.shrink_messages(&mut messages);
+
+        assert!(output_of(&messages[0]).len() < output.len());
+        assert_eq!(shrinker.report.truncated, 1);
+    }
+
+    #[test]
+    fn long_output_is_truncated() {
+        let options = ShrinkOptions { max_output: MIN_DEDUPE_SIZE };
+        let output = "line\n".repeat(MIN_DEDUPE_SIZE);
+        let mut messages = vec![tool_result("first", &output)];
+
+        let mut shrinker = Shrinker::new(&options);
+        shrinker.shrink_messages(&mut messages);
+
+        assert!(output_of(&messages[0]).len() < output.len());
+        assert!(messages[0].data["toolUseResult"]["stdout"].as_str().unwrap().len() < output.len());
+        assert_eq!(shrinker.report.truncated, 1);
+    }
+}
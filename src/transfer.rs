@@ -0,0 +1,163 @@
+use anyhow::Result;
+use chacha20poly1305::aead::OsRng;
+use chacha20poly1305::aead::rand_core::RngCore;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+use crate::backend::Backend;
+use crate::codec::Codec;
+use crate::progress::Progress;
+
+/// Default number of concurrent transfers
+pub const DEFAULT_WORKERS: usize = 4;
+
+/// How many times a failed transfer is retried before giving up
+const MAX_RETRIES: u32 = 3;
+
+/// Delay before the first retry, doubled on each subsequent attempt
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Settings shared by every transfer in a sync or restore
+#[derive(Clone)]
+pub struct TransferOptions {
+    pub workers: usize,
+    pub codec: Codec,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Direction {
+    Upload,
+    Download,
+}
+
+/// A single file to move between the local disk and the remote
+#[derive(Debug, Clone)]
+pub struct TransferJob {
+    pub key: String,
+    pub local_path: PathBuf,
+    pub size: u64,
+}
+
+/// Run transfers with at most `workers` in flight, retrying transient
+/// failures with exponential backoff and reporting progress as files finish
+pub async fn run_transfers(
+    backend: &Backend,
+    direction: Direction,
+    jobs: Vec<TransferJob>,
+    options: &TransferOptions,
+) -> Vec<(TransferJob, Result<()>)> {
+    if jobs.is_empty() {
+        return Vec::new();
+    }
+
+    let verb = match direction {
+        Direction::Upload => "uploaded",
+        Direction::Download => "downloaded",
+    };
+    let total_bytes = jobs.iter().map(|job| job.size).sum();
+    let progress = Arc::new(Progress::new(verb, jobs.len(), total_bytes));
+    let semaphore = Arc::new(Semaphore::new(options.workers.max(1)));
+    let mut tasks = JoinSet::new();
+
+    for job in jobs {
+        let permit = semaphore
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("transfer semaphore is never closed");
+        let backend = backend.clone();
+        let codec = options.codec.clone();
+        let progress = progress.clone();
+
+        // Backends shell out to gsutil/ssh, so each transfer runs on the
+        // blocking pool rather than an async worker
+        tasks.spawn_blocking(move || {
+            let _permit = permit;
+            let result = match direction {
+                Direction::Upload => upload_encoded(&backend, &codec, &job.local_path, &job.key),
+                Direction::Download => download_decoded(&backend, &codec, &job.key, &job.local_path),
+            };
+            progress.file_done(&job.key, job.size, result.is_ok());
+            (job, result)
+        });
+    }
+
+    let mut results = Vec::new();
+    while let Some(joined) = tasks.join_next().await {
+        match joined {
+            Ok(result) => results.push(result),
+            Err(e) => eprintln!("Warning: Transfer task panicked: {}", e),
+        }
+    }
+
+    progress.finish();
+    results
+}
+
+/// Encode a local file and upload it to `key`
+pub fn upload_encoded(backend: &Backend, codec: &Codec, local_path: &Path, key: &str) -> Result<()> {
+    let encoded_path = temp_file("upload")?;
+    codec.encode_file(local_path, &encoded_path)?;
+    let result = with_retries(|| backend.upload(&encoded_path, key));
+    fs::remove_file(&encoded_path).ok();
+    result.map(|_| ())
+}
+
+/// Download `key` and decode it into a local file
+pub fn download_decoded(backend: &Backend, codec: &Codec, key: &str, local_path: &Path) -> Result<()> {
+    let encoded_path = temp_file("download")?;
+    let result = with_retries(|| backend.download(key, &encoded_path))
+        .and_then(|_| codec.decode_file(&encoded_path, local_path));
+    fs::remove_file(&encoded_path).ok();
+    result
+}
+
+/// Scratch directory for in-flight transfers, ~/.mcc/temp
+pub fn temp_dir() -> Result<PathBuf> {
+    let home = std::env::var("HOME")?;
+    let dir = PathBuf::from(home).join(".mcc/temp");
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// A path in ~/.mcc/temp no other process will use, since a watch service
+/// and a manual sync may be transferring at the same time. `name` ends the
+/// file name so extensions are kept.
+pub fn temp_file(name: &str) -> Result<PathBuf> {
+    Ok(temp_dir()?.join(format!("{}-{:016x}-{}", std::process::id(), OsRng.next_u64(), name)))
+}
+
+/// Retry an operation while it fails with what looks like a transient error
+pub fn with_retries<T>(mut operation: impl FnMut() -> Result<T>) -> Result<T> {
+    let mut attempt = 0;
+    loop {
+        match operation() {
+            Ok(value) => return Ok(value),
+            Err(e) if attempt < MAX_RETRIES && is_transient(&e) => {
+                std::thread::sleep(INITIAL_BACKOFF * 2u32.pow(attempt));
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Errors that retrying won't fix: auth problems and missing files
+fn is_transient(error: &anyhow::Error) -> bool {
+    const PERMANENT: [&str; 7] = [
+        "Permission denied",
+        "Unauthorized",
+        "AccessDenied",
+        "No such file",
+        "No URLs matched",
+        "not enabled",
+        "Host key verification failed",
+    ];
+
+    let message = format!("{:#}", error);
+    !PERMANENT.iter().any(|pattern| message.contains(pattern))
+}
@@ -0,0 +1,247 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use notify::{EventKind, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use crate::backend::Backend;
+use crate::cloud::sync_sessions;
+use crate::transfer::TransferOptions;
+
+/// Default quiet period after the last write before syncing
+pub const DEFAULT_DEBOUNCE: Duration = Duration::from_secs(5);
+
+/// Claude Code appends to a transcript throughout a session, so a busy
+/// session is still synced this often even if it never goes quiet
+const MAX_DELAY_FACTOR: u32 = 12;
+
+const SERVICE_NAME: &str = "mcc-watch.service";
+
+/// What the watcher last did, written to ~/.mcc/watch-status.json for
+/// `mcc watch status`
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct WatchStatus {
+    pub pid: u32,
+    pub remote: String,
+    pub started_at: Option<DateTime<Utc>>,
+    pub last_sync: Option<DateTime<Utc>>,
+    pub last_uploaded: usize,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<DateTime<Utc>>,
+}
+
+impl WatchStatus {
+    pub fn path() -> Result<PathBuf> {
+        let home = std::env::var("HOME")?;
+        Ok(PathBuf::from(home).join(".mcc/watch-status.json"))
+    }
+
+    /// Load the last recorded status, or None if the watcher has never run
+    pub fn load() -> Result<Option<Self>> {
+        let path = Self::path()?;
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let content = fs::read_to_string(&path)?;
+        Ok(Some(serde_json::from_str(&content).context("Failed to parse watch status")?))
+    }
+
+    fn save(&self) -> Result<()> {
+        let path = Self::path()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Whether the process that wrote this status is still alive
+    pub fn is_running(&self) -> bool {
+        Command::new("kill")
+            .args(["-0", &self.pid.to_string()])
+            .output()
+            .is_ok_and(|output| output.status.success())
+    }
+}
+
+/// Watch ~/.claude/projects and sync sessions once writes have settled.
+/// Runs until the process is stopped.
+pub fn run(backend: &Backend, remote: &str, options: &TransferOptions, debounce: Duration) -> Result<()> {
+    let home = std::env::var("HOME")?;
+    let projects_dir = PathBuf::from(home).join(".claude/projects");
+    fs::create_dir_all(&projects_dir)?;
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx).context("Failed to start file watcher")?;
+    watcher
+        .watch(&projects_dir, RecursiveMode::Recursive)
+        .context(format!("Failed to watch {}", projects_dir.display()))?;
+
+    let mut status = WatchStatus {
+        pid: std::process::id(),
+        remote: remote.to_string(),
+        started_at: Some(Utc::now()),
+        ..WatchStatus::default()
+    };
+    status.save()?;
+
+    let runtime = tokio::runtime::Runtime::new()?;
+    let max_delay = debounce * MAX_DELAY_FACTOR;
+
+    println!("Watching {} (syncing to {})", projects_dir.display(), remote);
+
+    // Catch up on anything written while the watcher wasn't running
+    sync_once(&runtime, backend, options, &mut status);
+
+    // (first change, last change) since the last sync
+    let mut pending: Option<(Instant, Instant)> = None;
+
+    loop {
+        let event = match pending {
+            Some((first, last)) => {
+                let deadline = (last + debounce).min(first + max_delay);
+                rx.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            }
+            None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+
+        match event {
+            Ok(Ok(event)) if is_session_write(&event) => {
+                let now = Instant::now();
+                pending = Some(pending.map_or((now, now), |(first, _)| (first, now)));
+            }
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => eprintln!("Warning: File watcher error: {}", e),
+            Err(RecvTimeoutError::Timeout) => {
+                pending = None;
+                sync_once(&runtime, backend, options, &mut status);
+            }
+            Err(RecvTimeoutError::Disconnected) => anyhow::bail!("File watcher stopped unexpectedly"),
+        }
+    }
+}
+
+fn sync_once(runtime: &tokio::runtime::Runtime, backend: &Backend, options: &TransferOptions, status: &mut WatchStatus) {
+    let now = Utc::now();
+    match runtime.block_on(sync_sessions(backend, options)) {
+        Ok(summary) => {
+            status.last_sync = Some(now);
+            status.last_uploaded = summary.transferred.len();
+
+            if let Some((key, error)) = summary.failed.first() {
+                eprintln!("Warning: {} sessions failed to upload", summary.failed.len());
+                status.last_error = Some(format!("{}: {}", key, error));
+                status.last_error_at = Some(now);
+            } else if !summary.transferred.is_empty() {
+                println!(
+                    "✓ {} synced {} sessions",
+                    now.with_timezone(&chrono::Local).format("%H:%M:%S"),
+                    summary.transferred.len()
+                );
+            }
+        }
+        Err(e) => {
+            eprintln!("✗ Sync failed: {}", e);
+            status.last_error = Some(format!("{:#}", e));
+            status.last_error_at = Some(now);
+        }
+    }
+
+    if let Err(e) = status.save() {
+        eprintln!("Warning: Failed to save watch status: {}", e);
+    }
+}
+
+/// Created or modified session transcripts; everything else (directories,
+/// reads, editor swap files) is ignored
+fn is_session_write(event: &notify::Event) -> bool {
+    matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+        && event
+            .paths
+            .iter()
+            .any(|path| path.extension().and_then(|ext| ext.to_str()) == Some("jsonl"))
+}
+
+/// Location of the systemd user unit, ~/.config/systemd/user/mcc-watch.service
+fn service_path() -> Result<PathBuf> {
+    let home = std::env::var("HOME")?;
+    Ok(PathBuf::from(home).join(".config/systemd/user").join(SERVICE_NAME))
+}
+
+/// Install and start a systemd user service running `mcc watch`
+pub fn install_service(extra_args: &[String]) -> Result<PathBuf> {
+    let exe = std::env::current_exe().context("Failed to find the mcc executable")?;
+    let service_path = service_path()?;
+    if let Some(parent) = service_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut exec = format!("{} watch", exe.display());
+    for arg in extra_args {
+        exec.push(' ');
+        exec.push_str(arg);
+    }
+
+    // systemd starts services with a minimal PATH, but gsutil and ssh are
+    // found the same way they are from the user's shell
+    let path = std::env::var("PATH").unwrap_or_default();
+    let unit = format!(
+        "[Unit]\n\
+         Description=mcc: sync Claude Code sessions as they change\n\
+         After=network-online.target\n\
+         \n\
+         [Service]\n\
+         Environment=PATH={}\n\
+         ExecStart={}\n\
+         Restart=on-failure\n\
+         RestartSec=30\n\
+         \n\
+         [Install]\n\
+         WantedBy=default.target\n",
+        path, exec
+    );
+    fs::write(&service_path, unit)?;
+
+    systemctl(&["daemon-reload"])?;
+    systemctl(&["enable", "--now", SERVICE_NAME])?;
+    Ok(service_path)
+}
+
+/// Stop and remove the systemd user service
+pub fn uninstall_service() -> Result<()> {
+    let service_path = service_path()?;
+    if !service_path.exists() {
+        anyhow::bail!("{} is not installed", SERVICE_NAME);
+    }
+
+    systemctl(&["disable", "--now", SERVICE_NAME])?;
+    fs::remove_file(&service_path)?;
+    systemctl(&["daemon-reload"])
+}
+
+fn systemctl(args: &[&str]) -> Result<()> {
+    let output = Command::new("systemctl")
+        .arg("--user")
+        .args(args)
+        .output()
+        .context("Failed to run systemctl. Is systemd available?")?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "systemctl --user {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// Whether the systemd user service is installed
+pub fn service_installed() -> bool {
+    service_path().is_ok_and(|path| path.exists())
+}